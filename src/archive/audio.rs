@@ -0,0 +1,404 @@
+//! Voice and sound files: `.fuz` containers and RIFF audio headers
+//!
+//! Skyrim and Fallout 4 store dialogue as `.fuz` files: a small header, the
+//! `.lip` lip sync data and an xWMA (`.xwm`) stream, back to back.
+//! `split_fuz`/`join_fuz` convert between the two forms and `inspect_audio`
+//! reads the format, sample rate, channels and duration of `.fuz`, `.xwm`
+//! and `.wav` files from their RIFF headers, without decoding any samples.
+
+use anyhow::{bail, Context, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
+
+use super::filter::normalize_path;
+use super::{ArchiveFileEntry, ArchiveHandle};
+
+const FUZ_MAGIC: &[u8; 4] = b"FUZE";
+const FUZ_VERSION: u32 = 1;
+/// Magic, version and lip size
+const FUZ_HEADER_LEN: usize = 12;
+/// `RIFF`, size and form type
+const RIFF_HEADER_LEN: usize = 12;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Bytes read from the start of a file for its RIFF headers
+const AUDIO_HEADER_READ_LEN: usize = 16 * 1024;
+
+/// Folder of dialogue, the only `.fuz` files the games load
+const VOICE_DIR: &str = "sound/voice/";
+
+/// Extensions of the files `inspect_audio` understands
+const AUDIO_EXTENSIONS: &[&str] = &["fuz", "xwm", "wav"];
+
+/// Format of a `.fuz`, `.xwm` or `.wav` file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AudioInfo {
+    /// Codec from the `WAVEFORMATEX` format tag: `pcm`, `adpcm`, `float`,
+    /// `ima-adpcm`, `wmav2`, `wmapro`, `xma2` or `unknown`
+    pub codec: &'static str,
+    /// `WAVEFORMATEX` format tag
+    pub format_tag: u16,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    /// Playing time in milliseconds
+    pub duration_ms: u64,
+    /// Size of the lip sync data, only for `.fuz` files
+    pub lip_size: Option<u32>,
+}
+
+impl AudioInfo {
+    /// Human-readable codec name
+    pub fn codec_name(&self) -> &'static str {
+        match self.codec {
+            "pcm" => "PCM",
+            "adpcm" => "MS ADPCM",
+            "float" => "IEEE float",
+            "ima-adpcm" => "IMA ADPCM",
+            "wmav2" => "xWMA",
+            "wmapro" => "xWMA (WMA Pro)",
+            "xma2" => "XMA2",
+            _ => "Unknown codec",
+        }
+    }
+
+    /// `mono`, `stereo` or `<n> channels`
+    pub fn channel_layout(&self) -> String {
+        match self.channels {
+            1 => "mono".to_string(),
+            2 => "stereo".to_string(),
+            n => format!("{} channels", n),
+        }
+    }
+}
+
+fn codec(format_tag: u16) -> &'static str {
+    match format_tag {
+        WAVE_FORMAT_PCM => "pcm",
+        0x0002 => "adpcm",
+        WAVE_FORMAT_IEEE_FLOAT => "float",
+        0x0011 => "ima-adpcm",
+        0x0161 => "wmav2",
+        0x0162 => "wmapro",
+        0x0166 => "xma2",
+        _ => "unknown",
+    }
+}
+
+/// Check if a path has an extension `inspect_audio` understands
+pub fn is_audio_path(path: &str) -> bool {
+    path.rsplit_once('.')
+        .is_some_and(|(_, ext)| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// The two parts of a `.fuz` file
+#[derive(Debug, Clone, Copy)]
+pub struct Fuz<'a> {
+    /// `.lip` data, empty for lines without lip sync
+    pub lip: &'a [u8],
+    /// `.xwm` data
+    pub audio: &'a [u8],
+}
+
+/// Split a `.fuz` file into its lip sync data and xWMA audio
+pub fn split_fuz(data: &[u8]) -> Result<Fuz<'_>> {
+    if data.len() < FUZ_HEADER_LEN || !data.starts_with(FUZ_MAGIC) {
+        bail!("Not a FUZ file (no FUZE header)");
+    }
+    let lip_size = read_u32(data, 8) as usize;
+    let lip_end = FUZ_HEADER_LEN + lip_size;
+    if lip_end > data.len() {
+        bail!(
+            "Lip data ({} bytes) runs past the end of the file ({} bytes)",
+            lip_size,
+            data.len()
+        );
+    }
+
+    // Some tools pad the lip data to a 4-byte boundary
+    let audio_start = (lip_end..(lip_end + 4).min(data.len()))
+        .find(|&start| data[start..].starts_with(b"RIFF"))
+        .unwrap_or(lip_end);
+    Ok(Fuz {
+        lip: &data[FUZ_HEADER_LEN..lip_end],
+        audio: &data[audio_start..],
+    })
+}
+
+/// Build a `.fuz` file from xWMA audio and optional lip sync data
+pub fn join_fuz(audio: &[u8], lip: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(FUZ_HEADER_LEN + lip.len() + audio.len());
+    data.extend_from_slice(FUZ_MAGIC);
+    data.extend_from_slice(&FUZ_VERSION.to_le_bytes());
+    data.extend_from_slice(&(lip.len() as u32).to_le_bytes());
+    data.extend_from_slice(lip);
+    data.extend_from_slice(audio);
+    data
+}
+
+/// Sources of a `.fuz` file to be joined on packing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzSources {
+    /// Path of the `.fuz` file in the archive
+    pub fuz: String,
+    /// The `.xwm` file
+    pub xwm: String,
+    /// The `.lip` file next to it, if there is one
+    pub lip: Option<String>,
+}
+
+/// Pair every `.xwm` file under `sound\voice` with the `.lip` file of the
+/// same name (ignoring case and slashes) as the sources of a `.fuz` file.
+/// Returns the remaining paths, in their original order, and the pairs in
+/// the order of their `.xwm` files. A `.lip` file without an `.xwm` file
+/// stays on its own, and so does music and every other `.xwm` outside
+/// `sound\voice`, which the games load as is.
+pub fn pair_fuz_sources(paths: Vec<String>) -> (Vec<String>, Vec<FuzSources>) {
+    let stem = |path: &str, ext: &str| -> Option<String> {
+        if !normalize_path(path).starts_with(VOICE_DIR) {
+            return None;
+        }
+        let (stem, found) = path.rsplit_once('.')?;
+        found
+            .eq_ignore_ascii_case(ext)
+            .then(|| normalize_path(stem))
+    };
+    let lips: HashMap<String, &String> = paths
+        .iter()
+        .filter_map(|path| Some((stem(path, "lip")?, path)))
+        .collect();
+
+    let mut pairs = Vec::new();
+    for path in &paths {
+        let Some(key) = stem(path, "xwm") else {
+            continue;
+        };
+        let base = &path[..path.len() - ".xwm".len()];
+        pairs.push(FuzSources {
+            fuz: format!("{}.fuz", base),
+            xwm: path.clone(),
+            lip: lips.get(&key).map(|lip| lip.to_string()),
+        });
+    }
+
+    let paired: HashSet<&String> = pairs
+        .iter()
+        .flat_map(|pair| std::iter::once(&pair.xwm).chain(&pair.lip))
+        .collect();
+    let rest = paths
+        .iter()
+        .filter(|path| !paired.contains(*path))
+        .cloned()
+        .collect();
+    (rest, pairs)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Read the format and duration of a `.fuz`, `.xwm` or `.wav` file.
+/// `data` may be just the start of the file, as long as it reaches the
+/// `data` chunk; its length comes from the chunk header.
+pub fn inspect_audio(data: &[u8]) -> Result<AudioInfo> {
+    if data.starts_with(FUZ_MAGIC) {
+        let fuz = split_fuz(data)?;
+        let mut info = inspect_riff(fuz.audio).context("Invalid audio in FUZ file")?;
+        info.lip_size = Some(fuz.lip.len() as u32);
+        return Ok(info);
+    }
+    inspect_riff(data)
+}
+
+/// Parse a RIFF `WAVE` or `XWMA` file
+fn inspect_riff(data: &[u8]) -> Result<AudioInfo> {
+    if data.len() < RIFF_HEADER_LEN || !data.starts_with(b"RIFF") {
+        bail!("Not a RIFF audio file");
+    }
+    let form = &data[8..12];
+    if form != b"WAVE" && form != b"XWMA" {
+        bail!(
+            "Unsupported RIFF form type: {}",
+            String::from_utf8_lossy(form)
+        );
+    }
+
+    let mut format = None;
+    let mut data_len = None;
+    let mut fact_samples = None;
+    let mut decoded_len = None;
+    let mut offset = RIFF_HEADER_LEN;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let size = read_u32(data, offset + 4) as usize;
+        let body = &data[offset + 8..(offset + 8).saturating_add(size).min(data.len())];
+        // Only the start of the file may have been read
+        let complete = body.len() == size;
+        match id {
+            b"fmt " if complete => format = Some(body),
+            b"data" => data_len = Some(size as u64),
+            b"fact" if body.len() >= 4 => fact_samples = Some(read_u32(body, 0) as u64),
+            // Cumulative decoded size after each xWMA packet
+            b"dpds" if complete && body.len() >= 4 => {
+                decoded_len = Some(read_u32(body, body.len() / 4 * 4 - 4) as u64)
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        offset = (offset + 8).saturating_add(size).saturating_add(size & 1);
+    }
+
+    let Some(format) = format else {
+        bail!("No fmt chunk");
+    };
+    if format.len() < 16 {
+        bail!("fmt chunk is too short ({} bytes)", format.len());
+    }
+    let mut format_tag = read_u16(format, 0);
+    let channels = read_u16(format, 2);
+    let sample_rate = read_u32(format, 4);
+    let bytes_per_sec = read_u32(format, 8);
+    let block_align = read_u16(format, 12);
+    let bits_per_sample = read_u16(format, 14);
+    // WAVEFORMATEXTENSIBLE: the real format tag starts the sub-format GUID
+    if format_tag == WAVE_FORMAT_EXTENSIBLE && format.len() >= 26 {
+        format_tag = read_u16(format, 24);
+    }
+    if channels == 0 || sample_rate == 0 {
+        bail!(
+            "Invalid format: {} channels at {} Hz",
+            channels,
+            sample_rate
+        );
+    }
+    let Some(data_len) = data_len else {
+        bail!("No data chunk");
+    };
+
+    let duration_ms = if let Some(decoded_len) = decoded_len {
+        // xWMA decodes to 16-bit PCM unless the header says otherwise
+        let frame_len = channels as u64 * (bits_per_sample.max(16) as u64 / 8);
+        decoded_len / frame_len * 1000 / sample_rate as u64
+    } else if let Some(samples) =
+        fact_samples.filter(|_| !matches!(format_tag, WAVE_FORMAT_PCM | WAVE_FORMAT_IEEE_FLOAT))
+    {
+        samples * 1000 / sample_rate as u64
+    } else if matches!(format_tag, WAVE_FORMAT_PCM | WAVE_FORMAT_IEEE_FLOAT) && block_align > 0 {
+        data_len / block_align as u64 * 1000 / sample_rate as u64
+    } else if bytes_per_sec > 0 {
+        data_len * 1000 / bytes_per_sec as u64
+    } else {
+        0
+    };
+
+    Ok(AudioInfo {
+        codec: codec(format_tag),
+        format_tag,
+        sample_rate,
+        channels,
+        bits_per_sample,
+        duration_ms,
+        lip_size: None,
+    })
+}
+
+/// Read the headers of one audio file from an archive, decompressing only
+/// the start of it
+fn read_audio_header(archive: &ArchiveHandle, path: &str) -> Result<AudioInfo> {
+    let mut data = archive.read_prefix(path, AUDIO_HEADER_READ_LEN)?;
+    // The audio of a .fuz starts after its lip sync data
+    if data.len() == AUDIO_HEADER_READ_LEN && data.starts_with(FUZ_MAGIC) {
+        let needed = FUZ_HEADER_LEN + read_u32(&data, 8) as usize + AUDIO_HEADER_READ_LEN;
+        if needed > data.len() {
+            data = archive.read_prefix(path, needed)?;
+        }
+    }
+    inspect_audio(&data)
+}
+
+/// Fill in [`ArchiveFileEntry::audio`] for the `.fuz`, `.xwm` and `.wav`
+/// entries of an archive. Only the start of those files is read; ones that
+/// can't be read or parsed are logged and keep `audio` empty.
+pub fn read_audio_info(archive: &ArchiveHandle, entries: &mut [ArchiveFileEntry]) -> Result<()> {
+    let found: HashMap<String, AudioInfo> = entries
+        .par_iter()
+        .filter(|entry| is_audio_path(&entry.path))
+        .filter_map(|entry| match read_audio_header(archive, &entry.path) {
+            Ok(info) => Some((entry.path.clone(), info)),
+            Err(e) => {
+                warn!("Can't read audio header of {}: {:#}", entry.path, e);
+                None
+            }
+        })
+        .collect();
+
+    for entry in entries {
+        if let Some(info) = found.get(&entry.path) {
+            entry.audio = Some(*info);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn pairs_voice_files_with_their_lip() {
+        let (rest, pairs) = pair_fuz_sources(paths(&[
+            "sound/voice/mymod.esp/npc/Line.XWM",
+            "Sound\\Voice\\MyMod.esp\\NPC\\line.lip",
+            "meshes/a.nif",
+        ]));
+        assert_eq!(rest, ["meshes/a.nif"]);
+        assert_eq!(
+            pairs,
+            [FuzSources {
+                fuz: "sound/voice/mymod.esp/npc/Line.fuz".to_string(),
+                xwm: "sound/voice/mymod.esp/npc/Line.XWM".to_string(),
+                lip: Some("Sound\\Voice\\MyMod.esp\\NPC\\line.lip".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn voice_files_without_lip_become_fuz() {
+        let (rest, pairs) = pair_fuz_sources(paths(&["sound/voice/mymod.esp/npc/grunt.xwm"]));
+        assert!(rest.is_empty());
+        assert_eq!(
+            pairs,
+            [FuzSources {
+                fuz: "sound/voice/mymod.esp/npc/grunt.fuz".to_string(),
+                xwm: "sound/voice/mymod.esp/npc/grunt.xwm".to_string(),
+                lip: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn music_and_effects_are_left_alone() {
+        let input = paths(&[
+            "music/theme.xwm",
+            "sound/fx/door.xwm",
+            "sound/fx/door.lip",
+            "sound/voice/mymod.esp/npc/orphan.lip",
+        ]);
+        let (rest, pairs) = pair_fuz_sources(input.clone());
+        assert_eq!(rest, input);
+        assert!(pairs.is_empty());
+    }
+}
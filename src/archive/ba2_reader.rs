@@ -3,74 +3,412 @@
 //! Provides read support for FO4 format BA2 files (Fallout 4, Fallout 76, Starfield).
 
 use anyhow::{bail, Context, Result};
-use ba2::fo4::{Archive, File as Ba2File, FileWriteOptions};
+use ba2::fo4::{
+    hash_file, Archive, ArchiveOptions, CompressionFormat, DX10Header, File as Ba2File, FileHash,
+    FileHeader, FileWriteOptions,
+};
 use ba2::prelude::*;
-use ba2::ByteSlice;
+use ba2::{Borrowed, ByteSlice};
+use directxtex::{TexMetadata, DDS_FLAGS, TEX_DIMENSION, TEX_MISC_FLAG};
+use flate2::read::ZlibDecoder;
+use memmap2::Mmap;
 use rayon::prelude::*;
-use std::collections::HashSet;
-use std::io::Cursor;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
 use tracing::debug;
 
+use super::filter::normalize_path;
+use super::{
+    data_offset, map_archive, read_up_to, static_mapping, ArchiveFileEntry, ArchiveFormat,
+    Ba2CompressionFormat, BatchCallback, FormatOptions, OpenArchive, TextureInfo,
+};
+
 /// Entry for a file in a BA2 archive
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Ba2FileEntry {
     pub path: String,
+    /// Decompressed size of all chunks (excluding the DDS header for textures)
+    pub size: u64,
+    /// Size of all chunks as stored in the archive
+    pub packed_size: u64,
+    pub compressed: bool,
+    /// Name hash, formatted as described on `ArchiveFileEntry::hash`
+    pub hash: String,
+    /// Offset of the first chunk from the start of the archive
+    pub offset: u64,
+    /// Texture header for DX10 archives
+    pub texture: Option<TextureInfo>,
 }
 
-/// List all files in a BA2 archive
-pub fn list_files(ba2_path: &Path) -> Result<Vec<Ba2FileEntry>> {
-    let (archive, _options): (Archive, _) = Archive::read(ba2_path)
-        .with_context(|| format!("Failed to open BA2: {}", ba2_path.display()))?;
+/// A BA2 archive parsed once and kept open for repeated reads
+pub struct Ba2Archive {
+    path: PathBuf,
+    /// Borrows from `mapping`, so it is declared first to be dropped first
+    archive: Archive<'static>,
+    options: ArchiveOptions,
+    mapping: Mmap,
+    entries: OnceLock<Vec<ArchiveFileEntry>>,
+    /// Normalized path -> name hash, for archives whose stored hashes
+    /// don't match what we compute
+    index: OnceLock<HashMap<String, FileHash>>,
+}
+
+impl Ba2Archive {
+    /// Open and parse a BA2 archive
+    pub fn open(ba2_path: &Path) -> Result<Self> {
+        let mapping = map_archive(ba2_path)?;
+        // SAFETY: `archive` is dropped before `mapping` and no file borrowed
+        // from it leaves this struct
+        let bytes = unsafe { static_mapping(&mapping) };
+        let (archive, options): (Archive, _) = Archive::read(Borrowed(bytes))
+            .with_context(|| format!("Failed to open BA2: {}", ba2_path.display()))?;
+        Ok(Self {
+            path: ba2_path.to_path_buf(),
+            archive,
+            options,
+            mapping,
+            entries: OnceLock::new(),
+            index: OnceLock::new(),
+        })
+    }
 
-    let mut files = Vec::new();
+    /// Find a file case-insensitively (either slash convention).
+    /// Looks the path up by its name hash, falling back to a path index
+    /// built on first use.
+    fn find(&self, file_path: &str) -> Option<&Ba2File<'static>> {
+        let hash = hash_file(file_path.as_bytes().as_bstr()).0;
+        let hashed = self.archive.get_key_value(&hash).and_then(|(key, file)| {
+            // Names are missing when the archive has no strings; trust the hash then
+            let matches = key.name().is_empty()
+                || normalize_path(&String::from_utf8_lossy(key.name().as_bytes()))
+                    == normalize_path(file_path);
+            matches.then_some(file)
+        });
+        if hashed.is_some() {
+            return hashed;
+        }
 
-    for (key, _file) in archive.iter() {
-        let path = String::from_utf8_lossy(key.name().as_bytes()).to_string();
+        let hash = self.index().get(&normalize_path(file_path))?;
+        self.archive.get(hash)
+    }
 
-        files.push(Ba2FileEntry { path });
+    /// Normalized path -> name hash, built on first use
+    fn index(&self) -> &HashMap<String, FileHash> {
+        self.index.get_or_init(|| {
+            self.archive
+                .iter()
+                .map(|(key, _)| {
+                    let path = String::from_utf8_lossy(key.name().as_bytes());
+                    (normalize_path(&path), *key.hash())
+                })
+                .collect()
+        })
     }
 
-    debug!("Listed {} files in BA2 {}", files.len(), ba2_path.display());
-    Ok(files)
+    /// Compression format used by compressed chunks in this archive
+    pub fn compression(&self) -> Ba2CompressionFormat {
+        match self.options.compression_format() {
+            CompressionFormat::LZ4 => Ba2CompressionFormat::Lz4,
+            _ => Ba2CompressionFormat::Zlib,
+        }
+    }
+
+    /// Every file as stored (possibly compressed), with its path.
+    /// Pass these to [`Ba2Builder::add_stored_file`](super::Ba2Builder::add_stored_file)
+    /// to repack without recompressing.
+    ///
+    /// The files outlive this archive, so they come from a second read with
+    /// its own mapping.
+    pub fn stored_files(&self) -> Result<Vec<(String, Ba2File<'static>)>> {
+        let (archive, _): (Archive, _) = Archive::read(self.path.as_path())
+            .with_context(|| format!("Failed to open BA2: {}", self.path.display()))?;
+        Ok(archive
+            .iter()
+            .map(|(key, file)| {
+                let path = String::from_utf8_lossy(key.name().as_bytes()).to_string();
+                (path, file.clone())
+            })
+            .collect())
+    }
+
+    /// Every file with its sizes, hash, offset and texture header, from the
+    /// parsed archive
+    fn list(&self) -> Vec<Ba2FileEntry> {
+        let mut files = Vec::new();
+
+        for (key, file) in self.archive.iter() {
+            let path = String::from_utf8_lossy(key.name().as_bytes()).to_string();
+            let hash = key.hash();
+            let extension = hash.extension.to_le_bytes();
+            let extension = String::from_utf8_lossy(&extension);
+
+            let texture = match &file.header {
+                FileHeader::DX10(dx10) => Some(TextureInfo {
+                    width: dx10.width.into(),
+                    height: dx10.height.into(),
+                    mip_count: dx10.mip_count.into(),
+                    dxgi_format: dx10.format.into(),
+                    chunks: file.len() as u32,
+                    header_size: dds_header_size(dx10),
+                }),
+                _ => None,
+            };
+
+            files.push(Ba2FileEntry {
+                path,
+                size: file
+                    .iter()
+                    .map(|c| c.decompressed_len().unwrap_or(c.len()) as u64)
+                    .sum(),
+                packed_size: file.iter().map(|c| c.len() as u64).sum(),
+                compressed: file.iter().any(|c| c.is_compressed()),
+                hash: format!(
+                    "{:08x}/{:08x}.{}",
+                    hash.directory,
+                    hash.file,
+                    extension.trim_end_matches('\0')
+                ),
+                offset: file
+                    .iter()
+                    .next()
+                    .map(|c| data_offset(&self.mapping, c.as_ptr()))
+                    .unwrap_or_default(),
+                texture,
+            });
+        }
+
+        debug!(
+            "Listed {} files in BA2 {}",
+            files.len(),
+            self.path.display()
+        );
+        files
+    }
+
+    /// Extract a single file
+    pub fn extract(&self, file_path: &str) -> Result<Vec<u8>> {
+        let mut buffer = Cursor::new(Vec::new());
+        self.read_into(file_path, &mut buffer)?;
+        Ok(buffer.into_inner())
+    }
+
+    /// Extract multiple files in parallel.
+    /// `wanted` should contain lowercase forward-slash-separated paths.
+    pub fn extract_batch<F>(&self, wanted: &HashSet<String>, callback: F) -> Result<usize>
+    where
+        F: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
+    {
+        let write_options: FileWriteOptions = self.options.into();
+
+        // Collect matching entries with references
+        let mut entries: Vec<(String, &Ba2File)> = Vec::new();
+        for (key, file) in self.archive.iter() {
+            let path = String::from_utf8_lossy(key.name().as_bytes()).to_string();
+            let lookup = path.replace('\\', "/").to_lowercase();
+            if wanted.contains(&lookup) {
+                entries.push((path, file));
+            }
+        }
+
+        // Decompress + write in parallel
+        let extracted = AtomicUsize::new(0);
+        entries
+            .par_iter()
+            .try_for_each(|(path, file)| -> Result<()> {
+                let mut buffer = Cursor::new(Vec::new());
+                write_file(file, &mut buffer, &write_options)
+                    .with_context(|| format!("Failed to extract file: {}", path))?;
+
+                callback(path, buffer.into_inner())?;
+                extracted.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            })?;
+
+        let count = extracted.load(Ordering::Relaxed);
+        debug!(
+            "Batch extracted {} of {} wanted files from BA2 {}",
+            count,
+            wanted.len(),
+            self.path.display()
+        );
+        Ok(count)
+    }
 }
 
-/// Extract a single file from a BA2 archive
-#[allow(dead_code)]
-pub fn extract_file(ba2_path: &Path, file_path: &str) -> Result<Vec<u8>> {
-    let (archive, options): (Archive, _) = Archive::read(ba2_path)
-        .with_context(|| format!("Failed to open BA2: {}", ba2_path.display()))?;
-
-    let write_options: FileWriteOptions = options.into();
-
-    // Normalize path for comparison (BA2 uses forward slashes typically)
-    let normalized = file_path.replace('\\', "/").to_lowercase();
-    let normalized_backslash = file_path.replace('/', "\\").to_lowercase();
-
-    for (key, file) in archive.iter() {
-        let current_path = String::from_utf8_lossy(key.name().as_bytes()).to_lowercase();
-
-        // Try both slash conventions
-        if current_path == normalized
-            || current_path == normalized_backslash
-            || current_path.replace('\\', "/") == normalized
-            || current_path.replace('/', "\\") == normalized_backslash
-        {
-            // Write to memory buffer
-            let mut buffer = Cursor::new(Vec::new());
-            file.write(&mut buffer, &write_options)
-                .with_context(|| format!("Failed to extract file: {}", file_path))?;
-
-            return Ok(buffer.into_inner());
+impl OpenArchive for Ba2Archive {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn format(&self) -> ArchiveFormat {
+        ArchiveFormat::Ba2
+    }
+
+    fn options(&self) -> FormatOptions {
+        FormatOptions::Fo4(self.options)
+    }
+
+    fn entries(&self) -> Result<&[ArchiveFileEntry]> {
+        if let Some(entries) = self.entries.get() {
+            return Ok(entries);
         }
+        let entries = self
+            .list()
+            .into_iter()
+            .map(ArchiveFileEntry::from)
+            .collect();
+        Ok(self.entries.get_or_init(|| entries))
     }
 
-    bail!(
-        "File not found in BA2: {} (searched for '{}')",
-        file_path,
-        normalized
-    )
+    fn read_into(&self, file_path: &str, out: &mut dyn Write) -> Result<()> {
+        let Some(file) = self.find(file_path) else {
+            bail!(
+                "File not found in BA2: {} (searched for '{}')",
+                file_path,
+                file_path.replace('\\', "/").to_lowercase()
+            );
+        };
+
+        // Stream straight into the writer, no intermediate buffer
+        let write_options: FileWriteOptions = self.options.into();
+        let mut out = out;
+        write_file(file, &mut out, &write_options)
+            .with_context(|| format!("Failed to extract file: {}", file_path))?;
+        Ok(())
+    }
+
+    fn read_prefix(&self, file_path: &str, len: usize) -> Result<Vec<u8>> {
+        let file = match self.find(file_path) {
+            Some(file) if matches!(file.header, FileHeader::GNRL) => file,
+            // Textures get a DDS header built on extraction
+            _ => {
+                let mut data = self.extract(file_path)?;
+                data.truncate(len);
+                return Ok(data);
+            }
+        };
+
+        let mut data = Vec::with_capacity(len);
+        for chunk in file.iter() {
+            let remaining = len - data.len();
+            if remaining == 0 {
+                break;
+            }
+            let bytes = chunk.as_bytes();
+            if !chunk.is_compressed() {
+                data.extend_from_slice(&bytes[..remaining.min(bytes.len())]);
+            } else if self.options.compression_format() == CompressionFormat::Zip {
+                // zlib streams can stop early; LZ4 blocks can't
+                data.extend(read_up_to(ZlibDecoder::new(bytes), remaining)?);
+            } else {
+                let decompressed = chunk.decompress(&self.options.into())?;
+                let bytes = decompressed.as_bytes();
+                data.extend_from_slice(&bytes[..remaining.min(bytes.len())]);
+            }
+        }
+        Ok(data)
+    }
+
+    fn read_batch(&self, wanted_files: &[String], callback: &BatchCallback) -> Result<usize> {
+        let wanted: HashSet<String> = wanted_files
+            .iter()
+            .map(|p| p.replace('\\', "/").to_lowercase())
+            .collect();
+        self.extract_batch(&wanted, callback)
+    }
+}
+
+/// Write a file's decompressed data, with a DDS header for textures.
+///
+/// DX10 chunks are written in mip order under a header whose mip count
+/// matches the chunks, so textures whose chunks were stored out of order,
+/// or that claim more mips than they hold, still extract to a valid DDS.
+fn write_file<W: Write>(file: &Ba2File, out: &mut W, options: &FileWriteOptions) -> Result<()> {
+    let FileHeader::DX10(dx10) = &file.header else {
+        file.write(out, options)?;
+        return Ok(());
+    };
+    if file.iter().any(|chunk| chunk.mips.is_none()) {
+        file.write(out, options)?;
+        return Ok(());
+    }
+
+    let mut chunks: Vec<_> = file.iter().collect();
+    chunks.sort_by_key(|chunk| chunk.mips.as_ref().map(|mips| *mips.start()));
+    let mut next_mip = 0u16;
+    for chunk in &chunks {
+        let mips = chunk.mips.as_ref().unwrap();
+        if *mips.start() != next_mip || mips.end() < mips.start() {
+            bail!(
+                "texture chunks don't cover its mips in order (chunk for mips {}-{}, expected {} next)",
+                mips.start(),
+                mips.end(),
+                next_mip
+            );
+        }
+        next_mip = mips.end() + 1;
+    }
+
+    let in_order = chunks
+        .iter()
+        .zip(file.iter())
+        .all(|(sorted, stored)| std::ptr::eq(*sorted, stored));
+    if in_order && next_mip == u16::from(dx10.mip_count) {
+        file.write(out, options)?;
+        return Ok(());
+    }
+
+    debug!(
+        "Reassembling texture: {} chunks, {} of {} mips",
+        chunks.len(),
+        next_mip,
+        dx10.mip_count
+    );
+    let mut reordered: Ba2File = chunks.into_iter().cloned().collect();
+    reordered.header = DX10Header {
+        mip_count: next_mip.try_into()?,
+        ..*dx10
+    }
+    .into();
+    reordered.write(out, options)?;
+    Ok(())
+}
+
+/// Size of the DDS header written in front of a DX10 texture on extraction,
+/// built the same way the `ba2` crate builds it
+fn dds_header_size(dx10: &DX10Header) -> u32 {
+    let is_cubemap = (dx10.flags & 1) != 0;
+    let meta = TexMetadata {
+        width: dx10.width.into(),
+        height: dx10.height.into(),
+        depth: 1,
+        array_size: if is_cubemap { 6 } else { 1 },
+        mip_levels: dx10.mip_count.into(),
+        misc_flags: if is_cubemap {
+            TEX_MISC_FLAG::TEX_MISC_TEXTURECUBE.into()
+        } else {
+            0
+        },
+        misc_flags2: 0,
+        format: u32::from(dx10.format).into(),
+        dimension: TEX_DIMENSION::TEX_DIMENSION_TEXTURE2D,
+    };
+    meta.encode_dds_header(DDS_FLAGS::DDS_FLAGS_NONE)
+        .map(|header| header.len() as u32)
+        .unwrap_or(0)
+}
+
+/// List all files in a BA2 archive
+pub fn list_files(ba2_path: &Path) -> Result<Vec<Ba2FileEntry>> {
+    Ok(Ba2Archive::open(ba2_path)?.list())
+}
+
+/// Extract a single file from a BA2 archive
+pub fn extract_file(ba2_path: &Path, file_path: &str) -> Result<Vec<u8>> {
+    Ba2Archive::open(ba2_path)?.extract(file_path)
 }
 
 /// Extract multiple files from a BA2 archive in parallel.
@@ -85,41 +423,5 @@ pub fn extract_files_batch<F>(
 where
     F: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
 {
-    let (archive, options): (Archive, _) = Archive::read(ba2_path)
-        .with_context(|| format!("Failed to open BA2: {}", ba2_path.display()))?;
-
-    let write_options: FileWriteOptions = options.into();
-
-    // Collect matching entries with references
-    let mut entries: Vec<(String, &Ba2File)> = Vec::new();
-    for (key, file) in archive.iter() {
-        let path = String::from_utf8_lossy(key.name().as_bytes()).to_string();
-        let lookup = path.replace('\\', "/").to_lowercase();
-        if wanted.contains(&lookup) {
-            entries.push((path, file));
-        }
-    }
-
-    // Decompress + write in parallel
-    let extracted = AtomicUsize::new(0);
-    entries
-        .par_iter()
-        .try_for_each(|(path, file)| -> Result<()> {
-            let mut buffer = Cursor::new(Vec::new());
-            file.write(&mut buffer, &write_options)
-                .with_context(|| format!("Failed to extract file: {}", path))?;
-
-            callback(path, buffer.into_inner())?;
-            extracted.fetch_add(1, Ordering::Relaxed);
-            Ok(())
-        })?;
-
-    let count = extracted.load(Ordering::Relaxed);
-    debug!(
-        "Batch extracted {} of {} wanted files from BA2 {}",
-        count,
-        wanted.len(),
-        ba2_path.display()
-    );
-    Ok(count)
+    Ba2Archive::open(ba2_path)?.extract_batch(wanted, callback)
 }
@@ -4,18 +4,22 @@
 
 use anyhow::{bail, Context, Result};
 use ba2::fo4::{
-    Archive, ArchiveKey, ArchiveOptionsBuilder, Chunk, ChunkCompressionOptions,
-    CompressionFormat as Ba2CrateCompression, CompressionLevel, File as Ba2File,
-    FileReadOptionsBuilder, Format, Version,
+    ArchiveKey, Chunk, ChunkCompressionOptions, CompressionFormat as Ba2CrateCompression,
+    CompressionLevel, File as Ba2File, FileHeader, FileReadOptionsBuilder, Format, Version,
 };
 use ba2::prelude::*;
 use ba2::{CompressionResult, Copied};
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
 use std::path::Path;
-use tracing::info;
+use tracing::{info, warn};
+
+use super::dds::{inspect_dds, DdsInfo};
+use super::filter::normalize_path;
+use super::ArchiveOrder;
 
 /// BA2 archive version
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -68,10 +72,202 @@ pub enum Ba2Format {
     DX10,
 }
 
-/// Builder for creating BA2 archives
+/// How DX10 textures are split into chunks by mip level.
+///
+/// Each chunk holds whole mips, largest first, so the engine can stream the
+/// small mips of a texture without reading the large ones. Mips are added to
+/// a chunk until the next one would reach `max_chunk_size`; once
+/// `max_chunks - 1` chunks are full, the remaining mips go in the last one.
+/// Cubemaps are always a single chunk. The default matches Archive2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MipChunking {
+    /// Chunk size limit in bytes; `None` uses the size of a 512x512 mip in
+    /// the texture's format, like Archive2
+    pub max_chunk_size: Option<usize>,
+    /// Most chunks per texture, clamped to 1-4 (the most a BA2 file
+    /// record holds in practice)
+    pub max_chunks: usize,
+}
+
+impl Default for MipChunking {
+    fn default() -> Self {
+        Self {
+            max_chunk_size: None,
+            max_chunks: 4,
+        }
+    }
+}
+
+impl MipChunking {
+    /// Every texture in one chunk
+    pub fn single() -> Self {
+        Self {
+            max_chunk_size: None,
+            max_chunks: 1,
+        }
+    }
+
+    /// Mip ranges of each chunk for a texture whose mips have `mip_sizes`
+    fn split(&self, info: &DdsInfo, mip_sizes: &[usize]) -> Vec<Range<usize>> {
+        let limit = self
+            .max_chunk_size
+            .unwrap_or_else(|| info.image_size(512, 512));
+        let max_chunks = self.max_chunks.clamp(1, 4);
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        let mut size = 0;
+        for (mip, &mip_size) in mip_sizes.iter().enumerate() {
+            if size != 0 && size + mip_size >= limit && ranges.len() + 1 < max_chunks {
+                ranges.push(start..mip);
+                start = mip;
+                size = 0;
+            }
+            size += mip_size;
+        }
+        ranges.push(start..mip_sizes.len());
+        ranges
+    }
+}
+
+/// Re-split a texture read from a DDS into chunks by mip level, compressing
+/// each one when `compression` is given
+fn chunk_texture(
+    file: Ba2File<'_>,
+    info: &DdsInfo,
+    chunking: MipChunking,
+    compression: Option<&ChunkCompressionOptions>,
+) -> Result<Ba2File<'static>> {
+    let mut data = Vec::new();
+    for chunk in file.iter() {
+        data.extend_from_slice(chunk.as_bytes());
+    }
+
+    // (mips, bytes) of each chunk
+    let ranges: Vec<(Range<usize>, Range<usize>)> = if info.cubemap {
+        vec![(0..info.mip_count as usize, 0..data.len())]
+    } else {
+        let sizes = info.mip_sizes();
+        let expected: usize = sizes.iter().sum();
+        if data.len() != expected {
+            bail!(
+                "texture data is {} bytes, expected {}",
+                data.len(),
+                expected
+            );
+        }
+        let offsets: Vec<usize> = std::iter::once(0)
+            .chain(sizes.iter().scan(0, |offset, size| {
+                *offset += size;
+                Some(*offset)
+            }))
+            .collect();
+        chunking
+            .split(info, &sizes)
+            .into_iter()
+            .map(|mips| (mips.clone(), offsets[mips.start]..offsets[mips.end]))
+            .collect()
+    };
+
+    let mut chunks = Vec::with_capacity(ranges.len());
+    for (mips, bytes) in ranges {
+        let mut chunk = Chunk::from_decompressed(data[bytes].to_vec().into_boxed_slice());
+        chunk.mips = Some(mips.start as u16..=(mips.end - 1) as u16);
+        if let Some(options) = compression {
+            chunk = chunk.compress(options)?;
+        }
+        chunks.push(chunk);
+    }
+
+    let mut chunked: Ba2File<'static> = chunks.into_iter().collect();
+    chunked.header = file.header;
+    Ok(chunked)
+}
+
+/// Contents of a file added to the builder
+enum FileData {
+    /// Decompressed bytes (a DDS file for DX10 archives)
+    Raw(Vec<u8>),
+    /// A file as stored in another BA2, with that archive's compression format
+    Stored {
+        file: Ba2File<'static>,
+        compression: Ba2CompressionFormat,
+    },
+}
+
+impl FileData {
+    /// Decompressed size in bytes
+    fn len(&self) -> usize {
+        match self {
+            FileData::Raw(data) => data.len(),
+            FileData::Stored { file, .. } => file
+                .iter()
+                .map(|c| c.decompressed_len().unwrap_or(c.len()))
+                .sum(),
+        }
+    }
+}
+
+/// Crate compression format written for a builder setting, `None` when
+/// uncompressed. Compressed archives are currently always written with zlib.
+fn chunk_format(compression: Ba2CompressionFormat) -> Option<Ba2CrateCompression> {
+    match compression {
+        Ba2CompressionFormat::None => None,
+        _ => Some(Ba2CrateCompression::Zip),
+    }
+}
+
+/// Re-encode the chunks of a stored file for the target compression.
+/// Chunks already in the target form are copied as-is; also returns whether
+/// every chunk was.
+fn convert_stored(
+    file: &Ba2File<'static>,
+    source: Ba2CompressionFormat,
+    target: Ba2CompressionFormat,
+) -> Result<(Ba2File<'static>, bool)> {
+    let source_format = match source {
+        Ba2CompressionFormat::Lz4 => Ba2CrateCompression::LZ4,
+        _ => Ba2CrateCompression::Zip,
+    };
+    let target_format = chunk_format(target);
+
+    let mut converted = file.clone();
+    let mut copied = true;
+    for chunk in converted.iter_mut() {
+        let keep = if chunk.is_compressed() {
+            target_format == Some(source_format)
+        } else {
+            target_format.is_none()
+        };
+        if keep {
+            continue;
+        }
+        copied = false;
+
+        if chunk.is_compressed() {
+            let options = ChunkCompressionOptions::builder()
+                .compression_format(source_format)
+                .build();
+            *chunk = chunk.decompress(&options)?;
+        }
+        if let Some(format) = target_format {
+            let options = ChunkCompressionOptions::builder()
+                .compression_format(format)
+                .build();
+            *chunk = chunk.compress(&options)?;
+        }
+    }
+    Ok((converted, copied))
+}
+
+/// Builder for creating BA2 archives.
+///
+/// Output is byte-reproducible: files are kept in path order and written in
+/// hash order with fixed compression settings, whatever order they were
+/// added in. Paths that differ only in case are the same file; the one
+/// added last wins.
 pub struct Ba2Builder {
-    /// Files organized by path -> data
-    files: HashMap<String, Vec<u8>>,
+    /// Normalized path -> (path as added, data)
+    files: BTreeMap<String, (String, FileData)>,
     /// Archive format (General or DX10)
     format: Ba2Format,
     /// Compression format
@@ -80,21 +276,26 @@ pub struct Ba2Builder {
     strings: bool,
     /// Archive version
     version: Ba2Version,
+    /// Order of the file records and data
+    order: ArchiveOrder,
+    /// How DX10 textures are split into chunks
+    chunking: MipChunking,
 }
 
 impl Ba2Builder {
     pub fn new() -> Self {
         Self {
-            files: HashMap::new(),
+            files: BTreeMap::new(),
             format: Ba2Format::General,
             compression: Ba2CompressionFormat::Zlib,
             strings: true,
             version: Ba2Version::default(),
+            order: ArchiveOrder::default(),
+            chunking: MipChunking::default(),
         }
     }
 
     /// Create builder with settings detected from BA2 name
-    #[allow(dead_code)]
     pub fn from_name(name: &str) -> Self {
         let name_lower = name.to_lowercase();
 
@@ -118,11 +319,13 @@ impl Ba2Builder {
         let compression = Ba2CompressionFormat::Zlib;
 
         Self {
-            files: HashMap::new(),
+            files: BTreeMap::new(),
             format,
             compression,
             strings: true,
             version: Ba2Version::default(),
+            order: ArchiveOrder::default(),
+            chunking: MipChunking::default(),
         }
     }
 
@@ -144,8 +347,19 @@ impl Ba2Builder {
         self
     }
 
+    /// Set the order files are written in
+    pub fn with_order(mut self, order: ArchiveOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Set how DX10 textures are split into chunks by mip level
+    pub fn with_mip_chunking(mut self, chunking: MipChunking) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
     /// Enable or disable string table
-    #[allow(dead_code)]
     pub fn with_strings(mut self, strings: bool) -> Self {
         self.strings = strings;
         self
@@ -153,10 +367,41 @@ impl Ba2Builder {
 
     /// Add a file to the archive
     pub fn add_file(&mut self, path: &str, data: Vec<u8>) {
+        self.insert(path, FileData::Raw(data));
+    }
+
+    /// Add a file as stored in another BA2 whose chunks use `source_compression`.
+    /// Compressed chunks are copied without recompressing when this builder
+    /// writes the same compression format, which makes repacks much faster.
+    /// The file must match this builder's format (GNRL or DX10).
+    pub fn add_stored_file(
+        &mut self,
+        path: &str,
+        file: Ba2File<'static>,
+        source_compression: Ba2CompressionFormat,
+    ) {
+        self.insert(
+            path,
+            FileData::Stored {
+                file,
+                compression: source_compression,
+            },
+        );
+    }
+
+    fn insert(&mut self, path: &str, data: FileData) {
         // Normalize: forward slashes, strip leading slash
         let normalized = path.replace('\\', "/");
         let normalized = normalized.trim_start_matches('/').to_string();
-        self.files.insert(normalized, data);
+        let key = normalize_path(&normalized);
+        if let Some((previous, _)) = self.files.insert(key, (normalized.clone(), data)) {
+            if previous != normalized {
+                warn!(
+                    "{} replaces {} (paths differ only in case)",
+                    normalized, previous
+                );
+            }
+        }
     }
 
     /// Get number of files
@@ -170,7 +415,7 @@ impl Ba2Builder {
     }
 
     /// Build and write the BA2 to disk with progress callback
-    pub fn build_with_progress<F>(self, output_path: &Path, progress: F) -> Result<()>
+    pub fn build_with_progress<F>(mut self, output_path: &Path, progress: F) -> Result<()>
     where
         F: Fn(usize, usize, &str) + Send + Sync,
     {
@@ -179,7 +424,7 @@ impl Ba2Builder {
         }
 
         let file_count = self.file_count();
-        let total_size: u64 = self.files.values().map(|data| data.len() as u64).sum();
+        let total_size: u64 = self.files.values().map(|(_, data)| data.len() as u64).sum();
 
         info!(
             "Building BA2: {} ({} files, {} MB, format {:?}, compression {:?})",
@@ -196,31 +441,51 @@ impl Ba2Builder {
         }
 
         // Build archive entries in parallel
-        let entries: Vec<(String, Vec<u8>)> = self.files.into_iter().collect();
+        let entries = self.ordered_entries();
         let total = entries.len();
         let processed_count = std::sync::atomic::AtomicUsize::new(0);
         let compression = self.compression;
+        let copied = std::sync::atomic::AtomicUsize::new(0);
 
         let archive_entries: Result<Vec<(ArchiveKey<'static>, Ba2File<'static>)>> = entries
             .par_iter()
             .map(|(path, data)| {
-                // Create chunk from data
-                let chunk = Chunk::from_decompressed(data.clone().into_boxed_slice());
-
-                // Optionally compress the chunk
-                let chunk = if compression != Ba2CompressionFormat::None {
-                    let options = ChunkCompressionOptions::default();
-                    match chunk.compress(&options) {
-                        Ok(compressed) => compressed,
-                        Err(_) => chunk, // Fall back to uncompressed if compression fails
+                let file = match data {
+                    FileData::Raw(data) => {
+                        // Create chunk from data
+                        let chunk = Chunk::from_decompressed(data.clone().into_boxed_slice());
+
+                        // Optionally compress the chunk
+                        let chunk = if let Some(format) = chunk_format(compression) {
+                            let options = ChunkCompressionOptions::builder()
+                                .compression_format(format)
+                                .compression_level(CompressionLevel::FO4)
+                                .build();
+                            match chunk.compress(&options) {
+                                Ok(compressed) => compressed,
+                                Err(_) => chunk, // Fall back to uncompressed if compression fails
+                            }
+                        } else {
+                            chunk
+                        };
+
+                        // Create file from chunk
+                        let file: Ba2File = [chunk].into_iter().collect();
+                        file
+                    }
+                    FileData::Stored {
+                        file,
+                        compression: source,
+                    } => {
+                        let (file, as_is) = convert_stored(file, *source, compression)
+                            .with_context(|| format!("Failed to convert: {}", path))?;
+                        if as_is {
+                            copied.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        }
+                        file
                     }
-                } else {
-                    chunk
                 };
 
-                // Create file from chunk
-                let file: Ba2File = [chunk].into_iter().collect();
-
                 // Create key from path
                 let key: ArchiveKey = path.as_bytes().into();
 
@@ -233,60 +498,77 @@ impl Ba2Builder {
             .collect();
 
         let archive_entries = archive_entries?;
+        log_copied(copied.into_inner(), total);
 
-        // Build archive from entries
-        let archive: Archive = archive_entries.into_iter().collect();
-
-        let options = ArchiveOptionsBuilder::default()
-            .version(self.version.to_crate_version())
-            .strings(self.strings)
-            .build();
-
-        // Create parent directory
-        if let Some(parent) = output_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
-        // Write archive
-        let file = fs::File::create(output_path)
-            .with_context(|| format!("Failed to create BA2: {}", output_path.display()))?;
-        let mut writer = BufWriter::new(file);
-
-        archive
-            .write(&mut writer, &options)
-            .with_context(|| format!("Failed to write BA2: {}", output_path.display()))?;
+        self.write_archive(output_path, archive_entries, Format::GNRL)?;
 
         info!("Created BA2: {}", output_path.display());
         Ok(())
     }
 
     /// Build a DX10 (texture) archive with progress callback
-    fn build_dx10_with_progress<F>(self, output_path: &Path, progress: F) -> Result<()>
+    fn build_dx10_with_progress<F>(mut self, output_path: &Path, progress: F) -> Result<()>
     where
         F: Fn(usize, usize, &str) + Send + Sync,
     {
         let compress = self.compression != Ba2CompressionFormat::None;
-        let entries: Vec<(String, Vec<u8>)> = self.files.into_iter().collect();
+        let entries = self.ordered_entries();
         let total = entries.len();
         let processed_count = std::sync::atomic::AtomicUsize::new(0);
+        let copied = std::sync::atomic::AtomicUsize::new(0);
 
-        // Build read options for DX10 format
+        // Check every texture up front so one build reports all bad files
+        let invalid: Vec<String> = entries
+            .par_iter()
+            .filter_map(|(path, data)| match data {
+                FileData::Raw(data) => inspect_dds(data).err().map(|e| format!("{}: {}", path, e)),
+                FileData::Stored { .. } => None,
+            })
+            .collect();
+        if !invalid.is_empty() {
+            bail!(
+                "{} of {} textures can't be packed:\n  {}",
+                invalid.len(),
+                total,
+                invalid.join("\n  ")
+            );
+        }
+
+        // Read each DDS as one run of mips, then split it into chunks
         let read_options = FileReadOptionsBuilder::new()
             .format(Format::DX10)
+            .compression_result(CompressionResult::Decompressed)
+            .build();
+        let chunk_options = ChunkCompressionOptions::builder()
             .compression_format(Ba2CrateCompression::Zip)
             .compression_level(CompressionLevel::FO4)
-            .compression_result(if compress {
-                CompressionResult::Compressed
-            } else {
-                CompressionResult::Decompressed
-            })
             .build();
+        let chunk_options = compress.then_some(&chunk_options);
 
         let archive_entries: Result<Vec<(ArchiveKey<'static>, Ba2File<'static>)>> = entries
             .par_iter()
             .map(|(path, data)| {
-                let file = Ba2File::read(Copied(data), &read_options)
-                    .with_context(|| format!("Failed to parse DDS texture: {}", path))?;
+                let file = match data {
+                    FileData::Raw(data) => {
+                        let info = inspect_dds(data)
+                            .with_context(|| format!("Failed to parse DDS texture: {}", path))?;
+                        let file = Ba2File::read(Copied(data), &read_options)
+                            .with_context(|| format!("Failed to parse DDS texture: {}", path))?;
+                        chunk_texture(file, &info, self.chunking, chunk_options)
+                            .with_context(|| format!("Failed to chunk DDS texture: {}", path))?
+                    }
+                    FileData::Stored {
+                        file,
+                        compression: source,
+                    } => {
+                        let (file, as_is) = convert_stored(file, *source, self.compression)
+                            .with_context(|| format!("Failed to convert: {}", path))?;
+                        if as_is {
+                            copied.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        }
+                        file
+                    }
+                };
 
                 let key: ArchiveKey = path.as_bytes().into();
 
@@ -299,38 +581,198 @@ impl Ba2Builder {
             .collect();
 
         let archive_entries = archive_entries?;
-        let archive: Archive = archive_entries.into_iter().collect();
+        log_copied(copied.into_inner(), total);
+        self.write_archive(output_path, archive_entries, Format::DX10)?;
 
-        let options = ArchiveOptionsBuilder::default()
-            .version(self.version.to_crate_version())
-            .format(Format::DX10)
-            .compression_format(Ba2CrateCompression::Zip)
-            .strings(self.strings)
-            .build();
+        info!(
+            "Created DX10 BA2: {} ({} files)",
+            output_path.display(),
+            total
+        );
+        Ok(())
+    }
+}
+
+impl Ba2Builder {
+    /// Take the files out in the order they will be written
+    fn ordered_entries(&mut self) -> Vec<(String, FileData)> {
+        let mut entries: Vec<(String, FileData)> =
+            std::mem::take(&mut self.files).into_values().collect();
+        match self.order {
+            // Sorted by hash when written
+            ArchiveOrder::Hash => {}
+            ArchiveOrder::Vanilla => entries.sort_by_cached_key(|(path, _)| vanilla_key(path)),
+        }
+        entries
+    }
+
+    /// Write the header, file records, data and string table. The ba2 crate
+    /// always writes in hash order; this keeps the order of `entries`
+    /// instead (sorted by hash for [`ArchiveOrder::Hash`]). Readers look files
+    /// up through their own hash map, so either order loads.
+    fn write_archive(
+        &self,
+        output_path: &Path,
+        mut entries: Vec<(ArchiveKey<'static>, Ba2File<'static>)>,
+        format: Format,
+    ) -> Result<()> {
+        if self.order == ArchiveOrder::Hash {
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        // Two different paths with the same hash can't both be looked up
+        let mut hashes: Vec<(&ArchiveKey, u32, u32, u32)> = entries
+            .iter()
+            .map(|(key, _)| {
+                (
+                    key,
+                    key.hash().file,
+                    key.hash().extension,
+                    key.hash().directory,
+                )
+            })
+            .collect();
+        hashes.sort_by_key(|h| (h.1, h.2, h.3));
+        for pair in hashes.windows(2) {
+            if (pair[0].1, pair[0].2, pair[0].3) == (pair[1].1, pair[1].2, pair[1].3) {
+                bail!(
+                    "Hash collision: {} and {}",
+                    pair[0].0.name(),
+                    pair[1].0.name()
+                );
+            }
+        }
+
+        let version = self.version.to_crate_version();
+        let header_size: u64 = match version {
+            Version::v2 => 0x20,
+            Version::v3 => 0x24,
+            _ => 0x18,
+        };
+        let (record_size, chunk_size, format_tag): (u64, u64, &[u8; 4]) = match format {
+            Format::DX10 => (0x18, 0x18, b"DX10"),
+            _ => (0x10, 0x14, b"GNRL"),
+        };
+        let chunk_count: u64 = entries.iter().map(|(_, f)| f.len() as u64).sum();
+        let data_size: u64 = entries
+            .iter()
+            .flat_map(|(_, f)| f.iter())
+            .map(|c| c.len() as u64)
+            .sum();
+        let data_offset =
+            header_size + entries.len() as u64 * record_size + chunk_count * chunk_size;
+        let strings_offset = if self.strings {
+            data_offset + data_size
+        } else {
+            0
+        };
 
         if let Some(parent) = output_path.parent() {
             fs::create_dir_all(parent)?;
         }
-
         let file = fs::File::create(output_path)
             .with_context(|| format!("Failed to create BA2: {}", output_path.display()))?;
-        let mut writer = BufWriter::new(file);
+        let mut out = BufWriter::new(file);
+        let write_err = || format!("Failed to write BA2: {}", output_path.display());
+
+        // Header
+        let mut buf = Vec::with_capacity(data_offset as usize);
+        buf.extend_from_slice(b"BTDX");
+        buf.extend_from_slice(&(version as u32).to_le_bytes());
+        buf.extend_from_slice(format_tag);
+        buf.extend_from_slice(&u32::try_from(entries.len())?.to_le_bytes());
+        buf.extend_from_slice(&strings_offset.to_le_bytes());
+        if matches!(version, Version::v2 | Version::v3) {
+            buf.extend_from_slice(&1u64.to_le_bytes());
+        }
+        if version == Version::v3 {
+            // Compressed chunks are always zlib
+            buf.extend_from_slice(&0u32.to_le_bytes());
+        }
 
-        archive
-            .write(&mut writer, &options)
-            .with_context(|| format!("Failed to write BA2: {}", output_path.display()))?;
+        // File records and their chunk records
+        let mut offset = data_offset;
+        for (key, file) in &entries {
+            let hash = key.hash();
+            buf.extend_from_slice(&hash.file.to_le_bytes());
+            buf.extend_from_slice(&hash.extension.to_le_bytes());
+            buf.extend_from_slice(&hash.directory.to_le_bytes());
+            buf.push(0);
+            buf.push(
+                u8::try_from(file.len())
+                    .with_context(|| format!("Too many chunks: {}", key.name()))?,
+            );
+            buf.extend_from_slice(&(record_size as u16).to_le_bytes());
+            match (format, &file.header) {
+                (Format::DX10, FileHeader::DX10(dx10)) => {
+                    buf.extend_from_slice(&dx10.height.to_le_bytes());
+                    buf.extend_from_slice(&dx10.width.to_le_bytes());
+                    buf.extend_from_slice(&[
+                        dx10.mip_count,
+                        dx10.format,
+                        dx10.flags,
+                        dx10.tile_mode,
+                    ]);
+                }
+                (Format::GNRL, FileHeader::GNRL) => {}
+                _ => bail!("File does not match the archive format: {}", key.name()),
+            }
+            for chunk in file.iter() {
+                let (packed, unpacked) = match chunk.decompressed_len() {
+                    Some(len) => (u32::try_from(chunk.len())?, u32::try_from(len)?),
+                    None => (0, u32::try_from(chunk.len())?),
+                };
+                buf.extend_from_slice(&offset.to_le_bytes());
+                buf.extend_from_slice(&packed.to_le_bytes());
+                buf.extend_from_slice(&unpacked.to_le_bytes());
+                if format == Format::DX10 {
+                    let mips = chunk.mips.clone().unwrap_or(0..=0);
+                    buf.extend_from_slice(&mips.start().to_le_bytes());
+                    buf.extend_from_slice(&mips.end().to_le_bytes());
+                }
+                buf.extend_from_slice(&0xBAAD_F00Du32.to_le_bytes());
+                offset += chunk.len() as u64;
+            }
+        }
+        out.write_all(&buf).with_context(write_err)?;
 
-        info!(
-            "Created DX10 BA2: {} ({} files)",
-            output_path.display(),
-            total
-        );
+        // Chunk data in record order
+        for (_, file) in &entries {
+            for chunk in file.iter() {
+                out.write_all(chunk.as_bytes()).with_context(write_err)?;
+            }
+        }
+
+        if self.strings {
+            let mut names = Vec::new();
+            for (key, _) in &entries {
+                let name = key.name();
+                names.extend_from_slice(&u16::try_from(name.len())?.to_le_bytes());
+                names.extend_from_slice(name);
+            }
+            out.write_all(&names).with_context(write_err)?;
+        }
+        out.flush().with_context(write_err)?;
         Ok(())
     }
 }
 
+/// Sort key grouping files like Archive2 does: by directory, then by
+/// extension, then by name
+fn vanilla_key(path: &str) -> (String, String, String) {
+    let normalized = normalize_path(path);
+    let (dir, name) = normalized.rsplit_once('/').unwrap_or(("", &normalized));
+    let ext = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+    (dir.to_string(), ext.to_string(), name.to_string())
+}
+
 impl Default for Ba2Builder {
     fn default() -> Self {
         Self::new()
     }
 }
+
+fn log_copied(copied: usize, total: usize) {
+    if copied > 0 {
+        info!("Copied {} of {} files without recompressing", copied, total);
+    }
+}
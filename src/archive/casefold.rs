@@ -0,0 +1,366 @@
+//! Case folding for extracted paths
+//!
+//! Bethesda archives are case-insensitive, but most Linux filesystems are not.
+//! Extracting `Textures\a.dds` and `textures\b.dds` naively creates two sibling
+//! directories, which breaks mod managers and Proton prefixes. The resolver in
+//! this module maps archive paths onto a consistent on-disk layout.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How directory and file name case is handled when extracting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaseFolding {
+    /// Write paths exactly as stored in the archive
+    #[default]
+    Preserve,
+    /// Lowercase every path component
+    Lower,
+    /// Reuse the case of directories/files that already exist on disk,
+    /// falling back to the first spelling seen in the archive
+    Merge,
+}
+
+impl CaseFolding {
+    /// Get display name for this mode
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            CaseFolding::Preserve => "Preserve case",
+            CaseFolding::Lower => "Lowercase",
+            CaseFolding::Merge => "Merge with existing",
+        }
+    }
+
+    /// Short CLI-friendly name
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            CaseFolding::Preserve => "preserve",
+            CaseFolding::Lower => "lower",
+            CaseFolding::Merge => "merge",
+        }
+    }
+
+    /// Parse from CLI name (case-insensitive)
+    pub fn from_cli_name(name: &str) -> Option<CaseFolding> {
+        let lower = name.to_lowercase();
+        CaseFolding::all()
+            .iter()
+            .find(|m| m.cli_name() == lower)
+            .copied()
+    }
+
+    /// Get all modes
+    pub fn all() -> &'static [CaseFolding] {
+        &[
+            CaseFolding::Preserve,
+            CaseFolding::Lower,
+            CaseFolding::Merge,
+        ]
+    }
+
+    /// Convert index to mode
+    pub fn from_index(index: i32) -> CaseFolding {
+        match index {
+            1 => CaseFolding::Lower,
+            2 => CaseFolding::Merge,
+            _ => CaseFolding::Preserve,
+        }
+    }
+
+    /// Convert mode to index
+    pub fn index(self) -> i32 {
+        match self {
+            CaseFolding::Preserve => 0,
+            CaseFolding::Lower => 1,
+            CaseFolding::Merge => 2,
+        }
+    }
+}
+
+/// Two archive paths that resolve to the same file on a case-insensitive lookup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseCollision {
+    /// Archive path that claimed the output file first
+    pub first: String,
+    /// Later archive path that resolved to the same file
+    pub second: String,
+    /// Output file both paths map to
+    pub output: PathBuf,
+}
+
+/// Maps archive paths to output paths under a root folder
+pub struct OutputPathResolver {
+    root: PathBuf,
+    mode: CaseFolding,
+    /// Lowercase relative path -> on-disk spelling, for directories and files
+    known: HashMap<String, String>,
+    /// Lowercase relative file path -> archive path that claimed it
+    claimed: HashMap<String, String>,
+    /// Directory -> its entries on disk, lowercase name -> on-disk spelling.
+    /// Each directory is read once.
+    listings: HashMap<PathBuf, HashMap<String, String>>,
+    collisions: Vec<CaseCollision>,
+}
+
+impl OutputPathResolver {
+    pub fn new(root: &Path, mode: CaseFolding) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            mode,
+            known: HashMap::new(),
+            claimed: HashMap::new(),
+            listings: HashMap::new(),
+            collisions: Vec::new(),
+        }
+    }
+
+    /// Resolve an archive path (either slash convention) to its output path.
+    /// Records a collision when another archive path already resolved to the
+    /// same file, ignoring case.
+    pub fn resolve(&mut self, archive_path: &str) -> Result<PathBuf> {
+        let normalized = archive_path.replace('\\', "/");
+        let components: Vec<&str> = normalized.split('/').filter(|c| !c.is_empty()).collect();
+
+        let mut relative = String::new();
+        let mut lookup = String::new();
+
+        for component in components {
+            if !lookup.is_empty() {
+                lookup.push('/');
+                relative.push('/');
+            }
+            lookup.push_str(&component.to_lowercase());
+
+            let name = match self.mode {
+                CaseFolding::Preserve => component.to_string(),
+                CaseFolding::Lower => component.to_lowercase(),
+                CaseFolding::Merge => match self.known.get(&lookup) {
+                    Some(known) => known.clone(),
+                    None => {
+                        let name = self
+                            .find_on_disk(&self.root.join(&relative), component)?
+                            .unwrap_or_else(|| component.to_string());
+                        self.known.insert(lookup.clone(), name.clone());
+                        name
+                    }
+                },
+            };
+            relative.push_str(&name);
+        }
+
+        let output = self.root.join(&relative);
+        match self.claimed.get(&lookup) {
+            Some(first) if first != archive_path => {
+                self.collisions.push(CaseCollision {
+                    first: first.clone(),
+                    second: archive_path.to_string(),
+                    output: output.clone(),
+                });
+            }
+            Some(_) => {}
+            None => {
+                self.claimed.insert(lookup, archive_path.to_string());
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Collisions detected so far, in resolution order
+    pub fn collisions(&self) -> &[CaseCollision] {
+        &self.collisions
+    }
+
+    /// Find an existing entry in `dir` whose name matches `name` ignoring case
+    fn find_on_disk(&mut self, dir: &Path, name: &str) -> Result<Option<String>> {
+        if !self.listings.contains_key(dir) {
+            let listing = read_listing(dir)?;
+            self.listings.insert(dir.to_path_buf(), listing);
+        }
+        let Some(found) = self.listings[dir].get(&name.to_lowercase()) else {
+            return Ok(None);
+        };
+
+        // Prefer an exact match so existing layouts are left untouched
+        if found != name && dir.join(name).exists() {
+            return Ok(Some(name.to_string()));
+        }
+        Ok(Some(found.clone()))
+    }
+}
+
+/// Output paths for a whole extraction, resolved up front in archive order
+/// so the chosen directory spelling doesn't depend on extraction thread timing
+pub struct ExtractionPlan {
+    /// Archive paths to extract, in the order given
+    pub files: Vec<String>,
+    /// Archive path -> output path
+    pub targets: HashMap<String, PathBuf>,
+    /// Paths that collide ignoring case
+    pub collisions: Vec<CaseCollision>,
+}
+
+impl ExtractionPlan {
+    /// Resolve every path in `paths` under `root`.
+    /// When folding is enabled, the first colliding path wins and later ones
+    /// are left out of `files` so two entries never write the same file.
+    pub fn new(root: &Path, paths: &[String], mode: CaseFolding) -> Result<Self> {
+        let mut resolver = OutputPathResolver::new(root, mode);
+        let mut files = Vec::with_capacity(paths.len());
+        let mut targets = HashMap::with_capacity(paths.len());
+
+        for path in paths {
+            let before = resolver.collisions().len();
+            let output = resolver.resolve(path)?;
+            if mode != CaseFolding::Preserve && resolver.collisions().len() > before {
+                continue;
+            }
+            files.push(path.clone());
+            targets.insert(path.clone(), output);
+        }
+
+        Ok(Self {
+            files,
+            targets,
+            collisions: resolver.collisions,
+        })
+    }
+
+    /// Output path for an archive path
+    pub fn target(&self, archive_path: &str) -> Option<&Path> {
+        self.targets.get(archive_path).map(PathBuf::as_path)
+    }
+}
+
+/// Entries of `dir` by lowercase name; empty when it doesn't exist. When
+/// several entries differ only in case, the first one read is kept.
+fn read_listing(dir: &Path) -> Result<HashMap<String, String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut listing = HashMap::new();
+    for entry in entries {
+        let entry = entry?;
+        let entry_name = entry.file_name().to_string_lossy().to_string();
+        listing
+            .entry(entry_name.to_lowercase())
+            .or_insert(entry_name);
+    }
+    Ok(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATHS: [&str; 3] = ["Foo\\a.dds", "foo\\A.dds", "foo\\b.dds"];
+
+    fn paths() -> Vec<String> {
+        PATHS.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn temp_root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "bsa-ba2-tool-casefold-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn preserve_keeps_every_spelling_and_reports_collisions() {
+        let root = Path::new("/out");
+        let plan = ExtractionPlan::new(root, &paths(), CaseFolding::Preserve).unwrap();
+
+        assert_eq!(plan.files, paths());
+        assert_eq!(
+            plan.target("Foo\\a.dds"),
+            Some(root.join("Foo/a.dds").as_path())
+        );
+        assert_eq!(
+            plan.target("foo\\A.dds"),
+            Some(root.join("foo/A.dds").as_path())
+        );
+        assert_eq!(
+            plan.collisions,
+            vec![CaseCollision {
+                first: "Foo\\a.dds".to_string(),
+                second: "foo\\A.dds".to_string(),
+                output: root.join("foo/A.dds"),
+            }]
+        );
+    }
+
+    #[test]
+    fn lower_drops_later_collisions() {
+        let root = Path::new("/out");
+        let plan = ExtractionPlan::new(root, &paths(), CaseFolding::Lower).unwrap();
+
+        assert_eq!(plan.files, vec!["Foo\\a.dds", "foo\\b.dds"]);
+        assert_eq!(
+            plan.target("Foo\\a.dds"),
+            Some(root.join("foo/a.dds").as_path())
+        );
+        assert_eq!(
+            plan.target("foo\\b.dds"),
+            Some(root.join("foo/b.dds").as_path())
+        );
+        assert_eq!(plan.target("foo\\A.dds"), None);
+        assert_eq!(plan.collisions.len(), 1);
+        assert_eq!(plan.collisions[0].output, root.join("foo/a.dds"));
+    }
+
+    #[test]
+    fn merge_uses_first_spelling_without_existing_folders() {
+        let root = temp_root("first");
+        let plan = ExtractionPlan::new(&root, &paths(), CaseFolding::Merge).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(plan.files, vec!["Foo\\a.dds", "foo\\b.dds"]);
+        assert_eq!(
+            plan.target("Foo\\a.dds"),
+            Some(root.join("Foo/a.dds").as_path())
+        );
+        assert_eq!(
+            plan.target("foo\\b.dds"),
+            Some(root.join("Foo/b.dds").as_path())
+        );
+        assert_eq!(plan.collisions.len(), 1);
+        assert_eq!(plan.collisions[0].second, "foo\\A.dds");
+    }
+
+    #[test]
+    fn merge_reuses_existing_folders() {
+        let root = temp_root("existing");
+        fs::create_dir_all(root.join("FOO")).unwrap();
+        fs::write(root.join("FOO/B.dds"), b"").unwrap();
+        let plan = ExtractionPlan::new(&root, &paths(), CaseFolding::Merge).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            plan.target("Foo\\a.dds"),
+            Some(root.join("FOO/a.dds").as_path())
+        );
+        assert_eq!(
+            plan.target("foo\\b.dds"),
+            Some(root.join("FOO/B.dds").as_path())
+        );
+        assert_eq!(plan.collisions.len(), 1);
+    }
+
+    #[test]
+    fn same_path_twice_is_not_a_collision() {
+        let mut resolver = OutputPathResolver::new(Path::new("/out"), CaseFolding::Lower);
+        resolver.resolve("Foo\\a.dds").unwrap();
+        resolver.resolve("Foo\\a.dds").unwrap();
+        assert!(resolver.collisions().is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+//! Duplicate and conflict detection across archives and loose files
+//!
+//! Every path provided by more than one [`Vfs`] source is read from each
+//! source and hashed after decompression, so archives that store the same
+//! file with different compression still compare as identical.
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use twox_hash::XxHash3_128;
+
+use super::Vfs;
+
+/// Whether every copy of a conflicting path has the same contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStatus {
+    /// All copies are byte-identical once decompressed; the extras are redundant
+    Identical,
+    /// At least one copy differs, so the load order matters
+    Different,
+}
+
+impl ConflictStatus {
+    /// Get display name for this status
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ConflictStatus::Identical => "Identical contents",
+            ConflictStatus::Different => "Different contents",
+        }
+    }
+
+    /// Short CLI-friendly name
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            ConflictStatus::Identical => "identical",
+            ConflictStatus::Different => "different",
+        }
+    }
+
+    /// Parse from CLI name (case-insensitive)
+    pub fn from_cli_name(name: &str) -> Option<ConflictStatus> {
+        let lower = name.to_lowercase();
+        ConflictStatus::all()
+            .iter()
+            .find(|s| s.cli_name() == lower)
+            .copied()
+    }
+
+    /// Get all statuses
+    pub fn all() -> &'static [ConflictStatus] {
+        &[ConflictStatus::Identical, ConflictStatus::Different]
+    }
+}
+
+/// One source's copy of a conflicting path
+#[derive(Debug, Clone, Serialize)]
+pub struct ConflictCopy {
+    /// Archive or loose directory providing this copy
+    pub source: PathBuf,
+    /// Path as stored in that source
+    pub path: String,
+    /// Decompressed size in bytes
+    pub size: u64,
+    /// XXH3-128 of the decompressed contents, in lowercase hex
+    pub hash: String,
+    /// Whether this is the copy the game sees
+    pub winner: bool,
+}
+
+/// A path provided by more than one source
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    /// Path as stored in the winning source
+    pub path: String,
+    /// Whether the copies are identical
+    pub status: ConflictStatus,
+    /// Every copy, lowest priority first; the winner is last
+    pub copies: Vec<ConflictCopy>,
+}
+
+impl Conflict {
+    /// The copy the game sees
+    pub fn winner(&self) -> &ConflictCopy {
+        self.copies.last().expect("conflict without copies")
+    }
+}
+
+/// Find every path provided by more than one source, sorted by path
+pub fn find_conflicts(vfs: &Vfs) -> Result<Vec<Conflict>> {
+    find_conflicts_with_progress(vfs, |_, _, _| {})
+}
+
+/// Find conflicts, calling `progress(done, total, path)` as each path is hashed
+pub fn find_conflicts_with_progress<F>(vfs: &Vfs, progress: F) -> Result<Vec<Conflict>>
+where
+    F: Fn(usize, usize, &str) + Send + Sync,
+{
+    let files: Vec<_> = vfs.files().filter(|f| !f.overridden.is_empty()).collect();
+    let total = files.len();
+    let done = AtomicUsize::new(0);
+
+    files
+        .par_iter()
+        .map(|file| {
+            let layers = file.layers();
+            let winner = layers.len() - 1;
+            let copies = layers
+                .into_iter()
+                .enumerate()
+                .map(|(i, layer)| {
+                    let source = vfs.sources()[layer.source].path();
+                    let data = vfs.read_from(layer.source, &layer.path).with_context(|| {
+                        format!("Failed to read {} from {}", layer.path, source.display())
+                    })?;
+                    Ok(ConflictCopy {
+                        source: source.to_path_buf(),
+                        path: layer.path,
+                        size: data.len() as u64,
+                        hash: format!("{:032x}", XxHash3_128::oneshot(&data)),
+                        winner: i == winner,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let status = if copies.iter().all(|c| c.hash == copies[0].hash) {
+                ConflictStatus::Identical
+            } else {
+                ConflictStatus::Different
+            };
+
+            let current = done.fetch_add(1, Ordering::Relaxed) + 1;
+            progress(current, total, &file.path);
+
+            Ok(Conflict {
+                path: file.path.clone(),
+                status,
+                copies,
+            })
+        })
+        .collect()
+}
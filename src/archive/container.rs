@@ -0,0 +1,278 @@
+//! Converting between Bethesda archives and zip/tar containers
+//!
+//! Export streams every decompressed entry into the container with
+//! forward-slash paths, so nothing is unpacked to disk. Import reads a
+//! container entry by entry and hands the files to a callback, usually
+//! `BsaBuilder::add_file` or `Ba2Builder::add_file`.
+
+use anyhow::{bail, Context, Result};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tracing::info;
+
+use super::{ArchiveHandle, PathFilter};
+
+/// Entries decompressed in parallel before being written in order
+const EXPORT_BATCH: usize = 64;
+
+/// zstd level for `.tar.zst`; the zstd default, a good size/speed balance
+const ZSTD_LEVEL: i32 = 3;
+
+/// Container format for `export` and `import`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerFormat {
+    Zip,
+    Tar,
+    /// zstd-compressed tar
+    TarZst,
+}
+
+impl ContainerFormat {
+    /// Get display name for this format
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ContainerFormat::Zip => "Zip",
+            ContainerFormat::Tar => "Tar",
+            ContainerFormat::TarZst => "Tar (zstd)",
+        }
+    }
+
+    /// Short CLI-friendly name
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            ContainerFormat::Zip => "zip",
+            ContainerFormat::Tar => "tar",
+            ContainerFormat::TarZst => "tar.zst",
+        }
+    }
+
+    /// Parse from CLI name (case-insensitive)
+    pub fn from_cli_name(name: &str) -> Option<ContainerFormat> {
+        let lower = name.to_lowercase();
+        ContainerFormat::all()
+            .iter()
+            .find(|f| f.cli_name() == lower)
+            .copied()
+    }
+
+    /// Get all formats
+    pub fn all() -> &'static [ContainerFormat] {
+        &[
+            ContainerFormat::Zip,
+            ContainerFormat::Tar,
+            ContainerFormat::TarZst,
+        ]
+    }
+
+    /// Detect the format from a file name (`.zip`, `.tar`, `.tar.zst` or `.tzst`)
+    pub fn from_path(path: &Path) -> Option<ContainerFormat> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ContainerFormat::Zip)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ContainerFormat::TarZst)
+        } else if name.ends_with(".tar") {
+            Some(ContainerFormat::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Path of an archive entry inside a container: forward slashes, without
+/// empty or `.` components (`./meshes//x.nif` -> `meshes/x.nif`)
+fn container_path(path: &str) -> String {
+    path.replace('\\', "/")
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Writes entries to a container of either kind
+enum ContainerWriter {
+    Zip(Box<zip::ZipWriter<BufWriter<File>>>),
+    Tar(tar::Builder<Box<dyn Write>>),
+}
+
+impl ContainerWriter {
+    fn create(path: &Path, format: ContainerFormat) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create: {}", path.display()))?;
+        let out = BufWriter::new(file);
+        Ok(match format {
+            ContainerFormat::Zip => ContainerWriter::Zip(Box::new(zip::ZipWriter::new(out))),
+            ContainerFormat::Tar => ContainerWriter::Tar(tar::Builder::new(Box::new(out))),
+            ContainerFormat::TarZst => {
+                let encoder = zstd::Encoder::new(out, ZSTD_LEVEL)?.auto_finish();
+                ContainerWriter::Tar(tar::Builder::new(Box::new(encoder)))
+            }
+        })
+    }
+
+    fn add(&mut self, path: &str, data: &[u8], mtime: u64) -> Result<()> {
+        match self {
+            ContainerWriter::Zip(zip) => {
+                let options = zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .large_file(data.len() as u64 >= zip::ZIP64_BYTES_THR);
+                zip.start_file(path, options)?;
+                zip.write_all(data)?;
+            }
+            ContainerWriter::Tar(tar) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(mtime);
+                tar.append_data(&mut header, path, data)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            ContainerWriter::Zip(zip) => {
+                zip.finish()?.flush()?;
+            }
+            ContainerWriter::Tar(tar) => {
+                tar.into_inner()?.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write every entry of `archive` that passes `filter` into a zip or tar.
+/// Calls `progress(done, total, path)` after each entry; returns the number
+/// of entries written.
+pub fn export_archive<F>(
+    archive: &ArchiveHandle,
+    output_path: &Path,
+    format: ContainerFormat,
+    filter: &PathFilter,
+    progress: F,
+) -> Result<usize>
+where
+    F: Fn(usize, usize, &str),
+{
+    let paths: Vec<&str> = archive
+        .entries()?
+        .iter()
+        .map(|e| e.path.as_str())
+        .filter(|p| filter.matches(p))
+        .collect();
+    let total = paths.len();
+
+    // Entries inside the container get the archive's timestamp
+    let mtime = std::fs::metadata(archive.path())
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+
+    let mut writer = ContainerWriter::create(output_path, format)?;
+    let mut done = 0;
+    for batch in paths.chunks(EXPORT_BATCH) {
+        let data: Vec<Vec<u8>> = batch
+            .par_iter()
+            .map(|path| archive.read(path))
+            .collect::<Result<_>>()?;
+        for (path, data) in batch.iter().zip(data) {
+            writer
+                .add(&container_path(path), &data, mtime)
+                .with_context(|| format!("Failed to write {}", path))?;
+            done += 1;
+            progress(done, total, path);
+        }
+    }
+    writer.finish()?;
+
+    info!(
+        "Exported {} files from {} to {}",
+        total,
+        archive.path().display(),
+        output_path.display()
+    );
+    Ok(total)
+}
+
+/// Read every file of a zip or tar that passes `filter`, in container order,
+/// and hand it to `add(path, data)`. Directories and links are skipped.
+/// Returns the number of files read.
+pub fn import_container<F>(
+    input_path: &Path,
+    format: ContainerFormat,
+    filter: &PathFilter,
+    mut add: F,
+) -> Result<usize>
+where
+    F: FnMut(&str, Vec<u8>) -> Result<()>,
+{
+    let file = File::open(input_path)
+        .with_context(|| format!("Failed to open: {}", input_path.display()))?;
+    let reader = BufReader::new(file);
+    let mut count = 0;
+
+    let mut take = |path: &str, data: Vec<u8>| -> Result<()> {
+        let path = container_path(path);
+        if path.is_empty() || !filter.matches(&path) {
+            return Ok(());
+        }
+        if path.split('/').any(|c| c == "..") {
+            bail!("Refusing path outside the archive root: {}", path);
+        }
+        add(&path, data)?;
+        count += 1;
+        Ok(())
+    };
+
+    match format {
+        ContainerFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(reader)
+                .with_context(|| format!("Failed to read zip: {}", input_path.display()))?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i)?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let name = entry.name()?.to_string();
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry
+                    .read_to_end(&mut data)
+                    .with_context(|| format!("Failed to read {}", name))?;
+                take(&name, data)?;
+            }
+        }
+        ContainerFormat::Tar | ContainerFormat::TarZst => {
+            let reader: Box<dyn Read> = if format == ContainerFormat::TarZst {
+                Box::new(zstd::Decoder::new(reader)?)
+            } else {
+                Box::new(reader)
+            };
+            let mut tar = tar::Archive::new(reader);
+            for entry in tar
+                .entries()
+                .with_context(|| format!("Failed to read tar: {}", input_path.display()))?
+            {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().to_string();
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry
+                    .read_to_end(&mut data)
+                    .with_context(|| format!("Failed to read {}", name))?;
+                take(&name, data)?;
+            }
+        }
+    }
+
+    info!("Read {} files from {}", count, input_path.display());
+    Ok(count)
+}
@@ -0,0 +1,497 @@
+//! DDS header inspection
+//!
+//! DX10 BA2s store each texture as its DDS metadata plus the raw mip data,
+//! so a texture must be a 2D texture or cubemap whose size, mip count and
+//! DXGI format fit the BA2 record. `inspect_dds` checks that from the header
+//! alone, so a texture archive build can report every bad file before any
+//! compression work starts. `decode_dds_mip` decodes a mip to RGBA for
+//! previews.
+
+use anyhow::{bail, Context, Result};
+use directxtex::{ScratchImage, DDS_FLAGS, DXGI_FORMAT, TEX_FILTER_FLAGS};
+
+const MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: u32 = 124;
+const PIXEL_FORMAT_SIZE: u32 = 32;
+/// Magic plus `DDS_HEADER`
+const DATA_OFFSET: usize = 128;
+/// `DDS_HEADER_DXT10`
+const DX10_HEADER_LEN: usize = 20;
+
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+
+const DDSD_DEPTH: u32 = 0x80_0000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALLFACES: u32 = 0xFC00;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+const RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+const RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+/// How the pixel data of a format is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    /// Bits per pixel
+    Bits(usize),
+    /// Bytes per 4x4 block
+    Block(usize),
+    /// Bytes per 2x1 pixel pair
+    Pair(usize),
+}
+
+impl Layout {
+    /// Size of one `width` x `height` image
+    fn image_size(self, width: usize, height: usize) -> usize {
+        match self {
+            Layout::Bits(bits) => (width * bits).div_ceil(8) * height,
+            Layout::Block(bytes) => width.div_ceil(4) * height.div_ceil(4) * bytes,
+            Layout::Pair(bytes) => width.div_ceil(2) * bytes * height,
+        }
+    }
+}
+
+/// DXGI formats a BA2 can hold, with their layout
+const DXGI_FORMATS: &[(u32, &str, Layout)] = &[
+    (1, "R32G32B32A32_TYPELESS", Layout::Bits(128)),
+    (2, "R32G32B32A32_FLOAT", Layout::Bits(128)),
+    (3, "R32G32B32A32_UINT", Layout::Bits(128)),
+    (4, "R32G32B32A32_SINT", Layout::Bits(128)),
+    (5, "R32G32B32_TYPELESS", Layout::Bits(96)),
+    (6, "R32G32B32_FLOAT", Layout::Bits(96)),
+    (7, "R32G32B32_UINT", Layout::Bits(96)),
+    (8, "R32G32B32_SINT", Layout::Bits(96)),
+    (9, "R16G16B16A16_TYPELESS", Layout::Bits(64)),
+    (10, "R16G16B16A16_FLOAT", Layout::Bits(64)),
+    (11, "R16G16B16A16_UNORM", Layout::Bits(64)),
+    (12, "R16G16B16A16_UINT", Layout::Bits(64)),
+    (13, "R16G16B16A16_SNORM", Layout::Bits(64)),
+    (14, "R16G16B16A16_SINT", Layout::Bits(64)),
+    (15, "R32G32_TYPELESS", Layout::Bits(64)),
+    (16, "R32G32_FLOAT", Layout::Bits(64)),
+    (17, "R32G32_UINT", Layout::Bits(64)),
+    (18, "R32G32_SINT", Layout::Bits(64)),
+    (19, "R32G8X24_TYPELESS", Layout::Bits(64)),
+    (20, "D32_FLOAT_S8X24_UINT", Layout::Bits(64)),
+    (21, "R32_FLOAT_X8X24_TYPELESS", Layout::Bits(64)),
+    (22, "X32_TYPELESS_G8X24_UINT", Layout::Bits(64)),
+    (23, "R10G10B10A2_TYPELESS", Layout::Bits(32)),
+    (24, "R10G10B10A2_UNORM", Layout::Bits(32)),
+    (25, "R10G10B10A2_UINT", Layout::Bits(32)),
+    (26, "R11G11B10_FLOAT", Layout::Bits(32)),
+    (27, "R8G8B8A8_TYPELESS", Layout::Bits(32)),
+    (28, "R8G8B8A8_UNORM", Layout::Bits(32)),
+    (29, "R8G8B8A8_UNORM_SRGB", Layout::Bits(32)),
+    (30, "R8G8B8A8_UINT", Layout::Bits(32)),
+    (31, "R8G8B8A8_SNORM", Layout::Bits(32)),
+    (32, "R8G8B8A8_SINT", Layout::Bits(32)),
+    (33, "R16G16_TYPELESS", Layout::Bits(32)),
+    (34, "R16G16_FLOAT", Layout::Bits(32)),
+    (35, "R16G16_UNORM", Layout::Bits(32)),
+    (36, "R16G16_UINT", Layout::Bits(32)),
+    (37, "R16G16_SNORM", Layout::Bits(32)),
+    (38, "R16G16_SINT", Layout::Bits(32)),
+    (39, "R32_TYPELESS", Layout::Bits(32)),
+    (40, "D32_FLOAT", Layout::Bits(32)),
+    (41, "R32_FLOAT", Layout::Bits(32)),
+    (42, "R32_UINT", Layout::Bits(32)),
+    (43, "R32_SINT", Layout::Bits(32)),
+    (44, "R24G8_TYPELESS", Layout::Bits(32)),
+    (45, "D24_UNORM_S8_UINT", Layout::Bits(32)),
+    (46, "R24_UNORM_X8_TYPELESS", Layout::Bits(32)),
+    (47, "X24_TYPELESS_G8_UINT", Layout::Bits(32)),
+    (48, "R8G8_TYPELESS", Layout::Bits(16)),
+    (49, "R8G8_UNORM", Layout::Bits(16)),
+    (50, "R8G8_UINT", Layout::Bits(16)),
+    (51, "R8G8_SNORM", Layout::Bits(16)),
+    (52, "R8G8_SINT", Layout::Bits(16)),
+    (53, "R16_TYPELESS", Layout::Bits(16)),
+    (54, "R16_FLOAT", Layout::Bits(16)),
+    (55, "D16_UNORM", Layout::Bits(16)),
+    (56, "R16_UNORM", Layout::Bits(16)),
+    (57, "R16_UINT", Layout::Bits(16)),
+    (58, "R16_SNORM", Layout::Bits(16)),
+    (59, "R16_SINT", Layout::Bits(16)),
+    (60, "R8_TYPELESS", Layout::Bits(8)),
+    (61, "R8_UNORM", Layout::Bits(8)),
+    (62, "R8_UINT", Layout::Bits(8)),
+    (63, "R8_SNORM", Layout::Bits(8)),
+    (64, "R8_SINT", Layout::Bits(8)),
+    (65, "A8_UNORM", Layout::Bits(8)),
+    (66, "R1_UNORM", Layout::Bits(1)),
+    (67, "R9G9B9E5_SHAREDEXP", Layout::Bits(32)),
+    (68, "R8G8_B8G8_UNORM", Layout::Pair(4)),
+    (69, "G8R8_G8B8_UNORM", Layout::Pair(4)),
+    (70, "BC1_TYPELESS", Layout::Block(8)),
+    (71, "BC1_UNORM", Layout::Block(8)),
+    (72, "BC1_UNORM_SRGB", Layout::Block(8)),
+    (73, "BC2_TYPELESS", Layout::Block(16)),
+    (74, "BC2_UNORM", Layout::Block(16)),
+    (75, "BC2_UNORM_SRGB", Layout::Block(16)),
+    (76, "BC3_TYPELESS", Layout::Block(16)),
+    (77, "BC3_UNORM", Layout::Block(16)),
+    (78, "BC3_UNORM_SRGB", Layout::Block(16)),
+    (79, "BC4_TYPELESS", Layout::Block(8)),
+    (80, "BC4_UNORM", Layout::Block(8)),
+    (81, "BC4_SNORM", Layout::Block(8)),
+    (82, "BC5_TYPELESS", Layout::Block(16)),
+    (83, "BC5_UNORM", Layout::Block(16)),
+    (84, "BC5_SNORM", Layout::Block(16)),
+    (85, "B5G6R5_UNORM", Layout::Bits(16)),
+    (86, "B5G5R5A1_UNORM", Layout::Bits(16)),
+    (87, "B8G8R8A8_UNORM", Layout::Bits(32)),
+    (88, "B8G8R8X8_UNORM", Layout::Bits(32)),
+    (89, "R10G10B10_XR_BIAS_A2_UNORM", Layout::Bits(32)),
+    (90, "B8G8R8A8_TYPELESS", Layout::Bits(32)),
+    (91, "B8G8R8A8_UNORM_SRGB", Layout::Bits(32)),
+    (92, "B8G8R8X8_TYPELESS", Layout::Bits(32)),
+    (93, "B8G8R8X8_UNORM_SRGB", Layout::Bits(32)),
+    (94, "BC6H_TYPELESS", Layout::Block(16)),
+    (95, "BC6H_UF16", Layout::Block(16)),
+    (96, "BC6H_SF16", Layout::Block(16)),
+    (97, "BC7_TYPELESS", Layout::Block(16)),
+    (98, "BC7_UNORM", Layout::Block(16)),
+    (99, "BC7_UNORM_SRGB", Layout::Block(16)),
+    (115, "B4G4R4A4_UNORM", Layout::Bits(16)),
+];
+
+fn dxgi_entry(format: u32) -> Option<&'static (u32, &'static str, Layout)> {
+    DXGI_FORMATS.iter().find(|(f, _, _)| *f == format)
+}
+
+/// Name of a `DXGI_FORMAT` value without the `DXGI_FORMAT_` prefix, e.g.
+/// `BC7_UNORM_SRGB`
+pub fn dxgi_format_name(format: u32) -> Option<&'static str> {
+    dxgi_entry(format).map(|(_, name, _)| *name)
+}
+
+/// What a DDS header describes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DdsInfo {
+    pub width: u32,
+    pub height: u32,
+    pub mip_count: u32,
+    /// `DXGI_FORMAT` value; legacy pixel formats are mapped to their DXGI
+    /// equivalent
+    pub dxgi_format: u32,
+    /// Six faces instead of one image
+    pub cubemap: bool,
+    /// Has a `DX10` extended header
+    pub dx10_header: bool,
+}
+
+impl DdsInfo {
+    /// Name of the DXGI format, e.g. `BC1_UNORM`
+    pub fn format_name(&self) -> &'static str {
+        dxgi_format_name(self.dxgi_format).unwrap_or("UNKNOWN")
+    }
+
+    /// sRGB formats are gamma-corrected by the GPU when sampled
+    pub fn is_srgb(&self) -> bool {
+        self.format_name().ends_with("_SRGB")
+    }
+
+    /// Block-compressed (BC1-BC7) formats
+    pub fn is_block_compressed(&self) -> bool {
+        matches!(dxgi_entry(self.dxgi_format), Some((_, _, Layout::Block(_))))
+    }
+
+    /// Size of each mip of one face in the DXGI format, largest first
+    pub fn mip_sizes(&self) -> Vec<usize> {
+        let Some((_, _, layout)) = dxgi_entry(self.dxgi_format) else {
+            return Vec::new();
+        };
+        (0..self.mip_count)
+            .map(|mip| {
+                let width = (self.width >> mip).max(1) as usize;
+                let height = (self.height >> mip).max(1) as usize;
+                layout.image_size(width, height)
+            })
+            .collect()
+    }
+
+    /// Size of the `width` x `height` image in this texture's format
+    pub fn image_size(&self, width: usize, height: usize) -> usize {
+        dxgi_entry(self.dxgi_format)
+            .map(|(_, _, layout)| layout.image_size(width, height))
+            .unwrap_or(0)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Map a pre-DX10 pixel format to a DXGI format and the layout of the data
+/// as stored in the file
+fn legacy_format(data: &[u8]) -> Result<(u32, Layout)> {
+    let flags = read_u32(data, 80);
+    let fourcc = &data[84..88];
+    let bits = read_u32(data, 88) as usize;
+    let masks = [
+        read_u32(data, 92),
+        read_u32(data, 96),
+        read_u32(data, 100),
+        read_u32(data, 104),
+    ];
+
+    if flags & DDPF_FOURCC != 0 {
+        let format = match fourcc {
+            b"DXT1" => (71, Layout::Block(8)),
+            b"DXT2" | b"DXT3" => (74, Layout::Block(16)),
+            b"DXT4" | b"DXT5" => (77, Layout::Block(16)),
+            b"ATI1" | b"BC4U" => (80, Layout::Block(8)),
+            b"BC4S" => (81, Layout::Block(8)),
+            b"ATI2" | b"BC5U" => (83, Layout::Block(16)),
+            b"BC5S" => (84, Layout::Block(16)),
+            b"RGBG" => (68, Layout::Pair(4)),
+            b"GRGB" => (69, Layout::Pair(4)),
+            // D3DFMT values stored as a FourCC
+            _ => match u32::from_le_bytes(fourcc.try_into().unwrap()) {
+                36 => (11, Layout::Bits(64)),
+                110 => (13, Layout::Bits(64)),
+                111 => (54, Layout::Bits(16)),
+                112 => (34, Layout::Bits(32)),
+                113 => (10, Layout::Bits(64)),
+                114 => (41, Layout::Bits(32)),
+                115 => (16, Layout::Bits(64)),
+                116 => (2, Layout::Bits(128)),
+                _ => bail!(
+                    "unsupported FourCC {:?}",
+                    String::from_utf8_lossy(fourcc).trim_end_matches('\0')
+                ),
+            },
+        };
+        return Ok(format);
+    }
+
+    let format = if flags & DDPF_RGB != 0 {
+        match (bits, masks) {
+            (32, [0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000]) => Some(87),
+            (32, [0xFF_0000, 0xFF00, 0xFF, 0]) => Some(88),
+            (32, [0xFF, 0xFF00, 0xFF_0000, _]) => Some(28),
+            (32, [0xFFFF, 0xFFFF_0000, 0, 0]) => Some(35),
+            (32, [0x3FF, 0xF_FC00, 0x3FF0_0000, 0xC000_0000])
+            | (32, [0x3FF0_0000, 0xF_FC00, 0x3FF, 0xC000_0000]) => Some(24),
+            // Expanded to 32 bits when read
+            (24, [0xFF_0000, 0xFF00, 0xFF, 0]) => Some(28),
+            (16, [0xF800, 0x7E0, 0x1F, 0]) => Some(85),
+            (16, [0x7C00, 0x3E0, 0x1F, _]) => Some(86),
+            (16, [0xF00, 0xF0, 0xF, 0xF000]) => Some(115),
+            _ => None,
+        }
+    } else if flags & DDPF_LUMINANCE != 0 {
+        match (bits, masks) {
+            (8, [0xFF, _, _, 0]) => Some(61),
+            (16, [0xFFFF, _, _, 0]) => Some(56),
+            (16, [0xFF, _, _, 0xFF00]) => Some(49),
+            _ => None,
+        }
+    } else if flags & DDPF_ALPHA != 0 && bits == 8 {
+        Some(65)
+    } else {
+        None
+    };
+
+    match format {
+        Some(format) => Ok((format, Layout::Bits(bits))),
+        None => bail!(
+            "unsupported pixel format ({}-bit, flags {:#x}, masks {:#x}/{:#x}/{:#x}/{:#x})",
+            bits,
+            flags,
+            masks[0],
+            masks[1],
+            masks[2],
+            masks[3]
+        ),
+    }
+}
+
+/// Parse and check a DDS header for packing into a DX10 BA2
+///
+/// The error says why the texture can't be packed: a bad magic or header
+/// size, an unsupported pixel format, a volume texture or texture array, a
+/// size or mip count out of range, or pixel data shorter than the header
+/// promises.
+pub fn inspect_dds(data: &[u8]) -> Result<DdsInfo> {
+    if data.len() < 4 || &data[..4] != MAGIC {
+        bail!("not a DDS file (bad magic)");
+    }
+    if data.len() < DATA_OFFSET {
+        bail!(
+            "truncated header ({} bytes, expected at least {})",
+            data.len(),
+            DATA_OFFSET
+        );
+    }
+    let header_size = read_u32(data, 4);
+    if header_size != HEADER_SIZE {
+        bail!("bad header size {} (expected {})", header_size, HEADER_SIZE);
+    }
+    let pixel_format_size = read_u32(data, 76);
+    if pixel_format_size != PIXEL_FORMAT_SIZE {
+        bail!(
+            "bad pixel format size {} (expected {})",
+            pixel_format_size,
+            PIXEL_FORMAT_SIZE
+        );
+    }
+
+    let flags = read_u32(data, 8);
+    let height = read_u32(data, 12);
+    let width = read_u32(data, 16);
+    let depth = read_u32(data, 24);
+    let mip_count = read_u32(data, 28).max(1);
+    let caps2 = read_u32(data, 112);
+
+    let dx10_header = read_u32(data, 80) & DDPF_FOURCC != 0 && &data[84..88] == b"DX10";
+    let (dxgi_format, layout, cubemap, data_offset) = if dx10_header {
+        if data.len() < DATA_OFFSET + DX10_HEADER_LEN {
+            bail!("truncated DX10 header");
+        }
+        let format = read_u32(data, DATA_OFFSET);
+        let dimension = read_u32(data, DATA_OFFSET + 4);
+        let misc_flags = read_u32(data, DATA_OFFSET + 8);
+        let array_size = read_u32(data, DATA_OFFSET + 12);
+        if dimension != RESOURCE_DIMENSION_TEXTURE2D {
+            bail!(
+                "resource dimension {} is not a 2D texture; BA2 only stores 2D textures and cubemaps",
+                dimension
+            );
+        }
+        if array_size != 1 {
+            bail!(
+                "texture arrays are not supported (array size {})",
+                array_size
+            );
+        }
+        let Some((_, _, layout)) = dxgi_entry(format) else {
+            bail!("unsupported DXGI format {}", format);
+        };
+        (
+            format,
+            *layout,
+            misc_flags & RESOURCE_MISC_TEXTURECUBE != 0,
+            DATA_OFFSET + DX10_HEADER_LEN,
+        )
+    } else {
+        if caps2 & DDSCAPS2_VOLUME != 0 || (flags & DDSD_DEPTH != 0 && depth > 1) {
+            bail!("volume textures are not supported");
+        }
+        let cubemap = caps2 & DDSCAPS2_CUBEMAP != 0;
+        if cubemap && caps2 & DDSCAPS2_CUBEMAP_ALLFACES != DDSCAPS2_CUBEMAP_ALLFACES {
+            bail!("cubemap is missing faces; all six are required");
+        }
+        let (format, layout) = legacy_format(data)?;
+        (format, layout, cubemap, DATA_OFFSET)
+    };
+
+    if width == 0 || height == 0 {
+        bail!("zero size ({}x{})", width, height);
+    }
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        bail!(
+            "{}x{} is larger than a BA2 can store ({} pixels per side)",
+            width,
+            height,
+            u16::MAX
+        );
+    }
+    let max_mips = 32 - width.max(height).leading_zeros();
+    if mip_count > max_mips {
+        bail!(
+            "{} mips, but {}x{} only has {}",
+            mip_count,
+            width,
+            height,
+            max_mips
+        );
+    }
+
+    let faces = if cubemap { 6 } else { 1 };
+    let expected: usize = faces
+        * (0..mip_count)
+            .map(|mip| {
+                let w = (width >> mip).max(1) as usize;
+                let h = (height >> mip).max(1) as usize;
+                layout.image_size(w, h)
+            })
+            .sum::<usize>();
+    let actual = data.len() - data_offset;
+    if actual < expected {
+        bail!(
+            "truncated pixel data ({} bytes, expected {})",
+            actual,
+            expected
+        );
+    }
+
+    Ok(DdsInfo {
+        width,
+        height,
+        mip_count,
+        dxgi_format,
+        cubemap,
+        dx10_header,
+    })
+}
+
+/// One mip of a texture decoded to 8-bit RGBA
+#[derive(Debug, Clone)]
+pub struct DecodedMip {
+    pub width: u32,
+    pub height: u32,
+    /// `width * height` pixels, 4 bytes each, rows top to bottom. sRGB
+    /// textures stay sRGB-encoded, ready for display.
+    pub rgba: Vec<u8>,
+}
+
+/// Decode mip `mip` of a DDS (the first face of a cubemap) to RGBA.
+///
+/// Everything runs on the CPU: block-compressed formats (BC1-BC7) are
+/// decompressed in software and other formats converted.
+pub fn decode_dds_mip(data: &[u8], mip: u32) -> Result<DecodedMip> {
+    let info = inspect_dds(data)?;
+    if mip >= info.mip_count {
+        bail!("mip {} out of range ({} mips)", mip, info.mip_count);
+    }
+    let scratch = ScratchImage::load_dds(data, DDS_FLAGS::DDS_FLAGS_NONE, None, None)
+        .context("Failed to load DDS")?;
+    let image = scratch
+        .image(mip as usize, 0, 0)
+        .with_context(|| format!("DDS has no mip {}", mip))?;
+
+    let target = if info.is_srgb() {
+        DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+    } else {
+        DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM
+    };
+    let converted;
+    let (owner, image) = if image.format == target {
+        (&scratch, image)
+    } else {
+        converted = if image.format.is_compressed() {
+            image.decompress(target)
+        } else {
+            image.convert(target, TEX_FILTER_FLAGS::TEX_FILTER_DEFAULT, 0.5)
+        }
+        .with_context(|| format!("Failed to decode {}", info.format_name()))?;
+        (&converted, &converted.images()[0])
+    };
+
+    // Copy out row by row, the pitch may be padded
+    let start = image.pixels as usize - owner.pixels().as_ptr() as usize;
+    let pixels = &owner.pixels()[start..start + image.slice_pitch];
+    let row_len = image.width * 4;
+    let mut rgba = Vec::with_capacity(row_len * image.height);
+    for row in pixels.chunks(image.row_pitch).take(image.height) {
+        rgba.extend_from_slice(&row[..row_len]);
+    }
+    Ok(DecodedMip {
+        width: image.width as u32,
+        height: image.height as u32,
+        rgba,
+    })
+}
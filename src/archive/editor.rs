@@ -0,0 +1,376 @@
+//! In-place archive editing
+//!
+//! Loads an archive with its own mapping, adds, replaces, removes or renames
+//! entries, and writes a new archive. Entries that aren't touched keep their
+//! stored bytes, so nothing is decompressed or recompressed on save.
+
+use anyhow::{bail, Context, Result};
+use ba2::prelude::*;
+use ba2::{fo4, tes3, tes4, CompressionResult, Copied};
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use super::filter::normalize_path;
+use super::reader::split_path;
+use super::{detect_format, ArchiveFormat};
+
+/// A parsed archive in one of the three layouts
+enum Contents {
+    Tes3(tes3::Archive<'static>),
+    Tes4(tes4::Archive<'static>, tes4::ArchiveOptions),
+    Fo4(fo4::Archive<'static>, fo4::ArchiveOptions),
+}
+
+/// A new entry, already compressed to match the archive
+enum NewFile {
+    Tes3(tes3::File<'static>),
+    Tes4(tes4::File<'static>),
+    Fo4(fo4::File<'static>),
+}
+
+/// An archive loaded for editing.
+/// Paths may use either slash convention and any case.
+pub struct ArchiveEditor {
+    path: PathBuf,
+    contents: Contents,
+    /// Whether new BA2 entries are compressed (matches the existing entries)
+    compress_ba2: bool,
+    changes: usize,
+}
+
+impl ArchiveEditor {
+    /// Load an archive for editing
+    pub fn open(path: &Path) -> Result<Self> {
+        let contents = match detect_format(path) {
+            Some(ArchiveFormat::Tes3Bsa) => Contents::Tes3(
+                tes3::Archive::read(path)
+                    .with_context(|| format!("Failed to open TES3 BSA: {}", path.display()))?,
+            ),
+            Some(ArchiveFormat::Bsa) => {
+                let (archive, options) = tes4::Archive::read(path)
+                    .with_context(|| format!("Failed to open BSA: {}", path.display()))?;
+                Contents::Tes4(archive, options)
+            }
+            Some(ArchiveFormat::Ba2) => {
+                let (archive, options) = fo4::Archive::read(path)
+                    .with_context(|| format!("Failed to open BA2: {}", path.display()))?;
+                Contents::Fo4(archive, options)
+            }
+            None => bail!("Unknown archive format: {}", path.display()),
+        };
+
+        let compress_ba2 = match &contents {
+            Contents::Fo4(archive, _) => {
+                archive.is_empty()
+                    || archive
+                        .values()
+                        .flat_map(|file| file.iter())
+                        .any(|chunk| chunk.is_compressed())
+            }
+            _ => false,
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            contents,
+            compress_ba2,
+            changes: 0,
+        })
+    }
+
+    /// Path of the archive being edited
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of edits made since opening
+    pub fn changes(&self) -> usize {
+        self.changes
+    }
+
+    /// Check if the archive contains a file
+    pub fn contains(&self, file_path: &str) -> bool {
+        match &self.contents {
+            Contents::Tes3(archive) => tes3_key(archive, file_path).is_some(),
+            Contents::Tes4(archive, _) => tes4_key(archive, file_path).is_some(),
+            Contents::Fo4(archive, _) => fo4_key(archive, file_path).is_some(),
+        }
+    }
+
+    /// Add a new file, failing if the path is already taken
+    pub fn add(&mut self, file_path: &str, data: Vec<u8>) -> Result<()> {
+        if self.contains(file_path) {
+            bail!("File already exists in archive: {}", file_path);
+        }
+        let file = self.prepare(file_path, data)?;
+        self.place(file_path, file);
+        self.changes += 1;
+        Ok(())
+    }
+
+    /// Replace the contents of an existing file
+    pub fn replace(&mut self, file_path: &str, data: Vec<u8>) -> Result<()> {
+        if !self.contains(file_path) {
+            bail!("File not found in archive: {}", file_path);
+        }
+        let file = self.prepare(file_path, data)?;
+        // Keep the stored name's spelling
+        let stored = self.take(file_path)?;
+        self.place(&stored, file);
+        self.changes += 1;
+        Ok(())
+    }
+
+    /// Remove a file
+    pub fn remove(&mut self, file_path: &str) -> Result<()> {
+        self.take(file_path)?;
+        self.changes += 1;
+        Ok(())
+    }
+
+    /// Move a file to a new path, keeping its stored bytes
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        if normalize_path(from) != normalize_path(to) && self.contains(to) {
+            bail!("File already exists in archive: {}", to);
+        }
+
+        match &mut self.contents {
+            Contents::Tes3(archive) => {
+                let Some(file) = tes3_key(archive, from).and_then(|hash| archive.remove(&hash))
+                else {
+                    bail!("File not found in archive: {}", from);
+                };
+                archive.insert(tes3::ArchiveKey::from(to.replace('/', "\\")), file);
+            }
+            Contents::Tes4(archive, _) => {
+                let Some(file) = tes4_key(archive, from)
+                    .and_then(|(dir_hash, file_hash)| remove_tes4(archive, &dir_hash, &file_hash))
+                else {
+                    bail!("File not found in archive: {}", from);
+                };
+                insert_tes4(archive, to, file);
+            }
+            Contents::Fo4(archive, _) => {
+                let Some(file) = fo4_key(archive, from).and_then(|hash| archive.remove(&hash))
+                else {
+                    bail!("File not found in archive: {}", from);
+                };
+                archive.insert(fo4::ArchiveKey::from(to.replace('\\', "/")), file);
+            }
+        }
+
+        self.changes += 1;
+        Ok(())
+    }
+
+    /// Write the edited archive. Writing over the opened archive is safe: the
+    /// new archive goes to a temporary file that replaces the original once
+    /// the old one is closed.
+    pub fn save(self, output_path: &Path) -> Result<()> {
+        if let Some(parent) = output_path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let file_name = output_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let temp_path = output_path.with_file_name(format!(".{}.tmp", file_name));
+
+        let result = self.write(&temp_path);
+        // Close the original before replacing it, mapped files can't be
+        // replaced on Windows
+        drop(self);
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        fs::rename(&temp_path, output_path)
+            .with_context(|| format!("Failed to replace: {}", output_path.display()))?;
+        info!("Saved edited archive: {}", output_path.display());
+        Ok(())
+    }
+
+    fn write(&self, output_path: &Path) -> Result<()> {
+        let file = fs::File::create(output_path)
+            .with_context(|| format!("Failed to create: {}", output_path.display()))?;
+        let mut writer = BufWriter::new(file);
+
+        let context = || format!("Failed to write archive: {}", output_path.display());
+        match &self.contents {
+            Contents::Tes3(archive) => archive.write(&mut writer).with_context(context)?,
+            Contents::Tes4(archive, options) => {
+                archive.write(&mut writer, options).with_context(context)?
+            }
+            Contents::Fo4(archive, options) => {
+                archive.write(&mut writer, options).with_context(context)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove a file and return its stored path
+    fn take(&mut self, file_path: &str) -> Result<String> {
+        let removed = match &mut self.contents {
+            Contents::Tes3(archive) => tes3_key(archive, file_path)
+                .and_then(|hash| archive.remove_entry(&hash))
+                .map(|(key, _)| key.name().to_string()),
+            Contents::Tes4(archive, _) => {
+                tes4_key(archive, file_path).and_then(|(dir_hash, file_hash)| {
+                    let dir_name = archive.get_key_value(&dir_hash)?.0.name().to_string();
+                    let directory = archive.get_mut(&dir_hash)?;
+                    let (key, _) = directory.remove_entry(&file_hash)?;
+                    if directory.is_empty() {
+                        archive.remove(&dir_hash);
+                    }
+                    Some(format!("{}\\{}", dir_name, key.name()))
+                })
+            }
+            Contents::Fo4(archive, _) => fo4_key(archive, file_path)
+                .and_then(|hash| archive.remove_entry(&hash))
+                .map(|(key, _)| key.name().to_string()),
+        };
+
+        match removed {
+            Some(name) => {
+                // Archives without strings have no stored names
+                if name.trim_matches('\\').is_empty() {
+                    Ok(file_path.to_string())
+                } else {
+                    Ok(name)
+                }
+            }
+            None => bail!("File not found in archive: {}", file_path),
+        }
+    }
+
+    /// Build an entry for `data`, compressing it to match the archive
+    fn prepare(&self, file_path: &str, data: Vec<u8>) -> Result<NewFile> {
+        let file = match &self.contents {
+            Contents::Tes3(_) => NewFile::Tes3(tes3::File::from(data.into_boxed_slice())),
+            Contents::Tes4(_, options) => {
+                let file = tes4::File::from_decompressed(data.into_boxed_slice());
+                if options.flags().contains(tes4::ArchiveFlags::COMPRESSED) {
+                    let compression_options: tes4::FileCompressionOptions = options.into();
+                    let file = file
+                        .compress(&compression_options)
+                        .with_context(|| format!("Failed to compress: {}", file_path))?;
+                    NewFile::Tes4(file)
+                } else {
+                    NewFile::Tes4(file)
+                }
+            }
+            Contents::Fo4(_, options) => {
+                let read_options = fo4::FileReadOptionsBuilder::from(options)
+                    .compression_result(if self.compress_ba2 {
+                        CompressionResult::Compressed
+                    } else {
+                        CompressionResult::Decompressed
+                    })
+                    .build();
+                let file = fo4::File::read(Copied(&data), &read_options)
+                    .with_context(|| format!("Failed to read file for BA2: {}", file_path))?;
+                NewFile::Fo4(file)
+            }
+        };
+        Ok(file)
+    }
+
+    /// Insert an entry built by `prepare`
+    fn place(&mut self, file_path: &str, file: NewFile) {
+        match (&mut self.contents, file) {
+            (Contents::Tes3(archive), NewFile::Tes3(file)) => {
+                archive.insert(tes3::ArchiveKey::from(file_path.replace('/', "\\")), file);
+            }
+            (Contents::Tes4(archive, _), NewFile::Tes4(file)) => {
+                insert_tes4(archive, file_path, file);
+            }
+            (Contents::Fo4(archive, _), NewFile::Fo4(file)) => {
+                archive.insert(fo4::ArchiveKey::from(file_path.replace('\\', "/")), file);
+            }
+            _ => unreachable!("entry prepared for a different archive format"),
+        }
+    }
+}
+
+/// Stored hash of a TES3 file, by name hash or by scanning names
+fn tes3_key(archive: &tes3::Archive, file_path: &str) -> Option<tes3::FileHash> {
+    let key = tes3::ArchiveKey::from(file_path.replace('/', "\\"));
+    if archive.get(&key).is_some() {
+        return Some(*key.hash());
+    }
+    let wanted = normalize_path(file_path);
+    archive
+        .keys()
+        .find(|k| normalize_path(&k.name().to_string()) == wanted)
+        .map(|k| *k.hash())
+}
+
+/// Stored hashes of a TES4 file, by name hash or by scanning names
+fn tes4_key(
+    archive: &tes4::Archive,
+    file_path: &str,
+) -> Option<(tes4::DirectoryHash, tes4::FileHash)> {
+    let (dir_name, file_name) = split_path(file_path);
+    let dir_key = tes4::ArchiveKey::from(dir_name);
+    let file_key = tes4::DirectoryKey::from(file_name);
+    if let Some(directory) = archive.get(&dir_key) {
+        if directory.get(&file_key).is_some() {
+            return Some((*dir_key.hash(), *file_key.hash()));
+        }
+    }
+
+    let wanted = normalize_path(file_path);
+    archive.iter().find_map(|(dir_key, directory)| {
+        directory.keys().find_map(|file_key| {
+            let path = format!("{}\\{}", dir_key.name(), file_key.name());
+            (normalize_path(&path) == wanted).then(|| (*dir_key.hash(), *file_key.hash()))
+        })
+    })
+}
+
+/// Stored hash of a BA2 file, by name hash or by scanning names
+fn fo4_key(archive: &fo4::Archive, file_path: &str) -> Option<fo4::FileHash> {
+    let key = fo4::ArchiveKey::from(file_path.replace('\\', "/"));
+    if archive.get(&key).is_some() {
+        return Some(*key.hash());
+    }
+    let wanted = normalize_path(file_path);
+    archive
+        .keys()
+        .find(|k| normalize_path(&k.name().to_string()) == wanted)
+        .map(|k| *k.hash())
+}
+
+fn remove_tes4(
+    archive: &mut tes4::Archive<'static>,
+    dir_hash: &tes4::DirectoryHash,
+    file_hash: &tes4::FileHash,
+) -> Option<tes4::File<'static>> {
+    let directory = archive.get_mut(dir_hash)?;
+    let file = directory.remove(file_hash);
+    if directory.is_empty() {
+        archive.remove(dir_hash);
+    }
+    file
+}
+
+fn insert_tes4(archive: &mut tes4::Archive<'static>, file_path: &str, file: tes4::File<'static>) {
+    let (dir_name, file_name) = split_path(file_path);
+    let dir_key = tes4::ArchiveKey::from(dir_name);
+    match archive.get_mut(&dir_key) {
+        Some(directory) => {
+            directory.insert(tes4::DirectoryKey::from(file_name), file);
+        }
+        None => {
+            let mut directory = tes4::Directory::default();
+            directory.insert(tes4::DirectoryKey::from(file_name), file);
+            archive.insert(dir_key, directory);
+        }
+    }
+}
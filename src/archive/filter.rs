@@ -0,0 +1,123 @@
+//! Include/exclude filtering of archive paths
+//!
+//! Paths are normalized to lowercase with forward slashes before matching,
+//! so `Meshes\Actors\Bear.nif` and `meshes/actors/bear.nif` are the same path.
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+
+/// A single include or exclude rule
+#[derive(Debug, Clone)]
+enum Pattern {
+    /// Glob matched against the whole path
+    Glob(GlobMatcher),
+    /// Glob without a slash, matched against the file name only
+    NameGlob(GlobMatcher),
+    /// Regex searched anywhere in the path
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn glob(pattern: &str) -> Result<Self> {
+        let normalized = normalize_path(pattern);
+        let matcher = GlobBuilder::new(&normalized)
+            .case_insensitive(true)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid glob pattern: {}", pattern))?
+            .compile_matcher();
+
+        if normalized.contains('/') {
+            Ok(Pattern::Glob(matcher))
+        } else {
+            Ok(Pattern::NameGlob(matcher))
+        }
+    }
+
+    fn regex(pattern: &str) -> Result<Self> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .with_context(|| format!("Invalid regex: {}", pattern))?;
+        Ok(Pattern::Regex(regex))
+    }
+
+    fn is_match(&self, normalized: &str) -> bool {
+        match self {
+            Pattern::Glob(matcher) => matcher.is_match(normalized),
+            Pattern::NameGlob(matcher) => {
+                let name = normalized.rsplit('/').next().unwrap_or(normalized);
+                matcher.is_match(name)
+            }
+            Pattern::Regex(regex) => regex.is_match(normalized),
+        }
+    }
+}
+
+/// Include/exclude rules for archive paths.
+/// A path passes when it matches any include rule (or there are none)
+/// and matches no exclude rule.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl PathFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an include glob (`*`, `?`, `[...]`, `**`)
+    pub fn include_glob(&mut self, pattern: &str) -> Result<()> {
+        self.include.push(Pattern::glob(pattern)?);
+        Ok(())
+    }
+
+    /// Add an exclude glob
+    pub fn exclude_glob(&mut self, pattern: &str) -> Result<()> {
+        self.exclude.push(Pattern::glob(pattern)?);
+        Ok(())
+    }
+
+    /// Add an include regex
+    pub fn include_regex(&mut self, pattern: &str) -> Result<()> {
+        self.include.push(Pattern::regex(pattern)?);
+        Ok(())
+    }
+
+    /// Add an exclude regex
+    pub fn exclude_regex(&mut self, pattern: &str) -> Result<()> {
+        self.exclude.push(Pattern::regex(pattern)?);
+        Ok(())
+    }
+
+    /// Check if no rules are set
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Check if an archive path passes the filter
+    pub fn matches(&self, path: &str) -> bool {
+        let normalized = normalize_path(path);
+        let included =
+            self.include.is_empty() || self.include.iter().any(|p| p.is_match(&normalized));
+        included && !self.exclude.iter().any(|p| p.is_match(&normalized))
+    }
+
+    /// Keep only the paths that pass the filter
+    pub fn apply(&self, paths: Vec<String>) -> Vec<String> {
+        if self.is_empty() {
+            return paths;
+        }
+        paths.into_iter().filter(|p| self.matches(p)).collect()
+    }
+}
+
+/// Lowercase, forward slashes, no leading slash
+pub fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
+        .trim_start_matches('/')
+        .to_lowercase()
+}
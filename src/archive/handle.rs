@@ -0,0 +1,208 @@
+//! Unified handle over TES3, TES4 and BA2 archives
+//!
+//! [`open_archive`] parses an archive once and returns an [`OpenArchive`]
+//! trait object. Entries, single reads and batch reads all reuse that parse,
+//! so callers that read repeatedly (like the GUI) don't re-open the file.
+
+use anyhow::{bail, Result};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::ba2_reader::Ba2Archive;
+use super::reader::BsaArchive;
+use super::tes3_reader::Tes3Archive;
+use super::{detect_format, ArchiveFileEntry, ArchiveFormat, ArchiveInfo, GameVersion};
+
+/// Header options of an open archive, as read by the `ba2` crate
+#[derive(Debug, Clone, Copy)]
+pub enum FormatOptions {
+    /// TES3 BSAs have no header options
+    Tes3,
+    Tes4(ba2::tes4::ArchiveOptions),
+    Fo4(ba2::fo4::ArchiveOptions),
+}
+
+impl FormatOptions {
+    /// Best guess at the game these options were written for
+    pub fn game_version(&self) -> GameVersion {
+        match self {
+            FormatOptions::Tes3 => GameVersion::Morrowind,
+            FormatOptions::Tes4(options) => match options.version() {
+                ba2::tes4::Version::v103 => GameVersion::Oblivion,
+                ba2::tes4::Version::v104 => GameVersion::Fallout3, // Default for v104
+                ba2::tes4::Version::v105 => GameVersion::SkyrimSE,
+            },
+            FormatOptions::Fo4(options) => match options.version() {
+                ba2::fo4::Version::v2 => GameVersion::StarfieldV2,
+                ba2::fo4::Version::v3 => GameVersion::StarfieldV3,
+                ba2::fo4::Version::v7 => GameVersion::Fallout4NGv7,
+                ba2::fo4::Version::v8 => GameVersion::Fallout4NGv8,
+                _ => GameVersion::Fallout4Fo76,
+            },
+        }
+    }
+}
+
+/// Callback for batch reads: archive path as stored, decompressed data
+pub type BatchCallback<'a> = dyn Fn(&str, Vec<u8>) -> Result<()> + Send + Sync + 'a;
+
+/// An archive that has been opened and parsed once.
+/// Every method works the same regardless of the underlying format; paths
+/// may use either slash convention and any case.
+pub trait OpenArchive: Send + Sync {
+    /// Path of the archive on disk
+    fn path(&self) -> &Path;
+
+    /// Archive format
+    fn format(&self) -> ArchiveFormat;
+
+    /// Header options read from the archive
+    fn options(&self) -> FormatOptions;
+
+    /// All entries in the archive, listed on first use
+    fn entries(&self) -> Result<&[ArchiveFileEntry]>;
+
+    /// Decompress a single file into `out`
+    fn read_into(&self, file_path: &str, out: &mut dyn Write) -> Result<()>;
+
+    /// Read and decompress a single file
+    fn read(&self, file_path: &str) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.read_into(file_path, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Read the first `len` bytes of a file (all of it when shorter),
+    /// decompressing no more than the format requires
+    fn read_prefix(&self, file_path: &str, len: usize) -> Result<Vec<u8>> {
+        let mut data = self.read(file_path)?;
+        data.truncate(len);
+        Ok(data)
+    }
+
+    /// Read and decompress many files in parallel.
+    /// `wanted_files` should contain the original paths (as returned by `entries`).
+    /// Returns the number of files successfully extracted.
+    fn read_batch(&self, wanted_files: &[String], callback: &BatchCallback) -> Result<usize>;
+}
+
+/// Detect the format of an archive and open it
+pub fn open_archive(path: &Path) -> Result<Box<dyn OpenArchive>> {
+    match detect_format(path) {
+        Some(ArchiveFormat::Tes3Bsa) => Ok(Box::new(Tes3Archive::open(path)?)),
+        Some(ArchiveFormat::Bsa) => Ok(Box::new(BsaArchive::open(path)?)),
+        Some(ArchiveFormat::Ba2) => Ok(Box::new(Ba2Archive::open(path)?)),
+        None => bail!("Unknown archive format: {}", path.display()),
+    }
+}
+
+/// A shareable open archive.
+/// Cloning is cheap; all clones read from the same parsed archive.
+#[derive(Clone)]
+pub struct ArchiveHandle {
+    archive: Arc<dyn OpenArchive>,
+}
+
+impl ArchiveHandle {
+    /// Detect the format of an archive and open it
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            archive: open_archive(path)?.into(),
+        })
+    }
+
+    /// Path of the archive on disk
+    pub fn path(&self) -> &Path {
+        self.archive.path()
+    }
+
+    /// Detected archive format
+    pub fn format(&self) -> ArchiveFormat {
+        self.archive.format()
+    }
+
+    /// Header options read from the archive
+    pub fn options(&self) -> FormatOptions {
+        self.archive.options()
+    }
+
+    /// Best guess at the game this archive was made for
+    pub fn game_version(&self) -> Option<GameVersion> {
+        Some(self.options().game_version())
+    }
+
+    /// Archive-level information and totals
+    pub fn info(&self) -> Result<ArchiveInfo> {
+        let entries = self.entries()?;
+        let mut info = ArchiveInfo {
+            format: "tes3",
+            version: 0x100,
+            ba2_format: None,
+            flags: None,
+            types: None,
+            game: self.game_version().map(|v| v.cli_name()),
+            file_count: entries.len(),
+            total_size: entries.iter().map(|e| e.size).sum(),
+            total_packed_size: entries.iter().map(|e| e.packed_size).sum(),
+        };
+
+        match self.options() {
+            FormatOptions::Tes3 => {}
+            FormatOptions::Tes4(options) => {
+                info.format = "tes4";
+                info.version = options.version() as u32;
+                info.flags = Some(options.flags().bits());
+                info.types = Some(options.types().bits().into());
+            }
+            FormatOptions::Fo4(options) => {
+                info.format = "fo4";
+                info.version = options.version() as u32;
+                info.ba2_format = Some(match options.format() {
+                    ba2::fo4::Format::GNRL => "GNRL",
+                    ba2::fo4::Format::DX10 => "DX10",
+                    ba2::fo4::Format::GNMF => "GNMF",
+                });
+            }
+        }
+
+        Ok(info)
+    }
+
+    /// All entries in the archive
+    pub fn entries(&self) -> Result<&[ArchiveFileEntry]> {
+        self.archive.entries()
+    }
+
+    /// Read and decompress a single file (either slash convention, any case)
+    pub fn read(&self, file_path: &str) -> Result<Vec<u8>> {
+        self.archive.read(file_path)
+    }
+
+    /// Decompress a single file into a writer
+    pub fn read_into(&self, file_path: &str, out: &mut dyn Write) -> Result<()> {
+        self.archive.read_into(file_path, out)
+    }
+
+    /// Read the start of a file, see [`OpenArchive::read_prefix`]
+    pub fn read_prefix(&self, file_path: &str, len: usize) -> Result<Vec<u8>> {
+        self.archive.read_prefix(file_path, len)
+    }
+
+    /// Read and decompress many files in parallel, see [`OpenArchive::read_batch`]
+    pub fn read_batch<F>(&self, wanted_files: &[String], callback: F) -> Result<usize>
+    where
+        F: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
+    {
+        self.archive.read_batch(wanted_files, &callback)
+    }
+}
+
+impl std::fmt::Debug for ArchiveHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArchiveHandle")
+            .field("path", &self.path())
+            .field("format", &self.format())
+            .finish()
+    }
+}
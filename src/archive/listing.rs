@@ -0,0 +1,474 @@
+//! Machine-readable output for archive listings
+//!
+//! The schema below is stable: fields are only ever added at the end, never
+//! renamed, reordered or removed.
+//!
+//! Entries (`list`), see [`ArchiveFileEntry`]:
+//! - JSON: an array of objects with the keys `path`, `size`, `packed_size`,
+//!   `compressed`, `hash`, `offset`, `texture` and `audio`. `texture` is
+//!   `null` or an object with `width`, `height`, `mip_count`, `dxgi_format`,
+//!   `chunks` and `header_size`. `audio` is `null` (always, unless `list
+//!   --audio`) or an object with `codec`, `format_tag`, `sample_rate`,
+//!   `channels`, `bits_per_sample`, `duration_ms` and `lip_size` (`null`
+//!   except for `.fuz` files).
+//! - CSV/TSV: a header row, then one row per entry with the columns
+//!   `path,size,packed_size,compressed,hash,offset,width,height,mip_count,dxgi_format,chunks,codec,sample_rate,channels,duration_ms`.
+//!   Texture columns are empty for entries without a texture header, audio
+//!   columns for entries without audio info. `compressed` is `true` or
+//!   `false`.
+//!
+//! Archive info (`info`), see [`ArchiveInfo`]:
+//! - JSON: one object with the keys `format`, `version`, `ba2_format`,
+//!   `flags`, `types`, `game`, `file_count`, `total_size` and
+//!   `total_packed_size`. Optional values are `null`.
+//! - CSV/TSV: a header row with those keys and a single data row.
+//!
+//! Conflicts (`conflicts`), see [`Conflict`]:
+//! - JSON: an array of objects with the keys `path`, `status` (`identical` or
+//!   `different`) and `copies`, an array of objects with `source`, `path`,
+//!   `size`, `hash` and `winner`, lowest priority first.
+//! - CSV/TSV: a header row, then one row per copy with the columns
+//!   `path,status,source,source_path,size,hash,winner`. `path` is the path
+//!   in the winning source and `source_path` the path in this copy's source.
+//!
+//! Mesh inspection (`inspect`), see [`NifReport`]:
+//! - JSON: an array of objects with the keys `path`, `source`, `header`,
+//!   `textures` and `error`. `header` is `null` or an object with `version`,
+//!   `user_version`, `bs_version`, `block_count` and `block_types` (an array
+//!   of objects with `name` and `count`). `textures` is an array of objects
+//!   with `path`, `resolved` and `missing`.
+//! - CSV/TSV: a header row, then one row per texture with the columns
+//!   `path,source,version,user_version,bs_version,blocks,texture,resolved,missing,error`.
+//!   A mesh without textures gets one row with empty texture columns.
+//!
+//! Plugin reference checks (`check-refs`), see [`RefReport`]:
+//! - JSON: an object with `references` (the number of distinct assets
+//!   referenced), `missing`, an array of objects with `kind` (`model`,
+//!   `texture`, `sound` or `script`), `path`, `resolved` and `referenced_by`,
+//!   and `unreferenced`, an array of objects with `kind`, `path` and `source`.
+//! - CSV/TSV: a header row, then one row per missing or unreferenced asset
+//!   with the columns `status,kind,path,resolved,referenced_by,source`.
+//!   `status` is `missing` or `unreferenced`; columns that don't apply are
+//!   empty.
+
+use anyhow::Result;
+use std::io::Write;
+
+use super::{ArchiveFileEntry, ArchiveInfo, Conflict, NifReport, RefReport};
+
+/// Output format for `list` and `info`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl ListFormat {
+    /// Short CLI-friendly name
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            ListFormat::Text => "text",
+            ListFormat::Json => "json",
+            ListFormat::Csv => "csv",
+            ListFormat::Tsv => "tsv",
+        }
+    }
+
+    /// Parse from CLI name (case-insensitive)
+    pub fn from_cli_name(name: &str) -> Option<ListFormat> {
+        let lower = name.to_lowercase();
+        ListFormat::all()
+            .iter()
+            .find(|f| f.cli_name() == lower)
+            .copied()
+    }
+
+    /// Get all formats
+    pub fn all() -> &'static [ListFormat] {
+        &[
+            ListFormat::Text,
+            ListFormat::Json,
+            ListFormat::Csv,
+            ListFormat::Tsv,
+        ]
+    }
+
+    fn separator(&self) -> char {
+        match self {
+            ListFormat::Tsv => '\t',
+            _ => ',',
+        }
+    }
+}
+
+const ENTRY_COLUMNS: &[&str] = &[
+    "path",
+    "size",
+    "packed_size",
+    "compressed",
+    "hash",
+    "offset",
+    "width",
+    "height",
+    "mip_count",
+    "dxgi_format",
+    "chunks",
+    "codec",
+    "sample_rate",
+    "channels",
+    "duration_ms",
+];
+
+const INFO_COLUMNS: &[&str] = &[
+    "format",
+    "version",
+    "ba2_format",
+    "flags",
+    "types",
+    "game",
+    "file_count",
+    "total_size",
+    "total_packed_size",
+];
+
+const CONFLICT_COLUMNS: &[&str] = &[
+    "path",
+    "status",
+    "source",
+    "source_path",
+    "size",
+    "hash",
+    "winner",
+];
+
+/// Write archive entries in the given format
+pub fn write_entries<W: Write>(
+    out: &mut W,
+    entries: &[ArchiveFileEntry],
+    format: ListFormat,
+) -> Result<()> {
+    match format {
+        ListFormat::Text => {
+            for entry in entries {
+                writeln!(out, "{}", entry.path)?;
+            }
+        }
+        ListFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, entries).map_err(std::io::Error::from)?;
+            writeln!(out)?;
+        }
+        ListFormat::Csv | ListFormat::Tsv => {
+            write_row(out, format, ENTRY_COLUMNS.iter().map(|c| c.to_string()))?;
+            for entry in entries {
+                let texture = entry.texture.map(|t| {
+                    [t.width, t.height, t.mip_count, t.dxgi_format, t.chunks].map(|v| v.to_string())
+                });
+                let texture = texture.unwrap_or_default();
+                let audio = entry.audio.map(|a| {
+                    [
+                        a.codec.to_string(),
+                        a.sample_rate.to_string(),
+                        a.channels.to_string(),
+                        a.duration_ms.to_string(),
+                    ]
+                });
+                let audio = audio.unwrap_or_default();
+                let row = [
+                    entry.path.clone(),
+                    entry.size.to_string(),
+                    entry.packed_size.to_string(),
+                    entry.compressed.to_string(),
+                    entry.hash.clone(),
+                    entry.offset.to_string(),
+                ]
+                .into_iter()
+                .chain(texture)
+                .chain(audio);
+                write_row(out, format, row)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write archive-level information in the given format
+pub fn write_info<W: Write>(out: &mut W, info: &ArchiveInfo, format: ListFormat) -> Result<()> {
+    let optional = |v: Option<String>| v.unwrap_or_default();
+    let values = [
+        info.format.to_string(),
+        info.version.to_string(),
+        optional(info.ba2_format.map(str::to_string)),
+        optional(info.flags.map(|f| format!("0x{:x}", f))),
+        optional(info.types.map(|t| format!("0x{:x}", t))),
+        optional(info.game.map(str::to_string)),
+        info.file_count.to_string(),
+        info.total_size.to_string(),
+        info.total_packed_size.to_string(),
+    ];
+
+    match format {
+        ListFormat::Text => {
+            for (key, value) in INFO_COLUMNS.iter().zip(values) {
+                if !value.is_empty() {
+                    writeln!(out, "{:<18} {}", format!("{}:", key), value)?;
+                }
+            }
+        }
+        ListFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, info).map_err(std::io::Error::from)?;
+            writeln!(out)?;
+        }
+        ListFormat::Csv | ListFormat::Tsv => {
+            write_row(out, format, INFO_COLUMNS.iter().map(|c| c.to_string()))?;
+            write_row(out, format, values)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a conflict report in the given format
+pub fn write_conflicts<W: Write>(
+    out: &mut W,
+    conflicts: &[Conflict],
+    format: ListFormat,
+) -> Result<()> {
+    match format {
+        ListFormat::Text => {
+            for conflict in conflicts {
+                writeln!(out, "{} [{}]", conflict.path, conflict.status.cli_name())?;
+                for copy in conflict.copies.iter().rev() {
+                    let marker = if copy.winner { "*" } else { " " };
+                    writeln!(
+                        out,
+                        "  {} {} {:>10}  {}",
+                        marker,
+                        copy.hash,
+                        copy.size,
+                        copy.source.display()
+                    )?;
+                }
+            }
+        }
+        ListFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, conflicts).map_err(std::io::Error::from)?;
+            writeln!(out)?;
+        }
+        ListFormat::Csv | ListFormat::Tsv => {
+            write_row(out, format, CONFLICT_COLUMNS.iter().map(|c| c.to_string()))?;
+            for conflict in conflicts {
+                for copy in &conflict.copies {
+                    let row = [
+                        conflict.path.clone(),
+                        conflict.status.cli_name().to_string(),
+                        copy.source.display().to_string(),
+                        copy.path.clone(),
+                        copy.size.to_string(),
+                        copy.hash.clone(),
+                        copy.winner.to_string(),
+                    ];
+                    write_row(out, format, row)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+const NIF_COLUMNS: &[&str] = &[
+    "path",
+    "source",
+    "version",
+    "user_version",
+    "bs_version",
+    "blocks",
+    "texture",
+    "resolved",
+    "missing",
+    "error",
+];
+
+/// Write mesh inspection results in the given format
+pub fn write_nif_reports<W: Write>(
+    out: &mut W,
+    reports: &[NifReport],
+    format: ListFormat,
+) -> Result<()> {
+    match format {
+        ListFormat::Text => {
+            for report in reports {
+                match &report.header {
+                    Some(header) => {
+                        let bs_version = header
+                            .bs_version
+                            .map(|v| format!(", bs version {}", v))
+                            .unwrap_or_default();
+                        writeln!(
+                            out,
+                            "{} (NIF {}, user version {}{}, {} blocks)",
+                            report.path,
+                            header.version,
+                            header.user_version,
+                            bs_version,
+                            header.block_count
+                        )?;
+                        if !header.block_types.is_empty() {
+                            let types: Vec<String> = header
+                                .block_types
+                                .iter()
+                                .map(|t| format!("{} {}", t.name, t.count))
+                                .collect();
+                            writeln!(out, "  blocks: {}", types.join(", "))?;
+                        }
+                    }
+                    None => writeln!(out, "{}", report.path)?,
+                }
+                for texture in &report.textures {
+                    let marker = if texture.missing { "  [missing]" } else { "" };
+                    writeln!(out, "  {}{}", texture.path, marker)?;
+                }
+                if let Some(error) = &report.error {
+                    writeln!(out, "  ! {}", error)?;
+                }
+            }
+        }
+        ListFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, reports).map_err(std::io::Error::from)?;
+            writeln!(out)?;
+        }
+        ListFormat::Csv | ListFormat::Tsv => {
+            write_row(out, format, NIF_COLUMNS.iter().map(|c| c.to_string()))?;
+            for report in reports {
+                let header = report.header.as_ref().map(|h| {
+                    [
+                        h.version.clone(),
+                        h.user_version.to_string(),
+                        h.bs_version.map(|v| v.to_string()).unwrap_or_default(),
+                        h.block_count.to_string(),
+                    ]
+                });
+                let header = header.unwrap_or_default();
+                let textures: Vec<[String; 3]> = if report.textures.is_empty() {
+                    vec![Default::default()]
+                } else {
+                    report
+                        .textures
+                        .iter()
+                        .map(|t| [t.path.clone(), t.resolved.clone(), t.missing.to_string()])
+                        .collect()
+                };
+                for texture in textures {
+                    let row = [report.path.clone(), report.source.display().to_string()]
+                        .into_iter()
+                        .chain(header.clone())
+                        .chain(texture)
+                        .chain([report.error.clone().unwrap_or_default()]);
+                    write_row(out, format, row)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+const REF_COLUMNS: &[&str] = &[
+    "status",
+    "kind",
+    "path",
+    "resolved",
+    "referenced_by",
+    "source",
+];
+
+/// Write a plugin reference check in the given format
+pub fn write_ref_report<W: Write>(
+    out: &mut W,
+    report: &RefReport,
+    format: ListFormat,
+) -> Result<()> {
+    match format {
+        ListFormat::Text => {
+            writeln!(out, "Missing ({}):", report.missing.len())?;
+            for reference in &report.missing {
+                writeln!(
+                    out,
+                    "  {:<8} {}  ({})",
+                    reference.kind.cli_name(),
+                    reference.resolved,
+                    reference.referenced_by
+                )?;
+            }
+            writeln!(out, "Unreferenced ({}):", report.unreferenced.len())?;
+            for asset in &report.unreferenced {
+                writeln!(
+                    out,
+                    "  {:<8} {}  ({})",
+                    asset.kind.cli_name(),
+                    asset.path,
+                    asset.source.display()
+                )?;
+            }
+        }
+        ListFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, report).map_err(std::io::Error::from)?;
+            writeln!(out)?;
+        }
+        ListFormat::Csv | ListFormat::Tsv => {
+            write_row(out, format, REF_COLUMNS.iter().map(|c| c.to_string()))?;
+            for reference in &report.missing {
+                let row = [
+                    "missing".to_string(),
+                    reference.kind.cli_name().to_string(),
+                    reference.path.clone(),
+                    reference.resolved.clone(),
+                    reference.referenced_by.clone(),
+                    String::new(),
+                ];
+                write_row(out, format, row)?;
+            }
+            for asset in &report.unreferenced {
+                let row = [
+                    "unreferenced".to_string(),
+                    asset.kind.cli_name().to_string(),
+                    asset.path.clone(),
+                    String::new(),
+                    String::new(),
+                    asset.source.display().to_string(),
+                ];
+                write_row(out, format, row)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_row<W: Write>(
+    out: &mut W,
+    format: ListFormat,
+    fields: impl IntoIterator<Item = String>,
+) -> Result<()> {
+    let separator = format.separator();
+    let mut line = String::new();
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            line.push(separator);
+        }
+        line.push_str(&escape_field(&field, format));
+    }
+    writeln!(out, "{}", line)?;
+    Ok(())
+}
+
+/// Quote CSV fields per RFC 4180; TSV has no quoting, so tabs/newlines become spaces
+fn escape_field(field: &str, format: ListFormat) -> String {
+    match format {
+        ListFormat::Tsv => field.replace(['\t', '\n', '\r'], " "),
+        _ if field.contains([',', '"', '\n', '\r']) => {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        }
+        _ => field.to_string(),
+    }
+}
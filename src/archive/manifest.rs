@@ -0,0 +1,662 @@
+//! Pack manifests for scripted, repeatable archive builds
+//!
+//! A manifest is a TOML file describing one `pack` run:
+//!
+//! ```toml
+//! game = "skyrimse"
+//! source = "Data"              # relative to the manifest
+//! output = "MyMod.bsa"         # relative to the manifest
+//! compression = "default"      # default | none | zlib | lz4 (Skyrim SE)
+//! format = "auto"              # auto | general | dx10 (BA2 only)
+//! split_size = "2GB"           # optional; bytes or a KB/MB/GB size
+//! order = "hash"               # hash | vanilla (BA2 entry order)
+//! max_chunk_size = "2MB"       # optional; DX10 chunk size limit
+//! max_chunks = 4               # optional; DX10 chunks per texture, 1-4
+//! include = ["meshes/**", "textures/**"]
+//! exclude = ["**/*.psd"]
+//! files = ["meshes/a.nif"]     # optional explicit selection
+//!
+//! [remap]
+//! "textures/wip" = "textures/mymod"
+//! ```
+//!
+//! Files are taken from `source`, narrowed to `files` when given, then to
+//! the include/exclude globs (matched against the source path). Remaps
+//! replace a leading directory prefix; the longest matching prefix wins.
+//! With `split_size`, files are spread over several archives in path order:
+//! the first keeps the output name and later ones get 2, 3, ... appended to
+//! the stem.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::info;
+use walkdir::WalkDir;
+
+use super::filter::normalize_path;
+use super::{
+    is_plugin_path, ArchiveOrder, Ba2Builder, Ba2CompressionFormat, Ba2Format, BsaBuilder,
+    GameVersion, MipChunking, PathFilter,
+};
+use ba2::tes4::Version;
+
+/// Compression requested by a manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackCompression {
+    /// Whatever the game normally uses
+    #[default]
+    Default,
+    /// Store files uncompressed
+    None,
+    /// zlib (Oblivion through Skyrim LE, Fallout 4)
+    Zlib,
+    /// LZ4 (Skyrim SE; BA2 archives are always written with zlib)
+    Lz4,
+}
+
+impl PackCompression {
+    /// Get display name for this compression
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            PackCompression::Default => "Game default",
+            PackCompression::None => "Uncompressed",
+            PackCompression::Zlib => "zlib",
+            PackCompression::Lz4 => "LZ4",
+        }
+    }
+
+    /// Short CLI-friendly name
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            PackCompression::Default => "default",
+            PackCompression::None => "none",
+            PackCompression::Zlib => "zlib",
+            PackCompression::Lz4 => "lz4",
+        }
+    }
+
+    /// Parse from CLI name (case-insensitive)
+    pub fn from_cli_name(name: &str) -> Option<PackCompression> {
+        let lower = name.to_lowercase();
+        PackCompression::all()
+            .iter()
+            .find(|c| c.cli_name() == lower)
+            .copied()
+    }
+
+    /// Get all compression settings
+    pub fn all() -> &'static [PackCompression] {
+        &[
+            PackCompression::Default,
+            PackCompression::None,
+            PackCompression::Zlib,
+            PackCompression::Lz4,
+        ]
+    }
+}
+
+/// BA2 format requested by a manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackFormat {
+    /// DX10 when the archive name contains "textures", otherwise general
+    #[default]
+    Auto,
+    /// General archive (GNRL)
+    General,
+    /// DirectX 10 textures (DX10)
+    Dx10,
+}
+
+impl PackFormat {
+    /// Get display name for this format
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            PackFormat::Auto => "Detect from name",
+            PackFormat::General => "General (GNRL)",
+            PackFormat::Dx10 => "Textures (DX10)",
+        }
+    }
+
+    /// Short CLI-friendly name
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            PackFormat::Auto => "auto",
+            PackFormat::General => "general",
+            PackFormat::Dx10 => "dx10",
+        }
+    }
+
+    /// Parse from CLI name (case-insensitive)
+    pub fn from_cli_name(name: &str) -> Option<PackFormat> {
+        let lower = name.to_lowercase();
+        PackFormat::all()
+            .iter()
+            .find(|f| f.cli_name() == lower)
+            .copied()
+    }
+
+    /// Get all formats
+    pub fn all() -> &'static [PackFormat] {
+        &[PackFormat::Auto, PackFormat::General, PackFormat::Dx10]
+    }
+}
+
+/// What is being done to the files of a part
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackStage {
+    /// Reading loose files from the source folder
+    Reading,
+    /// Compressing and writing the archive
+    Compressing,
+}
+
+/// A loose file going into an archive
+#[derive(Debug, Clone)]
+pub struct PackFile {
+    /// File on disk
+    pub source: PathBuf,
+    /// Path inside the archive, after remapping
+    pub path: String,
+    /// Size on disk in bytes
+    pub size: u64,
+}
+
+/// One output archive of a manifest build
+#[derive(Debug, Clone)]
+pub struct PackPart {
+    /// Archive to write
+    pub output: PathBuf,
+    /// Files in archive path order
+    pub files: Vec<PackFile>,
+    /// Total size of the files on disk
+    pub size: u64,
+}
+
+/// A pack manifest. Relative `source` and `output` paths are resolved
+/// against the directory the manifest was loaded from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackManifest {
+    /// Game the archive is built for
+    #[serde(with = "game_name")]
+    pub game: GameVersion,
+    /// Folder of loose files, relative to the manifest or absolute
+    pub source: PathBuf,
+    /// Archive to write (the first one, when split), relative to the
+    /// manifest or absolute
+    pub output: PathBuf,
+    /// BA2 format
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub format: PackFormat,
+    /// Compression
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub compression: PackCompression,
+    /// Largest total file size per archive, in bytes
+    #[serde(
+        default,
+        deserialize_with = "deserialize_size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub split_size: Option<u64>,
+    /// Entry order inside each archive
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub order: ArchiveOrder,
+    /// DX10 chunk size limit in bytes; Archive2's when absent
+    #[serde(
+        default,
+        deserialize_with = "deserialize_size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_chunk_size: Option<u64>,
+    /// Most DX10 chunks per texture (1-4); Archive2's when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_chunks: Option<usize>,
+    /// Include globs; no globs includes everything
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Exclude globs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Explicit selection of source paths; every file when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<String>>,
+    /// Source directory prefix -> archive directory prefix
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub remap: BTreeMap<String, String>,
+    /// Directory relative paths are resolved against
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// Games are written by their CLI name
+mod game_name {
+    use super::GameVersion;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(game: &GameVersion, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(game.cli_name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<GameVersion, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        GameVersion::from_cli_name(&name)
+            .ok_or_else(|| D::Error::custom(format!("unknown game version: {}", name)))
+    }
+}
+
+/// Accept a size either as bytes or as a string like "512MB" or "2GB"
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Ok(Some(bytes)),
+        Size::Text(text) => parse_size(&text)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid size: {}", text))),
+    }
+}
+
+/// Parse "1048576", "512KB", "64MB" or "1.5GB" (binary units)
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim().to_uppercase();
+    let (number, unit) = match text.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(pos) => text.split_at(pos),
+        None => (text.as_str(), ""),
+    };
+    let scale: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return None,
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    (number > 0.0).then_some((number * scale as f64) as u64)
+}
+
+impl PackManifest {
+    /// Manifest packing every file of `source` into `output`
+    pub fn new(game: GameVersion, source: &Path, output: &Path) -> Self {
+        Self {
+            game,
+            source: source.to_path_buf(),
+            output: output.to_path_buf(),
+            format: PackFormat::default(),
+            compression: PackCompression::default(),
+            split_size: None,
+            order: ArchiveOrder::default(),
+            max_chunk_size: None,
+            max_chunks: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            files: None,
+            remap: BTreeMap::new(),
+            base_dir: PathBuf::new(),
+        }
+    }
+
+    /// Read a manifest from a TOML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+        let mut manifest: PackManifest = toml::from_str(&text)
+            .with_context(|| format!("Invalid manifest: {}", path.display()))?;
+        manifest.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(manifest)
+    }
+
+    /// Write the manifest as TOML. `source` and `output` are stored relative
+    /// to the manifest when they are inside its directory.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut manifest = self.clone();
+        manifest.source = relative_to(&self.source_dir(), dir);
+        manifest.output = relative_to(&self.output_path(), dir);
+        let text = toml::to_string_pretty(&manifest).context("Failed to serialize manifest")?;
+        std::fs::write(path, text)
+            .with_context(|| format!("Failed to write manifest: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Source folder, resolved against the manifest's directory
+    pub fn source_dir(&self) -> PathBuf {
+        self.base_dir.join(&self.source)
+    }
+
+    /// Output archive, resolved against the manifest's directory
+    pub fn output_path(&self) -> PathBuf {
+        self.base_dir.join(&self.output)
+    }
+
+    /// Include/exclude rules as a path filter
+    pub fn filter(&self) -> Result<PathFilter> {
+        let mut filter = PathFilter::new();
+        for pattern in &self.include {
+            filter.include_glob(pattern)?;
+        }
+        for pattern in &self.exclude {
+            filter.exclude_glob(pattern)?;
+        }
+        Ok(filter)
+    }
+
+    /// Check the settings against the game without touching the source folder
+    pub fn validate(&self) -> Result<()> {
+        if self.game.is_tes3() {
+            bail!("Morrowind TES3 BSA writing is not supported");
+        }
+        if !self.game.is_ba2() && self.format != PackFormat::Auto {
+            bail!(
+                "format = \"{}\" only applies to BA2 archives, not {}",
+                self.format.cli_name(),
+                self.game.display_name()
+            );
+        }
+        if self.split_size == Some(0) {
+            bail!("split_size must be greater than zero");
+        }
+        if !self.game.is_ba2() && (self.max_chunk_size.is_some() || self.max_chunks.is_some()) {
+            bail!(
+                "max_chunk_size and max_chunks only apply to BA2 archives, not {}",
+                self.game.display_name()
+            );
+        }
+        if self.max_chunk_size == Some(0) {
+            bail!("max_chunk_size must be greater than zero");
+        }
+        if let Some(count) = self.max_chunks.filter(|n| !(1..=4).contains(n)) {
+            bail!("max_chunks must be 1 to 4, not {}", count);
+        }
+        if self.game.is_ba2() {
+            self.ba2_compression()?;
+        } else {
+            self.bsa_compression()?;
+        }
+        self.filter()?;
+        Ok(())
+    }
+
+    /// Compression written to BA2 archives
+    fn ba2_compression(&self) -> Result<Ba2CompressionFormat> {
+        Ok(match self.compression {
+            PackCompression::Default => self.game.ba2_compression(),
+            PackCompression::None => Ba2CompressionFormat::None,
+            PackCompression::Zlib => Ba2CompressionFormat::Zlib,
+            // Compressed chunks are always written with zlib, which every
+            // BA2 game reads
+            PackCompression::Lz4 => bail!(
+                "BA2 archives are written with zlib compression, not LZ4; use compression = \"zlib\" or \"default\""
+            ),
+        })
+    }
+
+    /// How DX10 textures are split into chunks
+    fn mip_chunking(&self) -> MipChunking {
+        let default = MipChunking::default();
+        MipChunking {
+            max_chunk_size: self.max_chunk_size.map(|size| size as usize),
+            max_chunks: self.max_chunks.unwrap_or(default.max_chunks),
+        }
+    }
+
+    /// Whether BSA files are compressed; the codec is fixed by the version
+    fn bsa_compression(&self) -> Result<bool> {
+        let version = self.game.bsa_version().unwrap_or(Version::v104);
+        let codec = if version == Version::v105 {
+            PackCompression::Lz4
+        } else {
+            PackCompression::Zlib
+        };
+        match self.compression {
+            PackCompression::Default => Ok(self.game.supports_compression()),
+            PackCompression::None => Ok(false),
+            requested if requested == codec => Ok(true),
+            requested => bail!(
+                "{} archives use {} compression, not {}",
+                self.game.display_name(),
+                codec.display_name(),
+                requested.display_name()
+            ),
+        }
+    }
+
+    /// Archive path for a source path, after remapping
+    pub fn remap_path(&self, path: &str) -> String {
+        let components: Vec<&str> = path.split(['/', '\\']).filter(|c| !c.is_empty()).collect();
+        let mut best: Option<(usize, &str)> = None;
+        for (from, to) in &self.remap {
+            let prefix: Vec<String> = normalize_path(from)
+                .split('/')
+                .filter(|c| !c.is_empty())
+                .map(str::to_string)
+                .collect();
+            let matches = prefix.len() <= components.len()
+                && prefix
+                    .iter()
+                    .zip(&components)
+                    .all(|(p, c)| *p == c.to_lowercase());
+            if matches && best.is_none_or(|(len, _)| prefix.len() > len) {
+                best = Some((prefix.len(), to));
+            }
+        }
+
+        match best {
+            Some((len, to)) => to
+                .split(['/', '\\'])
+                .filter(|c| !c.is_empty())
+                .chain(components[len..].iter().copied())
+                .collect::<Vec<_>>()
+                .join("/"),
+            None => components.join("/"),
+        }
+    }
+
+    /// Work out which files go into which archive
+    pub fn plan(&self) -> Result<Vec<PackPart>> {
+        self.validate()?;
+        let source_dir = self.source_dir();
+        if !source_dir.is_dir() {
+            bail!("Source folder not found: {}", source_dir.display());
+        }
+
+        let mut wanted: Option<HashSet<String>> = self
+            .files
+            .as_ref()
+            .map(|files| files.iter().map(|f| normalize_path(f)).collect());
+        let filter = self.filter()?;
+
+        let mut files = Vec::new();
+        let mut seen: HashMap<String, String> = HashMap::new();
+        for entry in WalkDir::new(&source_dir).sort_by_file_name() {
+            let entry =
+                entry.with_context(|| format!("Failed to read {}", source_dir.display()))?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let Ok(rel) = entry.path().strip_prefix(&source_dir) else {
+                continue;
+            };
+            let rel = rel.to_string_lossy().replace('\\', "/");
+            if let Some(wanted) = &mut wanted {
+                if !wanted.remove(&normalize_path(&rel)) {
+                    continue;
+                }
+            }
+            if !filter.matches(&rel) {
+                continue;
+            }
+            // Plugins and the archives next to them are never loaded from
+            // inside an archive
+            let lower = rel.to_lowercase();
+            if entry.depth() == 1
+                && (is_plugin_path(&rel) || lower.ends_with(".bsa") || lower.ends_with(".ba2"))
+            {
+                continue;
+            }
+
+            let path = self.remap_path(&rel);
+            if let Some(previous) = seen.insert(normalize_path(&path), rel.clone()) {
+                bail!(
+                    "{} and {} both map to {} in the archive",
+                    previous,
+                    rel,
+                    path
+                );
+            }
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            files.push(PackFile {
+                source: entry.path().to_path_buf(),
+                path,
+                size,
+            });
+        }
+
+        if let Some(missing) = wanted.filter(|w| !w.is_empty()) {
+            let mut missing: Vec<String> = missing.into_iter().collect();
+            missing.sort();
+            bail!(
+                "{} listed file(s) not found in {}: {}",
+                missing.len(),
+                source_dir.display(),
+                missing.join(", ")
+            );
+        }
+        if files.is_empty() {
+            bail!("No files matched in {}", source_dir.display());
+        }
+        files.sort_by_cached_key(|f| normalize_path(&f.path));
+
+        let mut parts: Vec<PackPart> = Vec::new();
+        for file in files {
+            let start_new = match (parts.last(), self.split_size) {
+                (None, _) => true,
+                (Some(part), Some(limit)) => part.size + file.size > limit,
+                (Some(_), None) => false,
+            };
+            if start_new {
+                parts.push(PackPart {
+                    output: self.part_output(parts.len()),
+                    files: Vec::new(),
+                    size: 0,
+                });
+            }
+            let part = parts.last_mut().unwrap();
+            part.size += file.size;
+            part.files.push(file);
+        }
+        Ok(parts)
+    }
+
+    /// Output path of the part at `index`
+    fn part_output(&self, index: usize) -> PathBuf {
+        let output = self.output_path();
+        if index == 0 {
+            return output;
+        }
+        let stem = output
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = match output.extension() {
+            Some(ext) => format!("{}{}.{}", stem, index + 1, ext.to_string_lossy()),
+            None => format!("{}{}", stem, index + 1),
+        };
+        output.with_file_name(name)
+    }
+
+    /// BA2 format for a part, from the setting or the part's name
+    fn ba2_format(&self, output: &Path) -> Ba2Format {
+        match self.format {
+            PackFormat::General => Ba2Format::General,
+            PackFormat::Dx10 => Ba2Format::DX10,
+            PackFormat::Auto => {
+                let name_lower = output
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                if name_lower.contains("textures") {
+                    Ba2Format::DX10
+                } else {
+                    Ba2Format::General
+                }
+            }
+        }
+    }
+
+    /// Read the files of a part and write its archive, calling
+    /// `progress(stage, done, total, path)` along the way. Setting
+    /// `cancelled` stops reading after the current file.
+    pub fn build_part<F>(&self, part: &PackPart, cancelled: &AtomicBool, progress: F) -> Result<()>
+    where
+        F: Fn(PackStage, usize, usize, &str) + Send + Sync,
+    {
+        let total = part.files.len();
+        let read = |builder: &mut dyn FnMut(&str, Vec<u8>)| -> Result<()> {
+            for (idx, file) in part.files.iter().enumerate() {
+                let data = std::fs::read(&file.source)
+                    .with_context(|| format!("Failed to read: {}", file.source.display()))?;
+                builder(&file.path, data);
+                progress(PackStage::Reading, idx + 1, total, &file.path);
+                if cancelled.load(Ordering::SeqCst) {
+                    bail!("Cancelled");
+                }
+            }
+            Ok(())
+        };
+        let compress = |current: usize, total: usize, name: &str| {
+            progress(PackStage::Compressing, current, total, name)
+        };
+
+        if self.game.is_ba2() {
+            let mut builder = Ba2Builder::new()
+                .with_version(self.game.ba2_version().unwrap_or_default())
+                .with_compression(self.ba2_compression()?)
+                .with_format(self.ba2_format(&part.output))
+                .with_order(self.order)
+                .with_mip_chunking(self.mip_chunking());
+            read(&mut |path, data| builder.add_file(path, data))?;
+            builder.build_with_progress(&part.output, compress)?;
+        } else {
+            let Some(version) = self.game.bsa_version() else {
+                bail!("Morrowind TES3 BSA writing is not supported");
+            };
+            let mut builder = BsaBuilder::new()
+                .with_version(version)
+                .with_compression(self.bsa_compression()?);
+            read(&mut |path, data| builder.add_file(path, data))?;
+            builder.build_with_progress(&part.output, compress)?;
+        }
+
+        info!(
+            "Packed {} files into {} from manifest",
+            total,
+            part.output.display()
+        );
+        Ok(())
+    }
+}
+
+/// `path` relative to `dir` when it is inside it, otherwise unchanged
+fn relative_to(path: &Path, dir: &Path) -> PathBuf {
+    let absolute = |p: &Path| std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf());
+    match absolute(path).strip_prefix(absolute(dir)) {
+        Ok(rel) if rel.as_os_str().is_empty() => PathBuf::from("."),
+        Ok(rel) => rel.to_path_buf(),
+        Err(_) => path.to_path_buf(),
+    }
+}
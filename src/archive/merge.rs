@@ -0,0 +1,381 @@
+//! Merging several archives into one
+//!
+//! Inputs are read through the readers and written with the builders. When
+//! a path is in more than one input, the input with the highest priority
+//! wins and the others are reported as overridden. Files are copied without
+//! recompressing whenever the winner's format matches the output.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, info};
+
+use super::ba2_reader::Ba2Archive;
+use super::filter::normalize_path;
+use super::reader::BsaArchive;
+use super::tes3_reader::Tes3Archive;
+use super::{
+    detect_format, ArchiveFormat, Ba2Builder, Ba2Format, BsaBuilder, FormatOptions, GameVersion,
+    OpenArchive,
+};
+
+/// Which input wins when a path is in more than one archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePriority {
+    /// Later inputs override earlier ones, like a load order
+    #[default]
+    Last,
+    /// Earlier inputs override later ones
+    First,
+}
+
+impl MergePriority {
+    /// Get display name for this priority
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            MergePriority::Last => "Last input wins",
+            MergePriority::First => "First input wins",
+        }
+    }
+
+    /// Short CLI-friendly name
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            MergePriority::Last => "last",
+            MergePriority::First => "first",
+        }
+    }
+
+    /// Parse from CLI name (case-insensitive)
+    pub fn from_cli_name(name: &str) -> Option<MergePriority> {
+        let lower = name.to_lowercase();
+        MergePriority::all()
+            .iter()
+            .find(|p| p.cli_name() == lower)
+            .copied()
+    }
+
+    /// Get all priorities
+    pub fn all() -> &'static [MergePriority] {
+        &[MergePriority::Last, MergePriority::First]
+    }
+}
+
+/// A path found in more than one input
+#[derive(Debug, Clone)]
+pub struct MergeOverride {
+    /// Path as stored in the winning archive
+    pub path: String,
+    /// Archive whose copy was kept
+    pub winner: PathBuf,
+    /// Archives whose copies were dropped, lowest priority first
+    pub overridden: Vec<PathBuf>,
+}
+
+/// Result of a merge
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    /// Number of files in the merged archive
+    pub files: usize,
+    /// Paths found in more than one input, sorted by path
+    pub overrides: Vec<MergeOverride>,
+}
+
+/// An input archive, opened with its format's reader
+enum Input {
+    Tes3(Tes3Archive),
+    Tes4(BsaArchive),
+    Fo4(Ba2Archive),
+}
+
+impl Input {
+    fn open(path: &Path) -> Result<Self> {
+        match detect_format(path) {
+            Some(ArchiveFormat::Tes3Bsa) => Ok(Input::Tes3(Tes3Archive::open(path)?)),
+            Some(ArchiveFormat::Bsa) => Ok(Input::Tes4(BsaArchive::open(path)?)),
+            Some(ArchiveFormat::Ba2) => Ok(Input::Fo4(Ba2Archive::open(path)?)),
+            None => bail!("Unknown archive format: {}", path.display()),
+        }
+    }
+
+    fn archive(&self) -> &dyn OpenArchive {
+        match self {
+            Input::Tes3(archive) => archive,
+            Input::Tes4(archive) => archive,
+            Input::Fo4(archive) => archive,
+        }
+    }
+
+    fn is_dx10(&self) -> bool {
+        matches!(
+            self.archive().options(),
+            FormatOptions::Fo4(options) if options.format() == ba2::fo4::Format::DX10
+        )
+    }
+}
+
+/// The builder for the output archive
+enum Output {
+    Bsa(BsaBuilder),
+    Ba2(Ba2Builder, Ba2Format),
+}
+
+/// Builder for merging archives
+pub struct ArchiveMerger {
+    inputs: Vec<PathBuf>,
+    game: GameVersion,
+    priority: MergePriority,
+    preferred: Option<PathBuf>,
+}
+
+impl ArchiveMerger {
+    pub fn new(game: GameVersion) -> Self {
+        Self {
+            inputs: Vec::new(),
+            game,
+            priority: MergePriority::default(),
+            preferred: None,
+        }
+    }
+
+    /// Set which input wins on conflicts
+    pub fn with_priority(mut self, priority: MergePriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Make one input win over all others, regardless of order
+    pub fn with_preferred(mut self, input: &Path) -> Self {
+        self.preferred = Some(input.to_path_buf());
+        self
+    }
+
+    /// Add an input archive
+    pub fn add_input(&mut self, path: &Path) {
+        self.inputs.push(path.to_path_buf());
+    }
+
+    /// Input indices from lowest to highest priority
+    fn priority_order(&self) -> Result<Vec<usize>> {
+        let mut order: Vec<usize> = (0..self.inputs.len()).collect();
+        if self.priority == MergePriority::First {
+            order.reverse();
+        }
+        if let Some(preferred) = &self.preferred {
+            // Compare canonical paths so `./a.bsa` and `a.bsa` match
+            let canonical = preferred
+                .canonicalize()
+                .unwrap_or_else(|_| preferred.clone());
+            let Some(pos) = order.iter().position(|&i| {
+                self.inputs[i]
+                    .canonicalize()
+                    .is_ok_and(|input| input == canonical)
+            }) else {
+                bail!("Preferred archive is not an input: {}", preferred.display());
+            };
+            let index = order.remove(pos);
+            order.push(index);
+        }
+        Ok(order)
+    }
+
+    /// Merge the inputs and write the result to `output_path`
+    pub fn build_with_progress<F>(self, output_path: &Path, progress: F) -> Result<MergeReport>
+    where
+        F: Fn(usize, usize, &str) + Send + Sync,
+    {
+        if self.inputs.is_empty() {
+            bail!("No input archives to merge");
+        }
+        if self.game.is_tes3() {
+            bail!("Morrowind TES3 BSA writing is not supported");
+        }
+
+        let inputs: Vec<Input> = self
+            .inputs
+            .iter()
+            .map(|p| Input::open(p))
+            .collect::<Result<_>>()?;
+
+        // Normalized path -> (input, stored path), plus every input that had it
+        let mut winners: HashMap<String, (usize, String)> = HashMap::new();
+        let mut sources: HashMap<String, Vec<usize>> = HashMap::new();
+        for index in self.priority_order()? {
+            for entry in inputs[index].archive().entries()? {
+                let key = normalize_path(&entry.path);
+                winners.insert(key.clone(), (index, entry.path.clone()));
+                sources.entry(key).or_default().push(index);
+            }
+        }
+
+        let mut overrides: Vec<MergeOverride> = sources
+            .iter()
+            .filter(|(_, indices)| indices.len() > 1)
+            .map(|(key, indices)| {
+                let (winner, path) = &winners[key];
+                MergeOverride {
+                    path: path.clone(),
+                    winner: self.inputs[*winner].clone(),
+                    overridden: indices[..indices.len() - 1]
+                        .iter()
+                        .map(|&i| self.inputs[i].clone())
+                        .collect(),
+                }
+            })
+            .collect();
+        overrides.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut output = self.output_builder(output_path, &inputs, &winners)?;
+
+        // Files each input contributes, split into stored copies and raw reads
+        let mut wanted: Vec<Vec<String>> = vec![Vec::new(); inputs.len()];
+        for (index, path) in winners.values() {
+            wanted[*index].push(path.clone());
+        }
+
+        for (index, paths) in wanted.iter().enumerate() {
+            if paths.is_empty() {
+                continue;
+            }
+            let input = &inputs[index];
+            let stored = match (&mut output, input) {
+                (Output::Bsa(builder), Input::Tes4(archive)) => {
+                    let version = archive.version();
+                    for (path, file) in stored_subset(archive.stored_files()?, paths) {
+                        builder.add_stored_file(&path, file, version);
+                    }
+                    true
+                }
+                (Output::Ba2(builder, format), Input::Fo4(archive))
+                    if (*format == Ba2Format::DX10) == input.is_dx10() =>
+                {
+                    let compression = archive.compression();
+                    for (path, file) in stored_subset(archive.stored_files()?, paths) {
+                        builder.add_stored_file(&path, file, compression);
+                    }
+                    true
+                }
+                _ => false,
+            };
+
+            if !stored {
+                let raw = Mutex::new(Vec::with_capacity(paths.len()));
+                input.archive().read_batch(paths, &|path, data| {
+                    raw.lock().unwrap().push((path.to_string(), data));
+                    Ok(())
+                })?;
+                let raw = raw.into_inner().unwrap();
+                match &mut output {
+                    Output::Bsa(builder) => {
+                        for (path, data) in raw {
+                            builder.add_file(&path, data);
+                        }
+                    }
+                    Output::Ba2(builder, _) => {
+                        for (path, data) in raw {
+                            builder.add_file(&path, data);
+                        }
+                    }
+                }
+            }
+            debug!(
+                "Merging {} files from {} ({})",
+                paths.len(),
+                self.inputs[index].display(),
+                if stored { "stored" } else { "decompressed" }
+            );
+        }
+
+        // The output may also be an input, which is still mapped while the
+        // copy is written, so write next to it and move it into place
+        let file_name = output_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let temp_path = output_path.with_file_name(format!(".{}.tmp", file_name));
+
+        let files = winners.len();
+        let result = match output {
+            Output::Bsa(builder) => builder.build_with_progress(&temp_path, progress),
+            Output::Ba2(builder, _) => builder.build_with_progress(&temp_path, progress),
+        };
+        // Close the inputs before replacing one, mapped files can't be
+        // replaced on Windows
+        drop(inputs);
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        fs::rename(&temp_path, output_path)
+            .with_context(|| format!("Failed to write: {}", output_path.display()))?;
+
+        info!(
+            "Merged {} archives into {} ({} files, {} overridden paths)",
+            self.inputs.len(),
+            output_path.display(),
+            files,
+            overrides.len()
+        );
+        Ok(MergeReport { files, overrides })
+    }
+
+    /// Builder for the output archive, configured the way `pack` does
+    fn output_builder(
+        &self,
+        output_path: &Path,
+        inputs: &[Input],
+        winners: &HashMap<String, (usize, String)>,
+    ) -> Result<Output> {
+        if self.game.is_ba2() {
+            // Merging texture archives gives a texture archive
+            let name_lower = output_path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let format = if inputs.iter().all(Input::is_dx10) {
+                Ba2Format::DX10
+            } else if name_lower.contains("textures") {
+                // The name asks for a texture archive, which only holds .dds files
+                let mut not_textures: Vec<&str> = winners
+                    .values()
+                    .map(|(_, path)| path.as_str())
+                    .filter(|path| !path.to_lowercase().ends_with(".dds"))
+                    .collect();
+                if !not_textures.is_empty() {
+                    not_textures.sort_unstable();
+                    bail!(
+                        "{} is named as a texture archive, so it would be a DX10 archive, but {} merged files aren't .dds textures:\n  {}",
+                        output_path.display(),
+                        not_textures.len(),
+                        not_textures.join("\n  ")
+                    );
+                }
+                Ba2Format::DX10
+            } else {
+                Ba2Format::General
+            };
+            let builder = Ba2Builder::new()
+                .with_version(self.game.ba2_version().unwrap_or_default())
+                .with_compression(self.game.ba2_compression())
+                .with_format(format);
+            Ok(Output::Ba2(builder, format))
+        } else {
+            let builder = BsaBuilder::new()
+                .with_version(self.game.bsa_version().unwrap_or(ba2::tes4::Version::v104))
+                .with_compression(self.game.supports_compression());
+            Ok(Output::Bsa(builder))
+        }
+    }
+}
+
+/// Keep the stored files whose paths are in `paths`
+fn stored_subset<T>(files: Vec<(String, T)>, paths: &[String]) -> Vec<(String, T)> {
+    let wanted: std::collections::HashSet<String> =
+        paths.iter().map(|p| normalize_path(p)).collect();
+    files
+        .into_iter()
+        .filter(|(path, _)| wanted.contains(&normalize_path(path)))
+        .collect()
+}
@@ -5,10 +5,27 @@
 //! - TES4 format BSA files (Oblivion, FO3, FNV, Skyrim)
 //! - FO4 format BA2 files (Fallout 4, Fallout 76, Starfield)
 
+mod audio;
 mod ba2_reader;
 mod ba2_writer;
+mod casefold;
+mod conflicts;
+mod container;
+mod dds;
+mod editor;
+mod filter;
+mod handle;
+mod listing;
+mod manifest;
+mod merge;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+mod mount;
+mod naming;
+mod nif;
+mod plugin;
 mod reader;
 mod tes3_reader;
+mod vfs;
 mod writer;
 
 pub use reader::{
@@ -25,16 +42,88 @@ pub use tes3_reader::{
 // BA2 support for Fallout 4/Starfield
 pub use ba2_reader::{
     extract_file as extract_ba2_file, extract_files_batch as extract_ba2_files_batch,
-    list_files as list_ba2_files,
+    list_files as list_ba2_files, Ba2FileEntry,
 };
-pub use ba2_writer::{Ba2Builder, Ba2CompressionFormat, Ba2Format, Ba2Version};
+pub use ba2_writer::{Ba2Builder, Ba2CompressionFormat, Ba2Format, Ba2Version, MipChunking};
 
-use anyhow::{bail, Result};
+// DDS header checks and decoding for texture archives
+pub use dds::{decode_dds_mip, dxgi_format_name, inspect_dds, DdsInfo, DecodedMip};
+
+// Voice files: .fuz split/join and audio headers
+pub use audio::{
+    inspect_audio, is_audio_path, join_fuz, pair_fuz_sources, read_audio_info, split_fuz,
+    AudioInfo, Fuz, FuzSources,
+};
+
+// NIF headers and texture references
+pub use nif::{
+    inspect_meshes, inspect_meshes_with_progress, inspect_nif, texture_lookup_path, NifBlockType,
+    NifHeader, NifInfo, NifReport, NifTexture,
+};
+
+// Plugin asset references
+pub use plugin::{
+    asset_lookup_path, check_references, check_references_with_progress, plugin_references,
+    read_plugin_references, AssetKind, AssetReference, RefReport, UnreferencedAsset,
+    PLUGIN_EXTENSIONS,
+};
+
+// Archive names the games load for a plugin
+pub use naming::{
+    archive_load_warning, find_plugins, is_plugin_path, loads_archive_for_plugin,
+    plugin_archive_names, PluginArchiveNames,
+};
+
+// Case folding for extraction on case-sensitive filesystems
+pub use casefold::{CaseCollision, CaseFolding, ExtractionPlan, OutputPathResolver};
+
+// Include/exclude path filtering
+pub use filter::PathFilter;
+
+// In-place editing
+pub use editor::ArchiveEditor;
+
+// Manifest-driven packing
+pub use manifest::{
+    parse_size, PackCompression, PackFile, PackFormat, PackManifest, PackPart, PackStage,
+};
+
+// Merging several archives into one
+pub use merge::{ArchiveMerger, MergeOverride, MergePriority, MergeReport};
+
+// Layered view over archives and loose files
+pub use vfs::{Vfs, VfsDirEntry, VfsFile, VfsLayer, VfsSource};
+
+// Zip/tar export and import
+pub use container::{export_archive, import_container, ContainerFormat};
+
+// Duplicate and conflict reports
+pub use conflicts::{
+    find_conflicts, find_conflicts_with_progress, Conflict, ConflictCopy, ConflictStatus,
+};
+
+// Read-only FUSE mounts
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+pub use mount::{mount_vfs, ArchiveFs};
+
+// Format-independent archive handle
+pub use ba2_reader::Ba2Archive;
+pub use handle::{open_archive, ArchiveHandle, BatchCallback, FormatOptions, OpenArchive};
+pub use reader::BsaArchive;
+pub use tes3_reader::Tes3Archive;
+
+// Machine-readable listings
+pub use listing::{
+    write_conflicts, write_entries, write_info, write_nif_reports, write_ref_report, ListFormat,
+};
+
+use anyhow::{Context, Result};
 use ba2::tes4::{ArchiveFlags, ArchiveTypes, Version};
-use ba2::{guess_format, FileFormat, Reader};
-use std::collections::HashSet;
+use ba2::{guess_format, FileFormat};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::path::Path;
 use tracing::debug;
 
@@ -84,55 +173,167 @@ pub fn detect_format(path: &Path) -> Option<ArchiveFormat> {
 }
 
 /// Universal archive file entry
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ArchiveFileEntry {
+    /// Path as stored in the archive
     pub path: String,
+    /// Decompressed size in bytes. For DX10 textures this is the sum of the
+    /// chunk data, without the DDS header added on extraction.
+    pub size: u64,
+    /// Size as stored in the archive
+    pub packed_size: u64,
+    /// Whether the stored data is compressed
+    pub compressed: bool,
+    /// Name hash as stored in the archive, in lowercase hex:
+    /// - TES3: `<hash64>`
+    /// - TES4: `<directory hash64>/<file hash64>`
+    /// - BA2: `<directory crc32>/<file crc32>.<extension>`
+    pub hash: String,
+    /// Offset of the stored data from the start of the archive file.
+    /// Points past any embedded name or size prefix; for BA2 this is the
+    /// first chunk.
+    pub offset: u64,
+    /// Texture header, only present for entries in DX10 BA2 archives
+    pub texture: Option<TextureInfo>,
+    /// Audio format of `.fuz`, `.xwm` and `.wav` entries. Reading it means
+    /// reading the file, so it is only filled in by [`read_audio_info`].
+    pub audio: Option<AudioInfo>,
+}
+
+impl ArchiveFileEntry {
+    /// Size of the file as extracted, including the DDS header of textures
+    pub fn extracted_size(&self) -> u64 {
+        self.size + self.texture.map_or(0, |t| t.header_size as u64)
+    }
+}
+
+/// DX10 texture header of a BA2 entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TextureInfo {
+    pub width: u32,
+    pub height: u32,
+    pub mip_count: u32,
+    /// `DXGI_FORMAT` value
+    pub dxgi_format: u32,
+    /// Number of chunks the mips are split into
+    pub chunks: u32,
+    /// Size of the DDS header added in front of the chunks on extraction
+    pub header_size: u32,
 }
 
 /// List files from any Bethesda archive (TES3 BSA, TES4 BSA, or BA2)
 pub fn list_archive_files(archive_path: &Path) -> Result<Vec<ArchiveFileEntry>> {
-    match detect_format(archive_path) {
-        Some(ArchiveFormat::Tes3Bsa) => {
-            let files = list_tes3_files(archive_path)?;
-            Ok(files
-                .into_iter()
-                .map(|f| ArchiveFileEntry { path: f.path })
-                .collect())
-        }
-        Some(ArchiveFormat::Bsa) => {
-            let files = list_files(archive_path)?;
-            Ok(files
-                .into_iter()
-                .map(|f| ArchiveFileEntry { path: f.path })
-                .collect())
+    Ok(open_archive(archive_path)?.entries()?.to_vec())
+}
+
+impl From<BsaFileEntry> for ArchiveFileEntry {
+    fn from(f: BsaFileEntry) -> Self {
+        Self {
+            path: f.path,
+            size: f.size,
+            packed_size: f.packed_size,
+            compressed: f.compressed,
+            hash: f.hash,
+            offset: f.offset,
+            texture: None,
+            audio: None,
         }
-        Some(ArchiveFormat::Ba2) => {
-            let files = list_ba2_files(archive_path)?;
-            Ok(files
-                .into_iter()
-                .map(|f| ArchiveFileEntry { path: f.path })
-                .collect())
+    }
+}
+
+impl From<Ba2FileEntry> for ArchiveFileEntry {
+    fn from(f: Ba2FileEntry) -> Self {
+        Self {
+            path: f.path,
+            size: f.size,
+            packed_size: f.packed_size,
+            compressed: f.compressed,
+            hash: f.hash,
+            offset: f.offset,
+            texture: f.texture,
+            audio: None,
         }
-        None => bail!("Unknown archive format: {}", archive_path.display()),
+    }
+}
+
+/// Archive-level information
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveInfo {
+    /// `tes3`, `tes4` or `fo4`
+    pub format: &'static str,
+    /// Header version number (e.g. 104 for TES4, 1/2/3/7/8 for BA2)
+    pub version: u32,
+    /// BA2 content format: `GNRL`, `DX10` or `GNMF`
+    pub ba2_format: Option<&'static str>,
+    /// TES4 archive flags bitfield
+    pub flags: Option<u32>,
+    /// TES4 archive types bitfield
+    pub types: Option<u32>,
+    /// Best guess at the game this archive was made for
+    pub game: Option<&'static str>,
+    pub file_count: usize,
+    /// Sum of decompressed entry sizes
+    pub total_size: u64,
+    /// Sum of stored entry sizes
+    pub total_packed_size: u64,
+}
+
+/// Read archive-level information and totals for any Bethesda archive
+pub fn archive_info(archive_path: &Path) -> Result<ArchiveInfo> {
+    ArchiveHandle::open(archive_path)?.info()
+}
+
+/// Memory-map an archive for reading.
+/// Reading through the mapping lets entry offsets be recovered from data pointers.
+pub(crate) fn map_archive(path: &Path) -> Result<Mmap> {
+    let file = File::open(path).with_context(|| format!("Failed to open: {}", path.display()))?;
+    // SAFETY: the mapping is read-only and only lives while the archive is
+    // open; the archive is not expected to be modified while we read it.
+    let mapping = unsafe { Mmap::map(&file) }
+        .with_context(|| format!("Failed to map: {}", path.display()))?;
+    Ok(mapping)
+}
+
+/// View a mapping as `'static`, so an archive parsed from it can be kept
+/// next to the mapping it borrows from.
+///
+/// # Safety
+/// The mapped pages don't move when the `Mmap` does, but they are unmapped
+/// when it drops. Callers must drop everything parsed from the slice first
+/// (declare the archive field before the mapping) and never hand out data
+/// borrowed from it.
+pub(crate) unsafe fn static_mapping(mapping: &Mmap) -> &'static [u8] {
+    std::slice::from_raw_parts(mapping.as_ptr(), mapping.len())
+}
+
+/// Up to `len` bytes from a decompressing reader, leaving the rest unread
+pub(crate) fn read_up_to(reader: impl Read, len: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Offset of `data` within `mapping`, or 0 if it doesn't point into it
+pub(crate) fn data_offset(mapping: &[u8], data: *const u8) -> u64 {
+    let base = mapping.as_ptr() as usize;
+    let addr = data as usize;
+    if addr >= base && addr <= base + mapping.len() {
+        (addr - base) as u64
+    } else {
+        0
     }
 }
 
 /// Extract a file from any Bethesda archive (TES3 BSA, TES4 BSA, or BA2)
-#[allow(dead_code)]
 pub fn extract_archive_file(archive_path: &Path, file_path: &str) -> Result<Vec<u8>> {
-    let format = detect_format(archive_path);
+    let archive = open_archive(archive_path)?;
     debug!(
         "extract_archive_file: archive={}, file={}, format={:?}",
         archive_path.display(),
         file_path,
-        format
+        archive.format()
     );
-    match format {
-        Some(ArchiveFormat::Tes3Bsa) => extract_tes3_file(archive_path, file_path),
-        Some(ArchiveFormat::Bsa) => extract_file(archive_path, file_path),
-        Some(ArchiveFormat::Ba2) => extract_ba2_file(archive_path, file_path),
-        None => bail!("Unknown archive format: {}", archive_path.display()),
-    }
+    archive.read(file_path)
 }
 
 /// Extract multiple files from any Bethesda archive in a single pass.
@@ -147,30 +348,7 @@ pub fn extract_archive_files_batch<F>(
 where
     F: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
 {
-    let format = detect_format(archive_path);
-    match format {
-        Some(ArchiveFormat::Tes3Bsa) => {
-            let wanted: HashSet<String> = wanted_files.iter().map(|p| p.to_lowercase()).collect();
-            extract_tes3_files_batch(archive_path, &wanted, callback)
-        }
-        Some(ArchiveFormat::Bsa) => {
-            // BSA uses backslash-separated paths
-            let wanted: HashSet<String> = wanted_files
-                .iter()
-                .map(|p| p.replace('/', "\\").to_lowercase())
-                .collect();
-            extract_bsa_files_batch(archive_path, &wanted, callback)
-        }
-        Some(ArchiveFormat::Ba2) => {
-            // BA2 uses forward-slash paths
-            let wanted: HashSet<String> = wanted_files
-                .iter()
-                .map(|p| p.replace('\\', "/").to_lowercase())
-                .collect();
-            extract_ba2_files_batch(archive_path, &wanted, callback)
-        }
-        None => bail!("Unknown archive format: {}", archive_path.display()),
-    }
+    open_archive(archive_path)?.read_batch(wanted_files, &callback)
 }
 
 /// Game version for archive creation
@@ -352,26 +530,62 @@ impl GameVersion {
     }
 }
 
+/// Order of the entries inside a written archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveOrder {
+    /// Sorted by name hash
+    #[default]
+    Hash,
+    /// Like the Creation Kit and Archive2: by hash for BSAs, grouped by
+    /// directory and then extension for BA2s, so files loaded together are
+    /// stored together
+    Vanilla,
+}
+
+impl ArchiveOrder {
+    /// Get display name for this order
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ArchiveOrder::Hash => "By name hash",
+            ArchiveOrder::Vanilla => "Like official archives",
+        }
+    }
+
+    /// Short CLI-friendly name
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            ArchiveOrder::Hash => "hash",
+            ArchiveOrder::Vanilla => "vanilla",
+        }
+    }
+
+    /// Parse from CLI name (case-insensitive)
+    pub fn from_cli_name(name: &str) -> Option<ArchiveOrder> {
+        let lower = name.to_lowercase();
+        ArchiveOrder::all()
+            .iter()
+            .find(|o| o.cli_name() == lower)
+            .copied()
+    }
+
+    /// Get all orders
+    pub fn all() -> &'static [ArchiveOrder] {
+        &[ArchiveOrder::Hash, ArchiveOrder::Vanilla]
+    }
+}
+
 /// Detect game version from archive format
 pub fn detect_game_version(archive_path: &Path) -> Option<GameVersion> {
-    match detect_format(archive_path) {
-        Some(ArchiveFormat::Tes3Bsa) => Some(GameVersion::Morrowind),
-        Some(ArchiveFormat::Ba2) => Some(GameVersion::Fallout4Fo76), // Default to FO4/FO76
-        Some(ArchiveFormat::Bsa) => {
-            // Try to detect version from BSA header
-            let result: Result<(ba2::tes4::Archive, ba2::tes4::ArchiveOptions), _> =
-                ba2::tes4::Archive::read(archive_path);
-            if let Ok((_, options)) = result {
-                match options.version() {
-                    Version::v103 => Some(GameVersion::Oblivion),
-                    Version::v104 => Some(GameVersion::Fallout3), // Default for v104
-                    Version::v105 => Some(GameVersion::SkyrimSE),
-                }
-            } else {
-                Some(GameVersion::Fallout3) // Default
-            }
-        }
-        None => None,
+    let format = detect_format(archive_path)?;
+    match open_archive(archive_path) {
+        Ok(archive) => Some(archive.options().game_version()),
+        // Fall back to the most common game for the format
+        Err(_) => Some(match format {
+            ArchiveFormat::Tes3Bsa => GameVersion::Morrowind,
+            ArchiveFormat::Bsa => GameVersion::Fallout3,
+            ArchiveFormat::Ba2 => GameVersion::Fallout4Fo76,
+        }),
     }
 }
 
@@ -386,13 +600,11 @@ pub fn default_flags_fo3() -> ArchiveFlags {
 }
 
 /// Default flags for Oblivion BSAs (no compression)
-#[allow(dead_code)]
 pub fn default_flags_oblivion() -> ArchiveFlags {
     ArchiveFlags::DIRECTORY_STRINGS | ArchiveFlags::FILE_STRINGS
 }
 
 /// Detect archive types from BSA name
-#[allow(dead_code)]
 pub fn detect_types(name: &str) -> ArchiveTypes {
     let name_lower = name.to_lowercase();
 
@@ -412,7 +624,6 @@ pub fn detect_types(name: &str) -> ArchiveTypes {
 }
 
 /// Detect BSA version from archive name
-#[allow(dead_code)]
 pub fn detect_version(name: &str) -> Version {
     let name_lower = name.to_lowercase();
 
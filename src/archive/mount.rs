@@ -0,0 +1,386 @@
+//! Read-only FUSE filesystem over archives (Linux)
+//!
+//! The directory tree is built from the [`Vfs`] listing when mounting, so
+//! browsing never touches file data. A file is decompressed through the
+//! readers the first time it is read after being opened, and the data is
+//! dropped again when the last handle to it is released.
+//!
+//! Names are matched case-insensitively, like the games do, but listed with
+//! the case stored in the archive.
+
+use anyhow::{bail, Context, Result};
+use fuser::{
+    Config, Errno, FileAttr, FileHandle, FileType, Filesystem, FopenFlags, Generation, INodeNo,
+    LockOwner, MountOption, OpenAccMode, OpenFlags, ReplyAttr, ReplyData, ReplyDirectory,
+    ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs, Request,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+use super::Vfs;
+
+/// How long the kernel may cache attributes and lookups; nothing ever changes
+const TTL: Duration = Duration::from_secs(3600);
+
+const BLOCK_SIZE: u32 = 4096;
+
+/// A file or directory in the mounted tree; its inode is its index + 1
+struct Node {
+    parent: usize,
+    name: String,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    /// Lowercased child name -> node index
+    Dir(BTreeMap<String, usize>),
+    File {
+        source: usize,
+        path: String,
+        size: u64,
+    },
+}
+
+/// Decompressed data of an open file, shared by all handles to it
+struct OpenFile {
+    node: usize,
+    data: Option<Arc<Vec<u8>>>,
+}
+
+/// Read-only FUSE filesystem serving the files of a [`Vfs`]
+pub struct ArchiveFs {
+    vfs: Vfs,
+    nodes: Vec<Node>,
+    mtime: SystemTime,
+    uid: u32,
+    gid: u32,
+    open: Mutex<HashMap<u64, OpenFile>>,
+    next_handle: AtomicU64,
+}
+
+impl ArchiveFs {
+    /// Build the directory tree for every file in `vfs`
+    pub fn new(vfs: Vfs) -> Self {
+        let mut nodes = vec![Node {
+            parent: 0,
+            name: String::new(),
+            kind: NodeKind::Dir(BTreeMap::new()),
+        }];
+
+        for file in vfs.files() {
+            let components: Vec<&str> = file
+                .path
+                .split(['/', '\\'])
+                .filter(|c| !c.is_empty())
+                .collect();
+            let Some((name, dirs)) = components.split_last() else {
+                continue;
+            };
+
+            let mut parent = 0;
+            for dir in dirs {
+                parent =
+                    child_or_insert(&mut nodes, parent, dir, || NodeKind::Dir(BTreeMap::new()));
+            }
+            child_or_insert(&mut nodes, parent, name, || NodeKind::File {
+                source: file.source,
+                path: file.path.clone(),
+                size: file.size,
+            });
+        }
+
+        // Mount time stands in for files that have no timestamp of their own
+        let mtime = vfs
+            .sources()
+            .iter()
+            .filter_map(|s| std::fs::metadata(s.path()).and_then(|m| m.modified()).ok())
+            .max()
+            .unwrap_or_else(SystemTime::now);
+
+        // Files belong to whoever mounted them
+        let (uid, gid) = std::fs::metadata("/proc/self")
+            .map(|m| (m.uid(), m.gid()))
+            .unwrap_or((0, 0));
+
+        Self {
+            vfs,
+            nodes,
+            mtime,
+            uid,
+            gid,
+            open: Mutex::new(HashMap::new()),
+            next_handle: AtomicU64::new(1),
+        }
+    }
+
+    fn node(&self, ino: INodeNo) -> Option<(usize, &Node)> {
+        let index = (ino.0 as usize).checked_sub(1)?;
+        self.nodes.get(index).map(|n| (index, n))
+    }
+
+    fn attr(&self, index: usize) -> FileAttr {
+        let node = &self.nodes[index];
+        let (kind, size, perm, nlink) = match &node.kind {
+            NodeKind::Dir(children) => {
+                let subdirs = children
+                    .values()
+                    .filter(|&&c| matches!(self.nodes[c].kind, NodeKind::Dir(_)))
+                    .count();
+                (FileType::Directory, 0, 0o555, 2 + subdirs as u32)
+            }
+            NodeKind::File { size, .. } => (FileType::RegularFile, *size, 0o444, 1),
+        };
+        FileAttr {
+            ino: INodeNo(index as u64 + 1),
+            size,
+            blocks: size.div_ceil(512),
+            atime: self.mtime,
+            mtime: self.mtime,
+            ctime: self.mtime,
+            crtime: self.mtime,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        }
+    }
+
+    /// Decompressed data for an open handle, reading it on first use
+    fn data(&self, fh: u64) -> std::result::Result<Arc<Vec<u8>>, Errno> {
+        let node = {
+            let open = self.open.lock().unwrap();
+            let Some(file) = open.get(&fh) else {
+                return Err(Errno::EBADF);
+            };
+            if let Some(data) = &file.data {
+                return Ok(data.clone());
+            }
+            file.node
+        };
+
+        let NodeKind::File { source, path, .. } = &self.nodes[node].kind else {
+            return Err(Errno::EISDIR);
+        };
+        debug!("Decompressing {}", path);
+        let data = match self.vfs.read_from(*source, path) {
+            Ok(data) => Arc::new(data),
+            Err(e) => {
+                warn!("Failed to read {}: {:#}", path, e);
+                return Err(Errno::EIO);
+            }
+        };
+
+        // Share the data with every other handle to the same file
+        let mut open = self.open.lock().unwrap();
+        for file in open.values_mut().filter(|f| f.node == node) {
+            file.data.get_or_insert_with(|| data.clone());
+        }
+        Ok(data)
+    }
+}
+
+fn child_or_insert(
+    nodes: &mut Vec<Node>,
+    parent: usize,
+    name: &str,
+    kind: impl FnOnce() -> NodeKind,
+) -> usize {
+    let key = name.to_lowercase();
+    if let NodeKind::Dir(children) = &nodes[parent].kind {
+        if let Some(&index) = children.get(&key) {
+            return index;
+        }
+    }
+    let index = nodes.len();
+    nodes.push(Node {
+        parent,
+        name: name.to_string(),
+        kind: kind(),
+    });
+    if let NodeKind::Dir(children) = &mut nodes[parent].kind {
+        children.insert(key, index);
+    }
+    index
+}
+
+impl Filesystem for ArchiveFs {
+    fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        let Some((
+            _,
+            Node {
+                kind: NodeKind::Dir(children),
+                ..
+            },
+        )) = self.node(parent)
+        else {
+            reply.error(Errno::ENOTDIR);
+            return;
+        };
+        match children.get(&name.to_string_lossy().to_lowercase()) {
+            Some(&index) => reply.entry(&TTL, &self.attr(index), Generation(0)),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        match self.node(ino) {
+            Some((index, _)) => reply.attr(&TTL, &self.attr(index)),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn open(&self, _req: &Request, ino: INodeNo, flags: OpenFlags, reply: ReplyOpen) {
+        let Some((index, node)) = self.node(ino) else {
+            reply.error(Errno::ENOENT);
+            return;
+        };
+        if matches!(node.kind, NodeKind::Dir(_)) {
+            reply.error(Errno::EISDIR);
+            return;
+        }
+        if flags.acc_mode() != OpenAccMode::O_RDONLY {
+            reply.error(Errno::EROFS);
+            return;
+        }
+
+        let fh = self.next_handle.fetch_add(1, Ordering::Relaxed);
+        let mut open = self.open.lock().unwrap();
+        // Reuse data another handle already decompressed
+        let data = open
+            .values()
+            .find(|f| f.node == index)
+            .and_then(|f| f.data.clone());
+        open.insert(fh, OpenFile { node: index, data });
+        reply.opened(FileHandle(fh), FopenFlags::FOPEN_KEEP_CACHE);
+    }
+
+    fn read(
+        &self,
+        _req: &Request,
+        _ino: INodeNo,
+        fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        match self.data(fh.0) {
+            Ok(data) => {
+                let start = (offset as usize).min(data.len());
+                let end = start.saturating_add(size as usize).min(data.len());
+                reply.data(&data[start..end]);
+            }
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn release(
+        &self,
+        _req: &Request,
+        _ino: INodeNo,
+        fh: FileHandle,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.open.lock().unwrap().remove(&fh.0);
+        reply.ok();
+    }
+
+    fn readdir(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some((
+            index,
+            Node {
+                kind: NodeKind::Dir(children),
+                parent,
+                ..
+            },
+        )) = self.node(ino)
+        else {
+            reply.error(Errno::ENOTDIR);
+            return;
+        };
+
+        let entries = [
+            (index, FileType::Directory, "."),
+            (*parent, FileType::Directory, ".."),
+        ]
+        .into_iter()
+        .chain(children.values().map(|&c| {
+            let kind = match self.nodes[c].kind {
+                NodeKind::Dir(_) => FileType::Directory,
+                NodeKind::File { .. } => FileType::RegularFile,
+            };
+            (c, kind, self.nodes[c].name.as_str())
+        }));
+
+        for (i, (node, kind, name)) in entries.enumerate().skip(offset as usize) {
+            if reply.add(INodeNo(node as u64 + 1), i as u64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn statfs(&self, _req: &Request, _ino: INodeNo, reply: ReplyStatfs) {
+        let blocks = self
+            .nodes
+            .iter()
+            .map(|n| match n.kind {
+                NodeKind::File { size, .. } => size.div_ceil(BLOCK_SIZE as u64),
+                NodeKind::Dir(_) => 0,
+            })
+            .sum();
+        reply.statfs(
+            blocks,
+            0,
+            0,
+            self.nodes.len() as u64,
+            0,
+            BLOCK_SIZE,
+            255,
+            BLOCK_SIZE,
+        );
+    }
+}
+
+/// Mount `vfs` read-only at `mountpoint` and serve it until unmounted
+/// (e.g. with `fusermount -u <mountpoint>`)
+pub fn mount_vfs(vfs: Vfs, mountpoint: &Path) -> Result<()> {
+    if !mountpoint.is_dir() {
+        bail!("Mount point is not a directory: {}", mountpoint.display());
+    }
+    let fs_name = vfs
+        .sources()
+        .last()
+        .map(|s| s.path().display().to_string())
+        .unwrap_or_else(|| "bsa-ba2-tool".to_string());
+
+    let mut config = Config::default();
+    config.mount_options = vec![
+        MountOption::RO,
+        MountOption::FSName(fs_name),
+        MountOption::Subtype("bsa-ba2-tool".to_string()),
+    ];
+    fuser::mount(ArchiveFs::new(vfs), mountpoint, &config)
+        .with_context(|| format!("Failed to mount at {}", mountpoint.display()))
+}
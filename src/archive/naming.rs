@@ -0,0 +1,159 @@
+//! Archive names the games load for a plugin
+//!
+//! The games only load a mod's archives on their own when they are named
+//! after one of its plugins:
+//! - Oblivion, Fallout 3, New Vegas: any `.bsa` whose name starts with the
+//!   plugin name (`Mod.bsa`, `Mod - Textures.bsa`)
+//! - Skyrim LE: `Mod.bsa`
+//! - Skyrim SE: `Mod.bsa` and `Mod - Textures.bsa`
+//! - Fallout 4, Fallout 76, Starfield: `Mod - Main.ba2` and
+//!   `Mod - Textures.ba2`
+//!
+//! Morrowind loads nothing by name; archives are listed in `Morrowind.ini`.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use super::{GameVersion, PLUGIN_EXTENSIONS};
+
+/// Archive names to pack for one plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginArchiveNames {
+    /// Archive for everything that isn't a texture, or for everything when
+    /// the game has no separate texture archive
+    pub main: String,
+    /// Archive for `textures\**.dds`, when the game loads one
+    pub textures: Option<String>,
+}
+
+/// Whether a file name has a plugin extension
+pub fn is_plugin_path(path: &str) -> bool {
+    path.rsplit_once('.')
+        .is_some_and(|(_, ext)| PLUGIN_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Plugins directly in a folder, sorted by name
+pub fn find_plugins(dir: &Path) -> Result<Vec<String>> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    let mut plugins = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && is_plugin_path(&name) {
+            plugins.push(name);
+        }
+    }
+    plugins.sort_by_key(|p| p.to_lowercase());
+    Ok(plugins)
+}
+
+/// Plugin name without its extension: `MyMod.esp` -> `MyMod`
+fn plugin_stem(plugin: &str) -> &str {
+    let name = plugin.rsplit(['/', '\\']).next().unwrap_or(plugin);
+    match name.rsplit_once('.') {
+        Some((stem, _)) if is_plugin_path(name) => stem,
+        _ => name,
+    }
+}
+
+/// The archive names `game` loads for `plugin` (with or without its
+/// extension)
+pub fn plugin_archive_names(plugin: &str, game: GameVersion) -> PluginArchiveNames {
+    let stem = plugin_stem(plugin);
+    if game.is_ba2() {
+        return PluginArchiveNames {
+            main: format!("{} - Main.ba2", stem),
+            textures: Some(format!("{} - Textures.ba2", stem)),
+        };
+    }
+    let textures = match game {
+        GameVersion::Morrowind | GameVersion::SkyrimLE => None,
+        _ => Some(format!("{} - Textures.bsa", stem)),
+    };
+    PluginArchiveNames {
+        main: format!("{}.bsa", stem),
+        textures,
+    }
+}
+
+/// Whether `game` loads an archive named `archive_name` for `plugin`
+pub fn loads_archive_for_plugin(game: GameVersion, plugin: &str, archive_name: &str) -> bool {
+    let stem = plugin_stem(plugin).to_lowercase();
+    let name = archive_name.to_lowercase();
+    match game {
+        GameVersion::Morrowind => false,
+        GameVersion::Oblivion | GameVersion::Fallout3 | GameVersion::FalloutNewVegas => {
+            name.starts_with(&stem) && name.ends_with(".bsa")
+        }
+        GameVersion::SkyrimLE => name == format!("{}.bsa", stem),
+        GameVersion::SkyrimSE => {
+            name == format!("{}.bsa", stem) || name == format!("{} - textures.bsa", stem)
+        }
+        _ => name == format!("{} - main.ba2", stem) || name == format!("{} - textures.ba2", stem),
+    }
+}
+
+/// Which names `game` loads for a plugin, for messages
+fn loaded_names(game: GameVersion, plugin: &str) -> String {
+    let stem = plugin_stem(plugin);
+    match game {
+        GameVersion::Morrowind => "no archives by name".to_string(),
+        GameVersion::Oblivion | GameVersion::Fallout3 | GameVersion::FalloutNewVegas => {
+            format!("archives whose names start with \"{}\"", stem)
+        }
+        GameVersion::SkyrimLE => format!("{}.bsa", stem),
+        GameVersion::SkyrimSE => format!("{}.bsa and {} - Textures.bsa", stem, stem),
+        _ => format!("{} - Main.ba2 and {} - Textures.ba2", stem, stem),
+    }
+}
+
+/// Why `game` won't load an archive named `archive_name` for any of
+/// `plugins`, or `None` when it will. With no plugins only the extension and
+/// the general shape of the name are checked.
+pub fn archive_load_warning(
+    game: GameVersion,
+    archive_name: &str,
+    plugins: &[String],
+) -> Option<String> {
+    let ext = if game.is_ba2() { ".ba2" } else { ".bsa" };
+    if !archive_name.to_lowercase().ends_with(ext) {
+        return Some(format!(
+            "{} reads {} archives, so it won't load {}",
+            game.display_name(),
+            ext,
+            archive_name
+        ));
+    }
+    if game.is_tes3() {
+        return Some(format!(
+            "Morrowind only loads archives listed under [Archives] in Morrowind.ini; add {} there",
+            archive_name
+        ));
+    }
+    if plugins.is_empty() {
+        let name = archive_name.to_lowercase();
+        if game.is_ba2() && !name.ends_with(" - main.ba2") && !name.ends_with(" - textures.ba2") {
+            return Some(format!(
+                "{} only loads archives named \"<plugin> - Main.ba2\" or \"<plugin> - Textures.ba2\", so it won't load {}",
+                game.display_name(),
+                archive_name
+            ));
+        }
+        return None;
+    }
+    if plugins
+        .iter()
+        .any(|p| loads_archive_for_plugin(game, p, archive_name))
+    {
+        return None;
+    }
+    let expected: Vec<String> = plugins.iter().map(|p| loaded_names(game, p)).collect();
+    Some(format!(
+        "{} won't load {}: for {} it only loads {}",
+        game.display_name(),
+        archive_name,
+        plugins.join(", "),
+        expected.join("; ")
+    ))
+}
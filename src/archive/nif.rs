@@ -0,0 +1,745 @@
+//! NIF mesh header inspection
+//!
+//! `inspect_nif` reads the header of a NIF file: version, user version,
+//! Bethesda stream version and how many blocks of each type it has. When the
+//! header lists block sizes (NIF 20.2.0.5 and later: Fallout 3 onwards) the
+//! blocks that name textures are read too, so `inspect_meshes` can check
+//! every texture a mesh uses against the files of a [`Vfs`].
+
+use anyhow::{bail, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{asset_lookup_path, PathFilter, Vfs};
+
+/// 3.1, the first version with a binary version number after the header line
+const VERSION_3_1: u32 = 0x0301_0000;
+/// 5.0.0.1, block types move from the blocks into the header
+const VERSION_5_0_0_1: u32 = 0x0500_0001;
+const VERSION_10_0_1_2: u32 = 0x0A00_0102;
+const VERSION_10_0_1_8: u32 = 0x0A00_0108;
+const VERSION_10_1_0_0: u32 = 0x0A01_0000;
+const VERSION_20_0_0_3: u32 = 0x1400_0003;
+const VERSION_20_0_0_4: u32 = 0x1400_0004;
+const VERSION_20_0_0_5: u32 = 0x1400_0005;
+const VERSION_20_1_0_1: u32 = 0x1401_0001;
+const VERSION_20_1_0_3: u32 = 0x1401_0003;
+/// 20.2.0.5, the header lists the size of every block
+const VERSION_20_2_0_5: u32 = 0x1402_0005;
+const VERSION_20_2_0_7: u32 = 0x1402_0007;
+
+/// Longest header line accepted, including the newline
+const MAX_HEADER_LINE: usize = 128;
+
+/// Number of blocks of one type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NifBlockType {
+    pub name: String,
+    pub count: u32,
+}
+
+/// Header of a NIF file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NifHeader {
+    /// File version, e.g. `20.2.0.7`
+    pub version: String,
+    /// User version, 0 before NIF 10.0.1.8
+    pub user_version: u32,
+    /// Bethesda stream version (e.g. 34 for Fallout 3, 83 for Skyrim, 100
+    /// for Skyrim SE, 130 for Fallout 4), only in Bethesda NIFs
+    pub bs_version: Option<u32>,
+    /// Total number of blocks
+    pub block_count: u32,
+    /// Blocks per type, in the order the header lists the types. Empty
+    /// before NIF 5.0.0.1, where types aren't in the header.
+    pub block_types: Vec<NifBlockType>,
+}
+
+/// Header and texture references of a NIF file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NifInfo {
+    pub header: NifHeader,
+    /// Texture paths as written in the mesh, in block order without
+    /// duplicates. `None` when the header has no block sizes, so the blocks
+    /// can't be read.
+    pub textures: Option<Vec<String>>,
+}
+
+/// Bounds-checked little-endian reader
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let Some(bytes) = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+        else {
+            bail!(
+                "Truncated at offset {} (needs {} more bytes, {} left)",
+                self.pos,
+                len,
+                self.data.len().saturating_sub(self.pos)
+            );
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    /// `u32` length, then the characters
+    fn sized_string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    /// `u8` length including a trailing NUL, then the characters
+    fn export_string(&mut self) -> Result<String> {
+        let len = self.u8()? as usize;
+        let bytes = self.bytes(len)?;
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// `a.b.c.d` from a packed version number
+fn version_string(version: u32) -> String {
+    let [a, b, c, d] = version.to_be_bytes();
+    format!("{}.{}.{}.{}", a, b, c, d)
+}
+
+/// Packed version number from the header line version, e.g. `4.0.0.2`
+fn parse_version(text: &str) -> Option<u32> {
+    let mut parts = [0u8; 4];
+    let mut count = 0;
+    for part in text.split('.') {
+        *parts.get_mut(count)? = part.parse().ok()?;
+        count += 1;
+    }
+    (count >= 2).then(|| u32::from_be_bytes(parts))
+}
+
+/// Read the header of a NIF file and, when it lists block sizes, the
+/// texture paths of its shader and texture blocks
+pub fn inspect_nif(data: &[u8]) -> Result<NifInfo> {
+    let line_end = data.iter().take(MAX_HEADER_LINE).position(|&b| b == b'\n');
+    let line = line_end.map(|end| String::from_utf8_lossy(&data[..end]));
+    let Some(version_text) = line.as_deref().and_then(|line| {
+        [
+            "Gamebryo File Format, Version ",
+            "NetImmerse File Format, Version ",
+        ]
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))
+    }) else {
+        bail!("Not a NIF file (no Gamebryo/NetImmerse header line)");
+    };
+    let Some(line_version) = parse_version(version_text) else {
+        bail!("Unknown NIF version: {}", version_text);
+    };
+
+    let mut reader = Reader::new(data, line_end.unwrap() + 1);
+    let version = if line_version >= VERSION_3_1 {
+        reader.u32()?
+    } else {
+        line_version
+    };
+    if version >= VERSION_20_0_0_3 && reader.u8()? != 1 {
+        bail!("Big-endian NIF files are not supported");
+    }
+    let user_version = if version >= VERSION_10_0_1_8 {
+        reader.u32()?
+    } else {
+        0
+    };
+    let block_count = reader.u32()?;
+
+    let has_bs_header = (version == VERSION_10_0_1_2
+        || version == VERSION_20_2_0_7
+        || version == VERSION_20_0_0_5
+        || ((VERSION_10_1_0_0..=VERSION_20_0_0_4).contains(&version) && user_version <= 11))
+        && user_version >= 3;
+    let bs_version = if has_bs_header {
+        let bs_version = reader.u32()?;
+        reader.export_string()?; // Author
+        if bs_version > 130 {
+            reader.skip(4)?;
+        }
+        if bs_version < 131 {
+            reader.export_string()?; // Process script
+        }
+        reader.export_string()?; // Export script
+        if bs_version == 130 {
+            reader.export_string()?; // Max file path
+        }
+        Some(bs_version)
+    } else {
+        None
+    };
+
+    let mut header = NifHeader {
+        version: version_string(version),
+        user_version,
+        bs_version,
+        block_count,
+        block_types: Vec::new(),
+    };
+    if version < VERSION_5_0_0_1 {
+        return Ok(NifInfo {
+            header,
+            textures: None,
+        });
+    }
+
+    let type_count = reader.u16()? as usize;
+    let type_names = (0..type_count)
+        .map(|_| reader.sized_string())
+        .collect::<Result<Vec<_>>>()?;
+    // Each block has a 2-byte type index, which bounds a corrupt count
+    let mut block_types = Vec::with_capacity((block_count as usize).min(data.len() / 2));
+    let mut counts = vec![0u32; type_count];
+    for _ in 0..block_count {
+        // The top bit flags PhysX blocks in newer versions
+        let index = (reader.u16()? & 0x7FFF) as usize;
+        let Some(count) = counts.get_mut(index) else {
+            bail!(
+                "Block type index {} out of range ({} types)",
+                index,
+                type_count
+            );
+        };
+        *count += 1;
+        block_types.push(index);
+    }
+    header.block_types = type_names
+        .iter()
+        .zip(&counts)
+        .filter(|(_, &count)| count > 0)
+        .map(|(name, &count)| NifBlockType {
+            name: name.clone(),
+            count,
+        })
+        .collect();
+
+    if version < VERSION_20_2_0_5 {
+        return Ok(NifInfo {
+            header,
+            textures: None,
+        });
+    }
+    let block_sizes = (0..block_count)
+        .map(|_| reader.u32())
+        .collect::<Result<Vec<_>>>()?;
+    let strings = if version >= VERSION_20_1_0_1 {
+        let count = reader.u32()?;
+        reader.skip(4)?; // Longest string
+        (0..count)
+            .map(|_| reader.sized_string())
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+    if version >= VERSION_20_1_0_3 {
+        let groups = reader.u32()? as usize;
+        reader.skip(groups * 4)?;
+    }
+
+    let mut textures: Vec<String> = Vec::new();
+    let mut offset = reader.pos;
+    for (&type_index, &size) in block_types.iter().zip(&block_sizes) {
+        let end = offset.saturating_add(size as usize);
+        // Blocks past the end of the file can't be read
+        let Some(bytes) = data.get(offset..end) else {
+            break;
+        };
+        offset = end;
+        // Each block only sees its own bytes, and one that doesn't parse as
+        // expected is skipped; the header information is still good
+        let found = block_textures(
+            Reader::new(bytes, 0),
+            &type_names[type_index],
+            bs_version,
+            &strings,
+        )
+        .unwrap_or_default();
+        for texture in found {
+            let texture = texture.trim().to_string();
+            let valid = !texture.is_empty() && !texture.chars().any(char::is_control);
+            if valid && !textures.iter().any(|t| t.eq_ignore_ascii_case(&texture)) {
+                textures.push(texture);
+            }
+        }
+    }
+
+    Ok(NifInfo {
+        header,
+        textures: Some(textures),
+    })
+}
+
+/// Skip the `NiObjectNET` fields: name, extra data and controller
+fn skip_object_net(block: &mut Reader) -> Result<()> {
+    block.skip(4)?;
+    let extra_data = block.u32()? as usize;
+    block.skip(extra_data.saturating_mul(4))?;
+    block.skip(4)
+}
+
+/// Texture paths named by one block
+fn block_textures(
+    mut block: Reader,
+    type_name: &str,
+    bs_version: Option<u32>,
+    strings: &[String],
+) -> Result<Vec<String>> {
+    let bs_version = bs_version.unwrap_or(0);
+    let mut textures = Vec::new();
+    match type_name {
+        "BSShaderTextureSet" => {
+            let count = block.i32()?.max(0);
+            for _ in 0..count {
+                textures.push(block.sized_string()?);
+            }
+        }
+        "NiSourceTexture" => {
+            skip_object_net(&mut block)?;
+            if block.u8()? != 0 {
+                let index = block.i32()?;
+                if let Some(name) = usize::try_from(index).ok().and_then(|i| strings.get(i)) {
+                    textures.push(name.clone());
+                }
+            }
+        }
+        // Fallout 3 and New Vegas
+        "BSShaderNoLightingProperty" if bs_version <= 34 => {
+            skip_object_net(&mut block)?;
+            // Flags, shader type, shader flags 1 and 2, environment map
+            // scale, texture clamp mode
+            block.skip(22)?;
+            textures.push(block.sized_string()?);
+        }
+        // Skyrim and Fallout 4
+        "BSEffectShaderProperty" if (83..=130).contains(&bs_version) => {
+            skip_object_net(&mut block)?;
+            // Shader flags 1 and 2, UV offset and scale
+            block.skip(24)?;
+            textures.push(block.sized_string()?); // Source texture
+
+            // Clamp mode, lighting influence, env map LOD, falloff angles
+            // and opacities, base color, its scale, soft falloff depth
+            block.skip(44)?;
+            textures.push(block.sized_string()?); // Greyscale texture
+            if bs_version == 130 {
+                // Environment map, normal map, environment mask
+                for _ in 0..3 {
+                    textures.push(block.sized_string()?);
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(textures)
+}
+
+/// A texture referenced by a mesh
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NifTexture {
+    /// Path as written in the mesh
+    pub path: String,
+    /// Path the game loads, under `textures\`
+    pub resolved: String,
+    /// Whether no source of the VFS has the file
+    pub missing: bool,
+}
+
+/// Inspection result for one mesh
+#[derive(Debug, Clone, Serialize)]
+pub struct NifReport {
+    /// Path as stored in the winning source
+    pub path: String,
+    /// Archive or loose directory the mesh comes from
+    pub source: PathBuf,
+    /// Header, `None` if the file couldn't be read
+    pub header: Option<NifHeader>,
+    /// Referenced textures; empty when they couldn't be listed
+    pub textures: Vec<NifTexture>,
+    /// Why the header or the textures couldn't be read
+    pub error: Option<String>,
+}
+
+impl NifReport {
+    /// Number of referenced textures no source has
+    pub fn missing_count(&self) -> usize {
+        self.textures.iter().filter(|t| t.missing).count()
+    }
+}
+
+/// `textures\...` path the game loads for a texture reference. Paths are
+/// relative to `textures\` when they don't start with it, and anything up
+/// to a `data\` folder is dropped.
+pub fn texture_lookup_path(reference: &str) -> String {
+    asset_lookup_path(reference, "textures")
+}
+
+/// Inspect every `.nif` of a VFS that passes `filter`, sorted by path
+pub fn inspect_meshes(vfs: &Vfs, filter: &PathFilter) -> Result<Vec<NifReport>> {
+    inspect_meshes_with_progress(vfs, filter, |_, _, _| {})
+}
+
+/// Inspect meshes, calling `progress(done, total, path)` as each one is read
+pub fn inspect_meshes_with_progress<F>(
+    vfs: &Vfs,
+    filter: &PathFilter,
+    progress: F,
+) -> Result<Vec<NifReport>>
+where
+    F: Fn(usize, usize, &str) + Send + Sync,
+{
+    let meshes: Vec<_> = vfs
+        .files()
+        .filter(|f| f.path.to_lowercase().ends_with(".nif") && filter.matches(&f.path))
+        .collect();
+    let total = meshes.len();
+    let done = AtomicUsize::new(0);
+
+    meshes
+        .par_iter()
+        .map(|file| {
+            let source = vfs.sources()[file.source].path().to_path_buf();
+            let data = vfs.read_from(file.source, &file.path)?;
+            let mut report = NifReport {
+                path: file.path.clone(),
+                source,
+                header: None,
+                textures: Vec::new(),
+                error: None,
+            };
+            match inspect_nif(&data) {
+                Ok(info) => {
+                    let version = info.header.version.clone();
+                    report.header = Some(info.header);
+                    match info.textures {
+                        Some(textures) => {
+                            report.textures = textures
+                                .into_iter()
+                                .map(|path| {
+                                    let resolved = texture_lookup_path(&path);
+                                    NifTexture {
+                                        missing: vfs.lookup(&resolved).is_none(),
+                                        path,
+                                        resolved,
+                                    }
+                                })
+                                .collect();
+                        }
+                        None => {
+                            report.error = Some(format!(
+                                "Textures can't be listed for NIF {} (no block sizes)",
+                                version
+                            ));
+                        }
+                    }
+                }
+                Err(e) => report.error = Some(format!("{:#}", e)),
+            }
+
+            let current = done.fetch_add(1, Ordering::Relaxed) + 1;
+            progress(current, total, &file.path);
+            Ok(report)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sized(out: &mut Vec<u8>, text: &str) {
+        out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        out.extend_from_slice(text.as_bytes());
+    }
+
+    fn export(out: &mut Vec<u8>, text: &str) {
+        out.push(text.len() as u8 + 1);
+        out.extend_from_slice(text.as_bytes());
+        out.push(0);
+    }
+
+    /// A Skyrim (20.2.0.7, stream 83) NIF with the given blocks and string
+    /// table
+    fn skyrim_nif(blocks: &[(&str, Vec<u8>)], strings: &[&str]) -> Vec<u8> {
+        let mut out = b"Gamebryo File Format, Version 20.2.0.7\n".to_vec();
+        out.extend_from_slice(&VERSION_20_2_0_7.to_le_bytes());
+        out.push(1); // Little-endian
+        out.extend_from_slice(&12u32.to_le_bytes()); // User version
+        out.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+        out.extend_from_slice(&83u32.to_le_bytes()); // Stream version
+        export(&mut out, "author");
+        export(&mut out, "process");
+        export(&mut out, "export");
+
+        let mut types: Vec<&str> = Vec::new();
+        for (name, _) in blocks {
+            if !types.contains(name) {
+                types.push(name);
+            }
+        }
+        out.extend_from_slice(&(types.len() as u16).to_le_bytes());
+        for name in &types {
+            sized(&mut out, name);
+        }
+        for (name, _) in blocks {
+            let index = types.iter().position(|t| t == name).unwrap() as u16;
+            out.extend_from_slice(&index.to_le_bytes());
+        }
+        for (_, data) in blocks {
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+        out.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes()); // Longest string
+        for string in strings {
+            sized(&mut out, string);
+        }
+        out.extend_from_slice(&0u32.to_le_bytes()); // Groups
+        for (_, data) in blocks {
+            out.extend_from_slice(data);
+        }
+        out
+    }
+
+    fn texture_set(paths: &[&str]) -> Vec<u8> {
+        let mut block = (paths.len() as i32).to_le_bytes().to_vec();
+        for path in paths {
+            sized(&mut block, path);
+        }
+        block
+    }
+
+    /// `NiObjectNET` with no extra data or controller
+    fn object_net() -> Vec<u8> {
+        let mut block = 0u32.to_le_bytes().to_vec(); // Name
+        block.extend_from_slice(&0u32.to_le_bytes()); // Extra data
+        block.extend_from_slice(&(-1i32).to_le_bytes()); // Controller
+        block
+    }
+
+    #[test]
+    fn reads_header_and_textures() {
+        let data = skyrim_nif(
+            &[
+                ("NiNode", vec![0; 16]),
+                (
+                    "BSShaderTextureSet",
+                    texture_set(&["textures\\armor\\a_d.dds", "", "Armor\\a_n.dds "]),
+                ),
+                (
+                    "BSShaderTextureSet",
+                    texture_set(&["TEXTURES\\ARMOR\\A_D.DDS"]),
+                ),
+            ],
+            &["Scene Root"],
+        );
+        let info = inspect_nif(&data).unwrap();
+
+        assert_eq!(
+            info.header,
+            NifHeader {
+                version: "20.2.0.7".to_string(),
+                user_version: 12,
+                bs_version: Some(83),
+                block_count: 3,
+                block_types: vec![
+                    NifBlockType {
+                        name: "NiNode".to_string(),
+                        count: 1,
+                    },
+                    NifBlockType {
+                        name: "BSShaderTextureSet".to_string(),
+                        count: 2,
+                    },
+                ],
+            }
+        );
+        // Trimmed, without empty slots or case-insensitive duplicates
+        assert_eq!(
+            info.textures.unwrap(),
+            ["textures\\armor\\a_d.dds", "Armor\\a_n.dds"]
+        );
+    }
+
+    #[test]
+    fn blocks_are_read_within_their_size() {
+        // Claims two paths but holds one; the next block's bytes would read
+        // as a second path if the set could run past its end
+        let mut short_set = texture_set(&["textures\\a_d.dds"]);
+        short_set[..4].copy_from_slice(&2i32.to_le_bytes());
+        let mut next = Vec::new();
+        sized(&mut next, "garbage.dds");
+
+        let data = skyrim_nif(
+            &[
+                ("BSShaderTextureSet", short_set),
+                ("NiExtraData", next),
+                ("BSShaderTextureSet", texture_set(&["textures\\b_d.dds"])),
+            ],
+            &[],
+        );
+        let info = inspect_nif(&data).unwrap();
+        assert_eq!(info.textures.unwrap(), ["textures\\b_d.dds"]);
+    }
+
+    #[test]
+    fn truncated_blocks_keep_the_header() {
+        let mut data = skyrim_nif(
+            &[("BSShaderTextureSet", texture_set(&["textures\\a_d.dds"]))],
+            &[],
+        );
+        data.truncate(data.len() - 4);
+        let info = inspect_nif(&data).unwrap();
+        assert_eq!(info.header.block_count, 1);
+        assert_eq!(info.textures.unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(inspect_nif(b"DDS |").is_err());
+        assert!(inspect_nif(b"Gamebryo File Format, Version x\n").is_err());
+    }
+
+    #[test]
+    fn source_texture_names_come_from_the_string_table() {
+        let mut block = object_net();
+        block.push(1); // External file
+        block.extend_from_slice(&1i32.to_le_bytes());
+        let strings = ["Scene Root".to_string(), "textures\\sky.dds".to_string()];
+
+        let textures = block_textures(
+            Reader::new(&block, 0),
+            "NiSourceTexture",
+            Some(83),
+            &strings,
+        )
+        .unwrap();
+        assert_eq!(textures, ["textures\\sky.dds"]);
+    }
+
+    #[test]
+    fn effect_shader_textures_depend_on_the_stream_version() {
+        let effect_shader = |maps: &[&str]| {
+            let mut block = object_net();
+            block.extend_from_slice(&[0; 24]);
+            sized(&mut block, "textures\\fx\\glow.dds");
+            block.extend_from_slice(&[0; 44]);
+            sized(&mut block, "textures\\fx\\grey.dds");
+            for map in maps {
+                sized(&mut block, map);
+            }
+            block
+        };
+
+        let skyrim = effect_shader(&[]);
+        assert_eq!(
+            block_textures(
+                Reader::new(&skyrim, 0),
+                "BSEffectShaderProperty",
+                Some(83),
+                &[]
+            )
+            .unwrap(),
+            ["textures\\fx\\glow.dds", "textures\\fx\\grey.dds"]
+        );
+
+        let fallout4 = effect_shader(&["env.dds", "normal.dds", "mask.dds"]);
+        assert_eq!(
+            block_textures(
+                Reader::new(&fallout4, 0),
+                "BSEffectShaderProperty",
+                Some(130),
+                &[]
+            )
+            .unwrap()
+            .len(),
+            5
+        );
+
+        // Other games lay the block out differently
+        assert!(block_textures(
+            Reader::new(&skyrim, 0),
+            "BSEffectShaderProperty",
+            Some(34),
+            &[]
+        )
+        .unwrap()
+        .is_empty());
+    }
+
+    #[test]
+    fn no_lighting_property_texture() {
+        let mut block = object_net();
+        block.extend_from_slice(&[0; 22]);
+        sized(&mut block, "textures\\effects\\fx.dds");
+        assert_eq!(
+            block_textures(
+                Reader::new(&block, 0),
+                "BSShaderNoLightingProperty",
+                Some(34),
+                &[]
+            )
+            .unwrap(),
+            ["textures\\effects\\fx.dds"]
+        );
+    }
+
+    #[test]
+    fn texture_lookup_paths() {
+        assert_eq!(
+            texture_lookup_path("Armor/Iron/Cuirass_d.DDS"),
+            "textures\\armor\\iron\\cuirass_d.dds"
+        );
+        assert_eq!(
+            texture_lookup_path("textures\\armor\\a.dds"),
+            "textures\\armor\\a.dds"
+        );
+        assert_eq!(
+            texture_lookup_path("C:\\Games\\Skyrim\\Data\\Textures\\sky.dds"),
+            "textures\\sky.dds"
+        );
+        assert_eq!(
+            texture_lookup_path(" .\\textures\\a.dds "),
+            "textures\\a.dds"
+        );
+        // Only a whole `textures` folder counts
+        assert_eq!(
+            texture_lookup_path("texturesets\\a.dds"),
+            "textures\\texturesets\\a.dds"
+        );
+    }
+}
@@ -0,0 +1,880 @@
+//! Asset references of game plugins
+//!
+//! `plugin_references` walks the records of an ESP/ESM/ESL and collects the
+//! model, texture, sound and script paths they name. `check_references`
+//! looks each one up in a [`Vfs`], following the textures of the meshes it
+//! finds, and lists the archived assets that nothing refers to.
+
+use anyhow::{bail, Context, Result};
+use flate2::read::ZlibDecoder;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::warn;
+
+use super::filter::normalize_path;
+use super::{inspect_nif, texture_lookup_path, Vfs};
+
+/// Record flag: the record data is zlib compressed, after its `u32` size
+const COMPRESSED_FLAG: u32 = 0x0004_0000;
+
+/// File extensions of plugins
+pub const PLUGIN_EXTENSIONS: [&str; 3] = ["esp", "esm", "esl"];
+
+/// Folders of assets the game finds by name instead of through a record
+/// (voice files, FaceGen data, terrain LOD). They are never reported as
+/// unreferenced.
+const DERIVED_FOLDERS: [&str; 6] = [
+    "sound\\voice\\",
+    "\\facegendata\\",
+    "meshes\\terrain\\",
+    "textures\\terrain\\",
+    "meshes\\lod\\",
+    "textures\\lod\\",
+];
+
+/// What kind of asset a path refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetKind {
+    /// A `.nif` under `meshes\`
+    Model,
+    /// A texture or icon under `textures\` (`icons\` for Morrowind)
+    Texture,
+    /// A sound effect or music file under `sound\` or `music\`
+    Sound,
+    /// A compiled Papyrus script under `scripts\`
+    Script,
+}
+
+impl AssetKind {
+    /// Get display name for this kind
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            AssetKind::Model => "Model",
+            AssetKind::Texture => "Texture",
+            AssetKind::Sound => "Sound",
+            AssetKind::Script => "Script",
+        }
+    }
+
+    /// Short CLI-friendly name
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            AssetKind::Model => "model",
+            AssetKind::Texture => "texture",
+            AssetKind::Sound => "sound",
+            AssetKind::Script => "script",
+        }
+    }
+
+    /// Get all kinds
+    pub fn all() -> &'static [AssetKind] {
+        &[
+            AssetKind::Model,
+            AssetKind::Texture,
+            AssetKind::Sound,
+            AssetKind::Script,
+        ]
+    }
+
+    /// File extensions a reference of this kind can have
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            AssetKind::Model => &["nif"],
+            AssetKind::Texture => &["dds", "tga", "bmp"],
+            AssetKind::Sound => &["wav", "xwm", "fuz", "mp3"],
+            AssetKind::Script => &["pex"],
+        }
+    }
+
+    /// Kind of a file, from its extension
+    pub fn from_path(path: &str) -> Option<AssetKind> {
+        let (_, ext) = path.rsplit_once('.')?;
+        let ext = ext.to_lowercase();
+        AssetKind::all()
+            .iter()
+            .find(|k| k.extensions().contains(&ext.as_str()))
+            .copied()
+    }
+}
+
+/// An asset named by a plugin record or a mesh
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetReference {
+    /// What kind of asset it is
+    pub kind: AssetKind,
+    /// Path as written in the plugin or mesh
+    pub path: String,
+    /// Path to look up in the archives: lowercase, backslashes, starting
+    /// with the asset folder
+    pub resolved: String,
+    /// What names it: `Mod.esp WEAP 01000800 IronSword`, or the mesh path
+    pub referenced_by: String,
+}
+
+/// An archived asset that nothing refers to
+#[derive(Debug, Clone, Serialize)]
+pub struct UnreferencedAsset {
+    /// What kind of asset it is
+    pub kind: AssetKind,
+    /// Path as stored in the archive
+    pub path: String,
+    /// Archive it is in
+    pub source: PathBuf,
+}
+
+/// Plugin references checked against archives and loose folders
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RefReport {
+    /// Number of distinct assets referenced by the plugins and by the
+    /// meshes that were found
+    pub references: usize,
+    /// Referenced assets that no source has
+    pub missing: Vec<AssetReference>,
+    /// Archived meshes, textures, sounds and scripts nothing refers to
+    pub unreferenced: Vec<UnreferencedAsset>,
+}
+
+/// Lookup path of an asset reference: lowercase with backslashes, anything
+/// up to `data\` removed and `folder` prepended when it isn't there
+pub fn asset_lookup_path(reference: &str, folder: &str) -> String {
+    let path = reference.trim().replace('/', "\\").to_lowercase();
+    let mut path = path.trim_start_matches(['\\', '.']);
+    for data in ["data\\", "data files\\"] {
+        let marker = format!("{}{}\\", data, folder);
+        if let Some(pos) = path.rfind(&marker) {
+            if pos == 0 || path[..pos].ends_with('\\') {
+                path = &path[pos + data.len()..];
+                break;
+            }
+        }
+    }
+    if path
+        .strip_prefix(folder)
+        .is_some_and(|rest| rest.starts_with('\\'))
+    {
+        path.to_string()
+    } else {
+        format!("{}\\{}", folder, path)
+    }
+}
+
+/// Read a plugin file and collect its asset references
+pub fn read_plugin_references(path: &Path) -> Result<Vec<AssetReference>> {
+    let data =
+        std::fs::read(path).with_context(|| format!("Failed to read: {}", path.display()))?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    plugin_references(&data, &name)
+        .with_context(|| format!("Failed to read plugin: {}", path.display()))
+}
+
+/// Collect the model, texture, sound and script paths named by the records
+/// of a plugin, once per asset. `plugin_name` goes into
+/// [`AssetReference::referenced_by`].
+pub fn plugin_references(data: &[u8], plugin_name: &str) -> Result<Vec<AssetReference>> {
+    let mut references = References::default();
+    match data.get(..4) {
+        Some(b"TES3") => tes3_references(data, plugin_name, &mut references)?,
+        Some(b"TES4") => tes4_references(data, plugin_name, &mut references)?,
+        _ => bail!("Not a plugin file (no TES3/TES4 header record)"),
+    }
+    Ok(references.list)
+}
+
+/// References collected so far, one per lookup path
+#[derive(Default)]
+struct References {
+    seen: HashSet<String>,
+    list: Vec<AssetReference>,
+}
+
+impl References {
+    /// Add a reference unless its path is empty, has the wrong extension for
+    /// its kind, or was added before
+    fn add(&mut self, kind: AssetKind, path: &str, folder: &str, referenced_by: &str) {
+        let path = path.trim();
+        if path.is_empty() || path.chars().any(char::is_control) {
+            return;
+        }
+        if AssetKind::from_path(path) != Some(kind) {
+            return;
+        }
+        let resolved = asset_lookup_path(path, folder);
+        if self.seen.insert(resolved.clone()) {
+            self.list.push(AssetReference {
+                kind,
+                path: path.to_string(),
+                resolved,
+                referenced_by: referenced_by.to_string(),
+            });
+        }
+    }
+
+    fn push(&mut self, reference: AssetReference) {
+        if self.seen.insert(reference.resolved.clone()) {
+            self.list.push(reference);
+        }
+    }
+}
+
+/// Walk the records of an Oblivion or later plugin. Groups are stepped into,
+/// so every record is visited in file order.
+fn tes4_references(data: &[u8], plugin_name: &str, references: &mut References) -> Result<()> {
+    // Oblivion record headers are 20 bytes, later games add 4 bytes of
+    // version control info
+    let header_len = if data.get(20..24) == Some(b"HEDR") {
+        20
+    } else {
+        24
+    };
+    let mut pos = 0;
+    while pos < data.len() {
+        let Some(header) = data.get(pos..pos + header_len) else {
+            bail!("Truncated record header at offset {}", pos);
+        };
+        let tag = &header[..4];
+        if tag == b"GRUP" {
+            pos += header_len;
+            continue;
+        }
+        let size = read_u32(header, 4) as usize;
+        let flags = read_u32(header, 8);
+        let form_id = read_u32(header, 12);
+        let start = pos + header_len;
+        let Some(body) = start.checked_add(size).and_then(|end| data.get(start..end)) else {
+            bail!(
+                "Truncated {} record at offset {}",
+                String::from_utf8_lossy(tag),
+                pos
+            );
+        };
+        pos = start + size;
+
+        let body = if flags & COMPRESSED_FLAG != 0 {
+            Cow::Owned(decompress_record(body).with_context(|| {
+                format!(
+                    "Failed to decompress {} record {:08X}",
+                    String::from_utf8_lossy(tag),
+                    form_id
+                )
+            })?)
+        } else {
+            Cow::Borrowed(body)
+        };
+        let fields = tes4_subrecords(&body);
+        let found = tes4_record_references(tag, &fields);
+        if found.is_empty() {
+            continue;
+        }
+        let mut owner = format!(
+            "{} {} {:08X}",
+            plugin_name,
+            String::from_utf8_lossy(tag),
+            form_id
+        );
+        if let Some((_, edid)) = fields.iter().find(|(t, _)| *t == b"EDID") {
+            owner.push(' ');
+            owner.push_str(&zstring(edid));
+        }
+        for (kind, path, folder) in found {
+            references.add(kind, &path, folder, &owner);
+        }
+    }
+    Ok(())
+}
+
+/// Inflate the data of a compressed record
+fn decompress_record(body: &[u8]) -> Result<Vec<u8>> {
+    if body.len() < 4 {
+        bail!("Compressed record too short");
+    }
+    let size = read_u32(body, 0) as usize;
+    let mut data = Vec::with_capacity(size.min(body.len().saturating_mul(16)));
+    ZlibDecoder::new(&body[4..]).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Subrecords of a TES4-style record: 4-byte type, `u16` size, data. An
+/// `XXXX` subrecord holds the `u32` size of the one after it. Stops at the
+/// first truncated subrecord.
+fn tes4_subrecords(body: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    let mut next_size = None;
+    while let Some(header) = body.get(pos..pos + 6) {
+        let tag = &header[..4];
+        let size = next_size.take().unwrap_or(read_u16(header, 4) as usize);
+        let Some(field) = body.get(pos + 6..pos + 6 + size) else {
+            break;
+        };
+        pos += 6 + size;
+        if tag == b"XXXX" && size == 4 {
+            next_size = Some(read_u32(field, 0) as usize);
+            continue;
+        }
+        fields.push((tag, field));
+    }
+    fields
+}
+
+/// Asset paths in the subrecords of one TES4-style record, with the folder
+/// each is relative to
+fn tes4_record_references(
+    record: &[u8],
+    fields: &[(&[u8], &[u8])],
+) -> Vec<(AssetKind, String, &'static str)> {
+    let mut found = Vec::new();
+    for &(tag, field) in fields {
+        let (kind, folder) = match (record, tag) {
+            (_, b"MODL" | b"MOD2" | b"MOD3" | b"MOD4" | b"MOD5" | b"DMDL") => {
+                (AssetKind::Model, "meshes")
+            }
+            (_, b"ICON" | b"ICO2" | b"MICO" | b"MIC2") => (AssetKind::Texture, "textures"),
+            (b"TXST", _) if tag.starts_with(b"TX0") => (AssetKind::Texture, "textures"),
+            (b"EFSH", b"NAM7" | b"NAM8" | b"NAM9") => (AssetKind::Texture, "textures"),
+            (b"SOUN", b"FNAM") | (b"SNDR", b"ANAM") => (AssetKind::Sound, "sound"),
+            (b"MUSC", b"FNAM") | (b"MUST", b"ANAM" | b"BNAM") => (AssetKind::Sound, "music"),
+            (_, b"VMAD") => {
+                for name in vmad_scripts(record, field) {
+                    let path = format!("{}.pex", name.replace(':', "\\"));
+                    found.push((AssetKind::Script, path, "scripts"));
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        found.push((kind, zstring(field), folder));
+    }
+    found
+}
+
+/// Walk the records of a Morrowind plugin: 16-byte record headers and
+/// subrecords with a `u32` size, no groups
+fn tes3_references(data: &[u8], plugin_name: &str, references: &mut References) -> Result<()> {
+    let mut pos = 0;
+    while pos < data.len() {
+        let Some(header) = data.get(pos..pos + 16) else {
+            bail!("Truncated record header at offset {}", pos);
+        };
+        let tag = &header[..4];
+        let size = read_u32(header, 4) as usize;
+        let start = pos + 16;
+        let Some(body) = start.checked_add(size).and_then(|end| data.get(start..end)) else {
+            bail!(
+                "Truncated {} record at offset {}",
+                String::from_utf8_lossy(tag),
+                pos
+            );
+        };
+        pos = start + size;
+
+        let mut fields = Vec::new();
+        let mut field_pos = 0;
+        while let Some(field_header) = body.get(field_pos..field_pos + 8) {
+            let field_size = read_u32(field_header, 4) as usize;
+            let field_start = field_pos + 8;
+            let Some(field) = field_start
+                .checked_add(field_size)
+                .and_then(|end| body.get(field_start..end))
+            else {
+                break;
+            };
+            fields.push((&field_header[..4], field));
+            field_pos = field_start + field_size;
+        }
+
+        let name = fields
+            .iter()
+            .find(|(t, _)| *t == b"NAME")
+            .map(|(_, f)| zstring(f))
+            .unwrap_or_default();
+        let owner = format!("{} {} {}", plugin_name, String::from_utf8_lossy(tag), name);
+        for &(field_tag, field) in &fields {
+            let (kind, folder) = match (tag, field_tag) {
+                (_, b"MODL") => (AssetKind::Model, "meshes"),
+                (_, b"ITEX") => (AssetKind::Texture, "icons"),
+                (b"LTEX", b"DATA") => (AssetKind::Texture, "textures"),
+                (b"SOUN", b"FNAM") => (AssetKind::Sound, "sound"),
+                _ => continue,
+            };
+            references.add(kind, &zstring(field), folder, owner.trim_end());
+        }
+    }
+    Ok(())
+}
+
+/// Script names in a `VMAD` subrecord: the attached scripts and, for quests,
+/// dialogue, packages, perks and scenes, the fragment script. What was read
+/// before a property that can't be parsed is kept.
+fn vmad_scripts(record: &[u8], data: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let _ = read_vmad(record, data, &mut names);
+    names.retain(|n| !n.trim().is_empty());
+    names
+}
+
+fn read_vmad(record: &[u8], data: &[u8], names: &mut Vec<String>) -> Result<()> {
+    let mut reader = Reader::new(data);
+    let version = reader.u16()?;
+    // Object format: object values are 8 bytes either way
+    reader.skip(2)?;
+    let scripts = reader.u16()?;
+    for _ in 0..scripts {
+        names.push(reader.wstring()?);
+        if version >= 4 {
+            reader.skip(1)?; // Status
+        }
+        let properties = reader.u16()?;
+        for _ in 0..properties {
+            skip_property(&mut reader, version, false)?;
+        }
+    }
+
+    // Bytes before the fragment script name
+    let prefix = match record {
+        b"PERK" => 1,
+        b"INFO" | b"PACK" | b"SCEN" => 2,
+        b"QUST" => 3,
+        _ => return Ok(()),
+    };
+    if reader.remaining() > prefix {
+        reader.skip(prefix)?;
+        names.push(reader.wstring()?);
+    }
+    Ok(())
+}
+
+/// Skip one script property: name, type, status and value
+fn skip_property(reader: &mut Reader, version: u16, in_struct: bool) -> Result<()> {
+    reader.wstring()?;
+    let kind = reader.u8()?;
+    if version >= 4 {
+        reader.skip(1)?; // Status
+    }
+    if in_struct && matches!(kind, 7 | 17) {
+        bail!("Struct inside a struct");
+    }
+    skip_property_value(reader, kind, version)
+}
+
+/// Skip a property value of the given type. Arrays are the element type
+/// plus 10.
+fn skip_property_value(reader: &mut Reader, kind: u8, version: u16) -> Result<()> {
+    match kind {
+        0 => {}
+        // Form ID and alias
+        1 => reader.skip(8)?,
+        2 => {
+            reader.wstring()?;
+        }
+        3 | 4 => reader.skip(4)?,
+        5 => reader.skip(1)?,
+        // Fallout 4 struct
+        7 => {
+            let members = reader.u32()?;
+            for _ in 0..members {
+                skip_property(reader, version, true)?;
+            }
+        }
+        11..=15 | 17 => {
+            let count = reader.u32()?;
+            for _ in 0..count {
+                skip_property_value(reader, kind - 10, version)?;
+            }
+        }
+        _ => bail!("Unsupported script property type {}", kind),
+    }
+    Ok(())
+}
+
+/// Bounds-checked little-endian reader for `VMAD` data
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.remaining() {
+            bail!("Truncated at offset {}", self.pos);
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(read_u16(self.bytes(2)?, 0))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(read_u32(self.bytes(4)?, 0))
+    }
+
+    /// `u16` length, then the characters
+    fn wstring(&mut self) -> Result<String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+}
+
+/// A NUL-terminated string subrecord
+fn zstring(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Paths that satisfy a reference: the path itself, and the formats the game
+/// falls back to (`.dds` for `.tga`/`.bmp` textures, `.xwm` and `.fuz` for
+/// `.wav` sounds, `.xwm` for `.mp3` music)
+fn lookup_candidates(reference: &AssetReference) -> Vec<String> {
+    let mut candidates = vec![reference.resolved.clone()];
+    if let Some((stem, ext)) = reference.resolved.rsplit_once('.') {
+        let fallbacks: &[&str] = match ext {
+            "tga" | "bmp" => &["dds"],
+            "wav" => &["xwm", "fuz"],
+            "mp3" => &["xwm"],
+            _ => &[],
+        };
+        candidates.extend(fallbacks.iter().map(|ext| format!("{}.{}", stem, ext)));
+    }
+    candidates
+}
+
+/// The other weight variant of a `_0.nif`/`_1.nif` armor mesh, which the
+/// game loads along with the one named in the plugin
+fn weight_variant(resolved: &str) -> Option<String> {
+    if let Some(stem) = resolved.strip_suffix("_0.nif") {
+        Some(format!("{}_1.nif", stem))
+    } else {
+        resolved
+            .strip_suffix("_1.nif")
+            .map(|stem| format!("{}_0.nif", stem))
+    }
+}
+
+/// Check plugin references against the files of a VFS
+pub fn check_references(vfs: &Vfs, references: Vec<AssetReference>) -> Result<RefReport> {
+    check_references_with_progress(vfs, references, |_, _, _| {})
+}
+
+/// Check plugin references against the files of a VFS, reporting progress as
+/// each referenced mesh is read
+///
+/// Every mesh that is found is read for the textures it uses, which are
+/// checked too. Archived meshes, textures, sounds and scripts that neither
+/// the plugins nor those meshes name are listed as unreferenced, except in
+/// folders the game reads by name (voice files, FaceGen data, terrain LOD).
+pub fn check_references_with_progress<F>(
+    vfs: &Vfs,
+    references: Vec<AssetReference>,
+    progress: F,
+) -> Result<RefReport>
+where
+    F: Fn(usize, usize, &str) + Send + Sync,
+{
+    let mut all = References::default();
+    for reference in references {
+        all.push(reference);
+    }
+
+    let mut meshes: Vec<String> = Vec::new();
+    for reference in all.list.iter().filter(|r| r.kind == AssetKind::Model) {
+        meshes.push(reference.resolved.clone());
+        meshes.extend(weight_variant(&reference.resolved));
+    }
+    meshes.retain(|m| vfs.lookup(m).is_some());
+    meshes.sort();
+    meshes.dedup();
+
+    let total = meshes.len();
+    let done = AtomicUsize::new(0);
+    let mesh_textures: Vec<Vec<AssetReference>> = meshes
+        .par_iter()
+        .map(|mesh| {
+            let file = vfs.lookup(mesh).unwrap();
+            // A mesh that can't be read names no textures; `inspect`
+            // reports why
+            let textures = match vfs.read_from(file.source, &file.path) {
+                Ok(data) => inspect_nif(&data)
+                    .ok()
+                    .and_then(|info| info.textures)
+                    .unwrap_or_default(),
+                Err(e) => {
+                    warn!("Failed to read {}: {:#}", file.path, e);
+                    Vec::new()
+                }
+            };
+            let textures = textures
+                .into_iter()
+                .map(|path| AssetReference {
+                    kind: AssetKind::Texture,
+                    resolved: texture_lookup_path(&path),
+                    path,
+                    referenced_by: file.path.clone(),
+                })
+                .collect();
+            let current = done.fetch_add(1, Ordering::Relaxed) + 1;
+            progress(current, total, mesh);
+            textures
+        })
+        .collect();
+    for reference in mesh_textures.into_iter().flatten() {
+        all.push(reference);
+    }
+
+    let mut referenced: HashSet<String> = HashSet::new();
+    let mut missing = Vec::new();
+    for reference in &all.list {
+        let candidates = lookup_candidates(reference);
+        if !candidates.iter().any(|c| vfs.lookup(c).is_some()) {
+            missing.push(reference.clone());
+        }
+        referenced.extend(candidates.iter().map(|c| normalize_path(c)));
+        referenced.extend(weight_variant(&reference.resolved).map(|v| normalize_path(&v)));
+    }
+
+    let sources = vfs.sources();
+    let mut unreferenced = Vec::new();
+    for file in vfs.files() {
+        let Some(kind) = AssetKind::from_path(&file.path) else {
+            continue;
+        };
+        let key = normalize_path(&file.path);
+        let lookup = key.replace('/', "\\");
+        if referenced.contains(&key) || DERIVED_FOLDERS.iter().any(|d| lookup.contains(d)) {
+            continue;
+        }
+        for layer in file.layers() {
+            if !sources[layer.source].is_loose() {
+                unreferenced.push(UnreferencedAsset {
+                    kind,
+                    path: layer.path,
+                    source: sources[layer.source].path().to_path_buf(),
+                });
+            }
+        }
+    }
+
+    Ok(RefReport {
+        references: all.list.len(),
+        missing,
+        unreferenced,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wstring(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    fn subrecord(out: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+        out.extend_from_slice(tag);
+        out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        out.extend_from_slice(data);
+    }
+
+    /// Version 5 `VMAD` with two scripts: one with a property of every simple
+    /// type and a string array, one without properties
+    fn vmad() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&5u16.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+
+        wstring(&mut data, "MyQuestScript");
+        data.push(0);
+        data.extend_from_slice(&6u16.to_le_bytes());
+        let values: [(u8, &[u8]); 5] = [
+            (0, &[]),
+            (1, &[0; 8]),
+            (3, &7i32.to_le_bytes()),
+            (4, &1.5f32.to_le_bytes()),
+            (5, &[1]),
+        ];
+        for (kind, value) in values {
+            wstring(&mut data, "Prop");
+            data.push(kind);
+            data.push(1);
+            data.extend_from_slice(value);
+        }
+        wstring(&mut data, "Names");
+        data.push(12);
+        data.push(1);
+        data.extend_from_slice(&2u32.to_le_bytes());
+        wstring(&mut data, "first");
+        wstring(&mut data, "second");
+
+        wstring(&mut data, "OtherScript");
+        data.push(0);
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn asset_lookup_paths() {
+        assert_eq!(
+            asset_lookup_path("Armor/Iron/Cuirass.nif", "meshes"),
+            "meshes\\armor\\iron\\cuirass.nif"
+        );
+        assert_eq!(
+            asset_lookup_path("Meshes\\Armor\\Cuirass.nif", "meshes"),
+            "meshes\\armor\\cuirass.nif"
+        );
+        assert_eq!(
+            asset_lookup_path(
+                "C:\\Games\\Skyrim\\Data\\Meshes\\Clutter\\Cup.nif",
+                "meshes"
+            ),
+            "meshes\\clutter\\cup.nif"
+        );
+        assert_eq!(
+            asset_lookup_path(" .\\Data Files/Music/Explore.xwm", "music"),
+            "music\\explore.xwm"
+        );
+        assert_eq!(
+            asset_lookup_path("FX/Step.wav", "sound"),
+            "sound\\fx\\step.wav"
+        );
+        // A folder name that only starts like the folder isn't the folder
+        assert_eq!(
+            asset_lookup_path("meshesextra\\a.nif", "meshes"),
+            "meshes\\meshesextra\\a.nif"
+        );
+    }
+
+    #[test]
+    fn subrecords_are_split() {
+        let mut body = Vec::new();
+        subrecord(&mut body, b"EDID", b"Thing\0");
+        subrecord(&mut body, b"MODL", b"a.nif\0");
+        let fields = tes4_subrecords(&body);
+        assert_eq!(
+            fields,
+            vec![
+                (&b"EDID"[..], &b"Thing\0"[..]),
+                (&b"MODL"[..], &b"a.nif\0"[..])
+            ]
+        );
+    }
+
+    #[test]
+    fn xxxx_sets_the_next_size() {
+        let large = vec![7u8; 70_000];
+        let mut body = Vec::new();
+        subrecord(&mut body, b"XXXX", &(large.len() as u32).to_le_bytes());
+        body.extend_from_slice(b"OFST");
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&large);
+        subrecord(&mut body, b"MODL", b"a.nif\0");
+        let fields = tes4_subrecords(&body);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0], (&b"OFST"[..], &large[..]));
+        assert_eq!(fields[1], (&b"MODL"[..], &b"a.nif\0"[..]));
+    }
+
+    #[test]
+    fn truncated_subrecords_stop() {
+        let mut body = Vec::new();
+        subrecord(&mut body, b"EDID", b"Thing\0");
+        body.extend_from_slice(b"MODL");
+        body.extend_from_slice(&20u16.to_le_bytes());
+        body.extend_from_slice(b"short");
+        let fields = tes4_subrecords(&body);
+        assert_eq!(fields, vec![(&b"EDID"[..], &b"Thing\0"[..])]);
+    }
+
+    #[test]
+    fn vmad_names_scripts() {
+        assert_eq!(
+            vmad_scripts(b"ACTI", &vmad()),
+            vec!["MyQuestScript", "OtherScript"]
+        );
+    }
+
+    #[test]
+    fn vmad_names_quest_fragments() {
+        let mut data = vmad();
+        // Extra bind info, file name count and the fragment script
+        data.extend_from_slice(&[2, 0, 0]);
+        wstring(&mut data, "QF_MyQuest_0001");
+        assert_eq!(
+            vmad_scripts(b"QUST", &data),
+            vec!["MyQuestScript", "OtherScript", "QF_MyQuest_0001"]
+        );
+        // Other records don't have fragments
+        assert_eq!(vmad_scripts(b"ACTI", &data).len(), 2);
+    }
+
+    #[test]
+    fn vmad_keeps_names_before_a_bad_property() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&5u16.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        wstring(&mut data, "FirstScript");
+        data.push(0);
+        data.extend_from_slice(&1u16.to_le_bytes());
+        wstring(&mut data, "Prop");
+        data.push(99);
+        data.push(1);
+        wstring(&mut data, "Unreachable");
+
+        let mut names = Vec::new();
+        assert!(read_vmad(b"ACTI", &data, &mut names).is_err());
+        assert_eq!(names, vec!["FirstScript"]);
+        assert_eq!(vmad_scripts(b"ACTI", &data), vec!["FirstScript"]);
+    }
+
+    #[test]
+    fn vmad_version_3_has_no_status() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&3u16.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        for name in ["A", "B"] {
+            wstring(&mut data, name);
+            data.extend_from_slice(&1u16.to_le_bytes());
+            wstring(&mut data, "Count");
+            data.push(3);
+            data.extend_from_slice(&1i32.to_le_bytes());
+        }
+        assert_eq!(vmad_scripts(b"ACTI", &data), vec!["A", "B"]);
+    }
+}
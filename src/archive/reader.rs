@@ -1,92 +1,337 @@
 //! BSA reading with parallel extraction
 
 use anyhow::{bail, Context, Result};
-use ba2::tes4::{Archive, File as BsaFile, FileCompressionOptions};
-use ba2::{ByteSlice, Reader};
+use ba2::tes4::{
+    hash_directory, hash_file, Archive, ArchiveOptions, DirectoryHash, File as BsaFile,
+    FileCompressionOptions, FileHash, Version,
+};
+use ba2::{Borrowed, ByteSlice, Reader};
+use flate2::read::ZlibDecoder;
+use lz4_flex::frame::FrameDecoder;
+use memmap2::Mmap;
 use rayon::prelude::*;
-use std::collections::HashSet;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
 use tracing::debug;
 
+use super::filter::normalize_path;
+use super::{
+    data_offset, map_archive, read_up_to, static_mapping, ArchiveFileEntry, ArchiveFormat,
+    BatchCallback, FormatOptions, OpenArchive,
+};
+
 /// Entry for a file in a BSA archive
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct BsaFileEntry {
     pub path: String,
+    /// Decompressed size in bytes
+    pub size: u64,
+    /// Size as stored in the archive
+    pub packed_size: u64,
+    pub compressed: bool,
+    /// Name hash, formatted as described on `ArchiveFileEntry::hash`
+    pub hash: String,
+    /// Offset of the stored data from the start of the archive
+    pub offset: u64,
 }
 
-/// List all files in a BSA archive
-pub fn list_files(bsa_path: &Path) -> Result<Vec<BsaFileEntry>> {
-    let (archive, _): (Archive, _) = Archive::read(bsa_path)
-        .with_context(|| format!("Failed to open BSA: {}", bsa_path.display()))?;
+/// A TES4 BSA parsed once and kept open for repeated reads
+pub struct BsaArchive {
+    path: PathBuf,
+    /// Borrows from `mapping`, so it is declared first to be dropped first
+    archive: Archive<'static>,
+    options: ArchiveOptions,
+    mapping: Mmap,
+    entries: OnceLock<Vec<ArchiveFileEntry>>,
+    /// Normalized path -> name hashes, for archives whose stored hashes
+    /// don't match what we compute
+    index: OnceLock<HashMap<String, (DirectoryHash, FileHash)>>,
+}
+
+impl BsaArchive {
+    /// Open and parse a BSA archive
+    pub fn open(bsa_path: &Path) -> Result<Self> {
+        let mapping = map_archive(bsa_path)?;
+        // SAFETY: `archive` is dropped before `mapping` and no file borrowed
+        // from it leaves this struct
+        let bytes = unsafe { static_mapping(&mapping) };
+        let (archive, options): (Archive, _) = Archive::read(Borrowed(bytes))
+            .with_context(|| format!("Failed to open BSA: {}", bsa_path.display()))?;
+        Ok(Self {
+            path: bsa_path.to_path_buf(),
+            archive,
+            options,
+            mapping,
+            entries: OnceLock::new(),
+            index: OnceLock::new(),
+        })
+    }
+
+    /// Find a file case-insensitively (either slash convention).
+    /// Looks the path up by its name hash, falling back to a path index
+    /// built on first use.
+    fn find(&self, file_path: &str) -> Option<&BsaFile<'static>> {
+        let (dir_name, file_name) = split_path(file_path);
+        let dir_hash = hash_directory(dir_name.as_bytes().as_bstr()).0;
+        let file_hash = hash_file(file_name.as_bytes().as_bstr()).0;
 
-    let mut files = Vec::new();
+        let hashed = self
+            .archive
+            .get_key_value(&dir_hash)
+            .and_then(|(dir_key, folder)| {
+                let (file_key, file) = folder.get_key_value(&file_hash)?;
+                // Names are missing when the archive has no strings; trust the hash then
+                let dir_matches = dir_key.name().is_empty()
+                    || dir_key.name().eq_ignore_ascii_case(dir_name.as_bytes());
+                let file_matches = file_key.name().is_empty()
+                    || file_key.name().eq_ignore_ascii_case(file_name.as_bytes());
+                (dir_matches && file_matches).then_some(file)
+            });
+        if hashed.is_some() {
+            return hashed;
+        }
+
+        let (dir_hash, file_hash) = self.index().get(&normalize_path(file_path))?;
+        self.archive.get(dir_hash)?.get(file_hash)
+    }
 
-    for (dir_key, folder) in archive.iter() {
-        let dir_name = String::from_utf8_lossy(dir_key.name().as_bytes());
+    /// Normalized path -> name hashes, built on first use
+    fn index(&self) -> &HashMap<String, (DirectoryHash, FileHash)> {
+        self.index.get_or_init(|| {
+            let mut index = HashMap::new();
+            for (dir_key, folder) in self.archive.iter() {
+                let dir_name = String::from_utf8_lossy(dir_key.name().as_bytes());
+                for (file_key, _) in folder.iter() {
+                    let file_name = String::from_utf8_lossy(file_key.name().as_bytes());
+                    let path = join_path(&dir_name, &file_name);
+                    index.insert(normalize_path(&path), (*dir_key.hash(), *file_key.hash()));
+                }
+            }
+            index
+        })
+    }
 
-        for (file_key, _file) in folder.iter() {
-            let file_name = String::from_utf8_lossy(file_key.name().as_bytes());
+    /// Decompress a file if needed (uses version from archive options)
+    fn decompress(&self, file: &BsaFile) -> Result<Vec<u8>> {
+        let compression_options: FileCompressionOptions = (&self.options).into();
+        let data = if file.is_decompressed() {
+            file.as_bytes().to_vec()
+        } else {
+            file.decompress(&compression_options)?.as_bytes().to_vec()
+        };
+        Ok(data)
+    }
 
-            // Build full path with backslash (BSA convention)
-            let full_path = if dir_name.is_empty() || dir_name == "." {
-                file_name.to_string()
-            } else {
-                format!("{}\\{}", dir_name, file_name)
-            };
+    /// BSA version of the archive
+    pub fn version(&self) -> ba2::tes4::Version {
+        self.options.version()
+    }
 
-            files.push(BsaFileEntry { path: full_path });
+    /// Every file as stored (possibly compressed), with its path.
+    /// Pass these to [`BsaBuilder::add_stored_file`](super::BsaBuilder::add_stored_file)
+    /// to repack without recompressing.
+    ///
+    /// The files outlive this archive, so they come from a second read with
+    /// its own mapping.
+    pub fn stored_files(&self) -> Result<Vec<(String, BsaFile<'static>)>> {
+        let (archive, _): (Archive, _) = Archive::read(self.path.as_path())
+            .with_context(|| format!("Failed to open BSA: {}", self.path.display()))?;
+        let mut files = Vec::new();
+        for (dir_key, folder) in archive.iter() {
+            let dir_name = String::from_utf8_lossy(dir_key.name().as_bytes());
+            for (file_key, file) in folder.iter() {
+                let file_name = String::from_utf8_lossy(file_key.name().as_bytes());
+                files.push((join_path(&dir_name, &file_name), file.clone()));
+            }
         }
+        Ok(files)
     }
 
-    debug!("Listed {} files in BSA {}", files.len(), bsa_path.display());
-    Ok(files)
-}
+    /// Every file with its size, hash and offset, from the parsed archive
+    fn list(&self) -> Vec<BsaFileEntry> {
+        let mut files = Vec::new();
 
-/// Extract a single file from a BSA archive
-#[allow(dead_code)]
-pub fn extract_file(bsa_path: &Path, file_path: &str) -> Result<Vec<u8>> {
-    let (archive, options): (Archive, _) = Archive::read(bsa_path)
-        .with_context(|| format!("Failed to open BSA: {}", bsa_path.display()))?;
+        for (dir_key, folder) in self.archive.iter() {
+            let dir_name = String::from_utf8_lossy(dir_key.name().as_bytes());
 
-    // Convert archive options to compression options (includes version info)
-    let compression_options: FileCompressionOptions = (&options).into();
+            for (file_key, file) in folder.iter() {
+                let file_name = String::from_utf8_lossy(file_key.name().as_bytes());
 
-    // Normalize to backslashes and split
-    let normalized = file_path.replace('/', "\\");
-    let (dir_name, file_name) = if let Some(idx) = normalized.rfind('\\') {
-        (&normalized[..idx], &normalized[idx + 1..])
-    } else {
-        ("", normalized.as_str())
-    };
+                files.push(BsaFileEntry {
+                    path: join_path(&dir_name, &file_name),
+                    size: file.decompressed_len().unwrap_or(file.len()) as u64,
+                    packed_size: file.len() as u64,
+                    compressed: file.is_compressed(),
+                    hash: format!(
+                        "{:016x}/{:016x}",
+                        dir_key.hash().numeric(),
+                        file_key.hash().numeric()
+                    ),
+                    offset: data_offset(&self.mapping, file.as_ptr()),
+                });
+            }
+        }
 
-    // Search case-insensitively
-    for (dir_key, folder) in archive.iter() {
-        let current_dir = String::from_utf8_lossy(dir_key.name().as_bytes());
+        debug!(
+            "Listed {} files in BSA {}",
+            files.len(),
+            self.path.display()
+        );
+        files
+    }
+
+    /// Like [`find`](Self::find), with an error naming the path when missing
+    fn find_or_bail(&self, file_path: &str) -> Result<&BsaFile<'static>> {
+        match self.find(file_path) {
+            Some(file) => Ok(file),
+            None => {
+                let (dir_name, file_name) = split_path(file_path);
+                bail!(
+                    "File not found in BSA: {} (dir='{}', file='{}')",
+                    file_path,
+                    dir_name,
+                    file_name
+                )
+            }
+        }
+    }
+
+    /// Extract a single file
+    pub fn extract(&self, file_path: &str) -> Result<Vec<u8>> {
+        self.decompress(self.find_or_bail(file_path)?)
+    }
+
+    /// Extract multiple files in a single parallel pass.
+    /// `wanted` should contain lowercase backslash-separated paths.
+    pub fn extract_batch<F>(&self, wanted: &HashSet<String>, callback: F) -> Result<usize>
+    where
+        F: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
+    {
+        // Collect matching entries with references to file data
+        let mut entries: Vec<(String, &BsaFile)> = Vec::new();
+        for (dir_key, folder) in self.archive.iter() {
+            let dir_name = String::from_utf8_lossy(dir_key.name().as_bytes());
 
-        if current_dir.eq_ignore_ascii_case(dir_name) {
             for (file_key, file) in folder.iter() {
-                let current_file = String::from_utf8_lossy(file_key.name().as_bytes());
-
-                if current_file.eq_ignore_ascii_case(file_name) {
-                    // Extract with decompression if needed (uses version from archive options)
-                    let data = if file.is_decompressed() {
-                        file.as_bytes().to_vec()
-                    } else {
-                        file.decompress(&compression_options)?.as_bytes().to_vec()
-                    };
-                    return Ok(data);
+                let file_name = String::from_utf8_lossy(file_key.name().as_bytes());
+                let full_path = join_path(&dir_name, &file_name);
+                let lookup = full_path.to_lowercase();
+                if wanted.contains(&lookup) {
+                    entries.push((full_path, file));
                 }
             }
         }
+
+        // Decompress + write in parallel
+        let extracted = AtomicUsize::new(0);
+        entries
+            .par_iter()
+            .try_for_each(|(path, file)| -> Result<()> {
+                let data = self.decompress(file)?;
+
+                callback(path, data)?;
+                extracted.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            })?;
+
+        let count = extracted.load(Ordering::Relaxed);
+        debug!(
+            "Batch extracted {} of {} wanted files from BSA {}",
+            count,
+            wanted.len(),
+            self.path.display()
+        );
+        Ok(count)
+    }
+}
+
+impl OpenArchive for BsaArchive {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn format(&self) -> ArchiveFormat {
+        ArchiveFormat::Bsa
+    }
+
+    fn options(&self) -> FormatOptions {
+        FormatOptions::Tes4(self.options)
+    }
+
+    fn entries(&self) -> Result<&[ArchiveFileEntry]> {
+        if let Some(entries) = self.entries.get() {
+            return Ok(entries);
+        }
+        let entries = self
+            .list()
+            .into_iter()
+            .map(ArchiveFileEntry::from)
+            .collect();
+        Ok(self.entries.get_or_init(|| entries))
+    }
+
+    fn read_into(&self, file_path: &str, out: &mut dyn Write) -> Result<()> {
+        out.write_all(&self.extract(file_path)?)?;
+        Ok(())
+    }
+
+    fn read_prefix(&self, file_path: &str, len: usize) -> Result<Vec<u8>> {
+        let file = self.find_or_bail(file_path)?;
+        let bytes = file.as_bytes();
+        if file.is_decompressed() {
+            return Ok(bytes[..len.min(bytes.len())].to_vec());
+        }
+        // Stream only the start: LZ4 frames for SSE, zlib before that
+        match self.options.version() {
+            Version::v105 => read_up_to(FrameDecoder::new(bytes), len),
+            _ => read_up_to(ZlibDecoder::new(bytes), len),
+        }
+        .with_context(|| format!("Failed to decompress: {}", file_path))
+    }
+
+    fn read_batch(&self, wanted_files: &[String], callback: &BatchCallback) -> Result<usize> {
+        // BSA uses backslash-separated paths
+        let wanted: HashSet<String> = wanted_files
+            .iter()
+            .map(|p| p.replace('/', "\\").to_lowercase())
+            .collect();
+        self.extract_batch(&wanted, callback)
     }
+}
 
-    bail!(
-        "File not found in BSA: {} (dir='{}', file='{}')",
-        file_path,
-        dir_name,
-        file_name
-    )
+/// Split a path into BSA directory and file name, normalized to backslashes
+pub(crate) fn split_path(file_path: &str) -> (String, String) {
+    let normalized = file_path.replace('/', "\\");
+    match normalized.rfind('\\') {
+        Some(idx) => (
+            normalized[..idx].to_string(),
+            normalized[idx + 1..].to_string(),
+        ),
+        None => (String::new(), normalized),
+    }
+}
+
+/// Join a BSA directory and file name; root files are listed without their
+/// "." directory
+fn join_path(dir_name: &str, file_name: &str) -> String {
+    if dir_name.is_empty() || dir_name == "." {
+        file_name.to_string()
+    } else {
+        format!("{}\\{}", dir_name, file_name)
+    }
+}
+
+/// List all files in a BSA archive
+pub fn list_files(bsa_path: &Path) -> Result<Vec<BsaFileEntry>> {
+    Ok(BsaArchive::open(bsa_path)?.list())
+}
+
+/// Extract a single file from a BSA archive
+pub fn extract_file(bsa_path: &Path, file_path: &str) -> Result<Vec<u8>> {
+    BsaArchive::open(bsa_path)?.extract(file_path)
 }
 
 /// Extract multiple files from a BSA archive in a single parallel pass.
@@ -101,54 +346,5 @@ pub fn extract_files_batch<F>(
 where
     F: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
 {
-    let (archive, options): (Archive, _) = Archive::read(bsa_path)
-        .with_context(|| format!("Failed to open BSA: {}", bsa_path.display()))?;
-
-    let compression_options: FileCompressionOptions = (&options).into();
-
-    // Collect matching entries with references to file data
-    let mut entries: Vec<(String, &BsaFile)> = Vec::new();
-    for (dir_key, folder) in archive.iter() {
-        let dir_name = String::from_utf8_lossy(dir_key.name().as_bytes());
-
-        for (file_key, file) in folder.iter() {
-            let file_name = String::from_utf8_lossy(file_key.name().as_bytes());
-
-            let full_path = if dir_name.is_empty() || dir_name == "." {
-                file_name.to_string()
-            } else {
-                format!("{}\\{}", dir_name, file_name)
-            };
-
-            let lookup = full_path.to_lowercase();
-            if wanted.contains(&lookup) {
-                entries.push((full_path, file));
-            }
-        }
-    }
-
-    // Decompress + write in parallel
-    let extracted = AtomicUsize::new(0);
-    entries
-        .par_iter()
-        .try_for_each(|(path, file)| -> Result<()> {
-            let data = if file.is_decompressed() {
-                file.as_bytes().to_vec()
-            } else {
-                file.decompress(&compression_options)?.as_bytes().to_vec()
-            };
-
-            callback(path, data)?;
-            extracted.fetch_add(1, Ordering::Relaxed);
-            Ok(())
-        })?;
-
-    let count = extracted.load(Ordering::Relaxed);
-    debug!(
-        "Batch extracted {} of {} wanted files from BSA {}",
-        count,
-        wanted.len(),
-        bsa_path.display()
-    );
-    Ok(count)
+    BsaArchive::open(bsa_path)?.extract_batch(wanted, callback)
 }
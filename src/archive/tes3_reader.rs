@@ -1,61 +1,218 @@
 //! TES3 (Morrowind) BSA reading
 
 use anyhow::{bail, Context, Result};
-use ba2::tes3::{Archive, File as Tes3File};
-use ba2::{ByteSlice, Reader};
+use ba2::tes3::{hash_file, Archive, File as Tes3File, FileHash};
+use ba2::{Borrowed, ByteSlice, Reader};
+use memmap2::Mmap;
 use rayon::prelude::*;
-use std::collections::HashSet;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
 use tracing::debug;
 
-use super::BsaFileEntry;
+use super::filter::normalize_path;
+use super::{
+    data_offset, map_archive, static_mapping, ArchiveFileEntry, ArchiveFormat, BatchCallback,
+    BsaFileEntry, FormatOptions, OpenArchive,
+};
+
+/// A TES3 (Morrowind) BSA parsed once and kept open for repeated reads
+pub struct Tes3Archive {
+    path: PathBuf,
+    /// Borrows from `mapping`, so it is declared first to be dropped first
+    archive: Archive<'static>,
+    mapping: Mmap,
+    entries: OnceLock<Vec<ArchiveFileEntry>>,
+    /// Normalized path -> name hash, for archives whose stored hashes
+    /// don't match what we compute
+    index: OnceLock<HashMap<String, FileHash>>,
+}
 
-/// List all files in a TES3 (Morrowind) BSA archive
-pub fn list_files(bsa_path: &Path) -> Result<Vec<BsaFileEntry>> {
-    let archive: Archive = Archive::read(bsa_path)
-        .with_context(|| format!("Failed to open TES3 BSA: {}", bsa_path.display()))?;
+impl Tes3Archive {
+    /// Open and parse a TES3 BSA archive
+    pub fn open(bsa_path: &Path) -> Result<Self> {
+        let mapping = map_archive(bsa_path)?;
+        // SAFETY: `archive` is dropped before `mapping` and no file borrowed
+        // from it leaves this struct
+        let bytes = unsafe { static_mapping(&mapping) };
+        let archive: Archive = Archive::read(Borrowed(bytes))
+            .with_context(|| format!("Failed to open TES3 BSA: {}", bsa_path.display()))?;
+        Ok(Self {
+            path: bsa_path.to_path_buf(),
+            archive,
+            mapping,
+            entries: OnceLock::new(),
+            index: OnceLock::new(),
+        })
+    }
+
+    /// Find a file case-insensitively (either slash convention).
+    /// Looks the path up by its name hash, falling back to a path index
+    /// built on first use.
+    fn find(&self, file_path: &str) -> Option<&Tes3File<'static>> {
+        let hash = hash_file(file_path.as_bytes().as_bstr()).0;
+        let hashed = self.archive.get_key_value(&hash).and_then(|(key, file)| {
+            // Names are missing when the archive has no strings; trust the hash then
+            let matches = key.name().is_empty()
+                || normalize_path(&String::from_utf8_lossy(key.name().as_bytes()))
+                    == normalize_path(file_path);
+            matches.then_some(file)
+        });
+        if hashed.is_some() {
+            return hashed;
+        }
 
-    let mut files = Vec::new();
+        let hash = self.index().get(&normalize_path(file_path))?;
+        self.archive.get(hash)
+    }
 
-    for (key, _file) in archive.iter() {
-        let path = String::from_utf8_lossy(key.name().as_bytes()).to_string();
+    /// Normalized path -> name hash, built on first use
+    fn index(&self) -> &HashMap<String, FileHash> {
+        self.index.get_or_init(|| {
+            self.archive
+                .iter()
+                .map(|(key, _)| {
+                    let path = String::from_utf8_lossy(key.name().as_bytes());
+                    (normalize_path(&path), *key.hash())
+                })
+                .collect()
+        })
+    }
+
+    /// Every file with its size, hash and offset, from the parsed archive
+    fn list(&self) -> Vec<BsaFileEntry> {
+        let mut files = Vec::new();
+
+        for (key, file) in self.archive.iter() {
+            let path = String::from_utf8_lossy(key.name().as_bytes()).to_string();
+
+            // TES3 BSAs are never compressed
+            files.push(BsaFileEntry {
+                path,
+                size: file.len() as u64,
+                packed_size: file.len() as u64,
+                compressed: false,
+                hash: format!("{:016x}", key.hash().numeric()),
+                offset: data_offset(&self.mapping, file.as_ptr()),
+            });
+        }
 
-        files.push(BsaFileEntry { path });
+        debug!(
+            "Listed {} files in TES3 BSA {}",
+            files.len(),
+            self.path.display()
+        );
+        files
     }
 
-    debug!(
-        "Listed {} files in TES3 BSA {}",
-        files.len(),
-        bsa_path.display()
-    );
-    Ok(files)
+    /// Extract a single file
+    pub fn extract(&self, file_path: &str) -> Result<Vec<u8>> {
+        match self.find(file_path) {
+            // TES3 BSAs are uncompressed, so just return the raw bytes
+            Some(file) => Ok(file.as_bytes().to_vec()),
+            None => bail!(
+                "File not found in TES3 BSA: {} (looking for '{}')",
+                self.path.display(),
+                file_path
+            ),
+        }
+    }
+
+    /// Extract multiple files in parallel.
+    /// `wanted` should contain lowercase backslash-separated paths.
+    pub fn extract_batch<F>(&self, wanted: &HashSet<String>, callback: F) -> Result<usize>
+    where
+        F: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
+    {
+        // Collect matching entries
+        let mut entries: Vec<(String, &Tes3File)> = Vec::new();
+        for (key, file) in self.archive.iter() {
+            let path = String::from_utf8_lossy(key.name().as_bytes()).to_string();
+            let lookup = path.to_lowercase();
+            if wanted.contains(&lookup) {
+                entries.push((path, file));
+            }
+        }
+
+        // Write in parallel
+        let extracted = AtomicUsize::new(0);
+        entries
+            .par_iter()
+            .try_for_each(|(path, file)| -> Result<()> {
+                callback(path, file.as_bytes().to_vec())?;
+                extracted.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            })?;
+
+        let count = extracted.load(Ordering::Relaxed);
+        debug!(
+            "Batch extracted {} of {} wanted files from TES3 BSA {}",
+            count,
+            wanted.len(),
+            self.path.display()
+        );
+        Ok(count)
+    }
 }
 
-/// Extract a single file from a TES3 (Morrowind) BSA archive
-#[allow(dead_code)]
-pub fn extract_file(bsa_path: &Path, file_path: &str) -> Result<Vec<u8>> {
-    let archive: Archive = Archive::read(bsa_path)
-        .with_context(|| format!("Failed to open TES3 BSA: {}", bsa_path.display()))?;
+impl OpenArchive for Tes3Archive {
+    fn path(&self) -> &Path {
+        &self.path
+    }
 
-    // Normalize path separators
-    let normalized = file_path.replace('/', "\\");
+    fn format(&self) -> ArchiveFormat {
+        ArchiveFormat::Tes3Bsa
+    }
 
-    // Search case-insensitively
-    for (key, file) in archive.iter() {
-        let current_path = String::from_utf8_lossy(key.name().as_bytes());
+    fn options(&self) -> FormatOptions {
+        FormatOptions::Tes3
+    }
 
-        if current_path.eq_ignore_ascii_case(&normalized) {
-            // TES3 BSAs are uncompressed, so just return the raw bytes
-            return Ok(file.as_bytes().to_vec());
+    fn entries(&self) -> Result<&[ArchiveFileEntry]> {
+        if let Some(entries) = self.entries.get() {
+            return Ok(entries);
+        }
+        let entries = self
+            .list()
+            .into_iter()
+            .map(ArchiveFileEntry::from)
+            .collect();
+        Ok(self.entries.get_or_init(|| entries))
+    }
+
+    fn read_into(&self, file_path: &str, out: &mut dyn Write) -> Result<()> {
+        match self.find(file_path) {
+            Some(file) => {
+                out.write_all(file.as_bytes())?;
+                Ok(())
+            }
+            None => bail!(
+                "File not found in TES3 BSA: {} (looking for '{}')",
+                self.path.display(),
+                file_path
+            ),
         }
     }
 
-    bail!(
-        "File not found in TES3 BSA: {} (looking for '{}')",
-        bsa_path.display(),
-        file_path
-    )
+    fn read_batch(&self, wanted_files: &[String], callback: &BatchCallback) -> Result<usize> {
+        let wanted: HashSet<String> = wanted_files
+            .iter()
+            .map(|p| p.replace('/', "\\").to_lowercase())
+            .collect();
+        self.extract_batch(&wanted, callback)
+    }
+}
+
+/// List all files in a TES3 (Morrowind) BSA archive
+pub fn list_files(bsa_path: &Path) -> Result<Vec<BsaFileEntry>> {
+    Ok(Tes3Archive::open(bsa_path)?.list())
+}
+
+/// Extract a single file from a TES3 (Morrowind) BSA archive
+pub fn extract_file(bsa_path: &Path, file_path: &str) -> Result<Vec<u8>> {
+    Tes3Archive::open(bsa_path)?.extract(file_path)
 }
 
 /// Extract multiple files from a TES3 BSA archive in parallel.
@@ -70,35 +227,5 @@ pub fn extract_files_batch<F>(
 where
     F: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
 {
-    let archive: Archive = Archive::read(bsa_path)
-        .with_context(|| format!("Failed to open TES3 BSA: {}", bsa_path.display()))?;
-
-    // Collect matching entries
-    let mut entries: Vec<(String, &Tes3File)> = Vec::new();
-    for (key, file) in archive.iter() {
-        let path = String::from_utf8_lossy(key.name().as_bytes()).to_string();
-        let lookup = path.to_lowercase();
-        if wanted.contains(&lookup) {
-            entries.push((path, file));
-        }
-    }
-
-    // Write in parallel
-    let extracted = AtomicUsize::new(0);
-    entries
-        .par_iter()
-        .try_for_each(|(path, file)| -> Result<()> {
-            callback(path, file.as_bytes().to_vec())?;
-            extracted.fetch_add(1, Ordering::Relaxed);
-            Ok(())
-        })?;
-
-    let count = extracted.load(Ordering::Relaxed);
-    debug!(
-        "Batch extracted {} of {} wanted files from TES3 BSA {}",
-        count,
-        wanted.len(),
-        bsa_path.display()
-    );
-    Ok(count)
+    Tes3Archive::open(bsa_path)?.extract_batch(wanted, callback)
 }
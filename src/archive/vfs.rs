@@ -0,0 +1,288 @@
+//! Virtual file system over archives and loose directories
+//!
+//! Resolves what the game sees for each path: archives are layered in the
+//! order they are added (later wins, like a load order), and loose files
+//! override every archive, as they do in game. Loose directories are layered
+//! among themselves in the order they are added.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use super::filter::normalize_path;
+use super::ArchiveHandle;
+
+/// Where a file in the VFS comes from
+#[derive(Debug, Clone)]
+pub enum VfsSource {
+    /// An archive, opened once and kept open for reads
+    Archive(ArchiveHandle),
+    /// A directory of loose files
+    Loose(PathBuf),
+}
+
+impl VfsSource {
+    /// Path of the archive or directory on disk
+    pub fn path(&self) -> &Path {
+        match self {
+            VfsSource::Archive(archive) => archive.path(),
+            VfsSource::Loose(dir) => dir,
+        }
+    }
+
+    /// Whether this is a loose directory
+    pub fn is_loose(&self) -> bool {
+        matches!(self, VfsSource::Loose(_))
+    }
+}
+
+/// A resolved file in the VFS
+#[derive(Debug, Clone)]
+pub struct VfsFile {
+    /// Path as stored in the winning source, with `/` separators for loose files
+    pub path: String,
+    /// Size as read from the winning source, including the DDS header of
+    /// BA2 textures
+    pub size: u64,
+    /// Index of the winning source in [`Vfs::sources`]
+    pub source: usize,
+    /// Copies in the sources this file overrides, lowest priority first
+    pub overridden: Vec<VfsLayer>,
+}
+
+/// One source's copy of a file
+#[derive(Debug, Clone)]
+pub struct VfsLayer {
+    /// Index of the source in [`Vfs::sources`]
+    pub source: usize,
+    /// Path as stored in that source
+    pub path: String,
+    /// Size as read from that source
+    pub size: u64,
+}
+
+impl VfsFile {
+    /// Every copy of this file, lowest priority first; the winner is last
+    pub fn layers(&self) -> Vec<VfsLayer> {
+        let mut layers = self.overridden.clone();
+        layers.push(VfsLayer {
+            source: self.source,
+            path: self.path.clone(),
+            size: self.size,
+        });
+        layers
+    }
+}
+
+/// An entry of a merged directory listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VfsDirEntry {
+    /// File or directory name, cased as in the first source that has it
+    pub name: String,
+    /// Whether this entry is a directory
+    pub is_dir: bool,
+}
+
+/// Layered view over archives and loose directories
+#[derive(Debug, Default)]
+pub struct Vfs {
+    sources: Vec<VfsSource>,
+    /// Normalized path -> resolved file
+    files: BTreeMap<String, VfsFile>,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open an archive and layer it over the archives added before it
+    pub fn add_archive(&mut self, path: &Path) -> Result<()> {
+        let archive = ArchiveHandle::open(path)?;
+        let files: Vec<(String, u64)> = archive
+            .entries()?
+            .iter()
+            .map(|e| (e.path.clone(), e.extracted_size()))
+            .collect();
+        self.add_source(VfsSource::Archive(archive), files);
+        Ok(())
+    }
+
+    /// Layer a loose directory over all archives and earlier loose directories
+    pub fn add_loose_dir(&mut self, dir: &Path) -> Result<()> {
+        if !dir.is_dir() {
+            bail!("Not a directory: {}", dir.display());
+        }
+        let mut files = Vec::new();
+        for entry in WalkDir::new(dir) {
+            let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if let Ok(rel) = entry.path().strip_prefix(dir) {
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                let path = rel.to_string_lossy().replace('\\', "/");
+                files.push((path, size));
+            }
+        }
+        self.add_source(VfsSource::Loose(dir.to_path_buf()), files);
+        Ok(())
+    }
+
+    /// Layer rank of a source: loose directories outrank archives,
+    /// otherwise later sources win
+    fn rank(&self, index: usize) -> (bool, usize) {
+        (self.sources[index].is_loose(), index)
+    }
+
+    fn add_source(&mut self, source: VfsSource, files: Vec<(String, u64)>) {
+        let index = self.sources.len();
+        self.sources.push(source);
+
+        for (path, size) in files {
+            let key = normalize_path(&path);
+            let Some(existing) = self.files.get(&key) else {
+                self.files.insert(
+                    key,
+                    VfsFile {
+                        path,
+                        size,
+                        source: index,
+                        overridden: Vec::new(),
+                    },
+                );
+                continue;
+            };
+            // Paths that differ only in case within one source
+            if existing.source == index {
+                continue;
+            }
+
+            let mut layers = existing.layers();
+            layers.push(VfsLayer {
+                source: index,
+                path,
+                size,
+            });
+            layers.sort_by_key(|l| self.rank(l.source));
+
+            let file = self.files.get_mut(&key).unwrap();
+            let winner = layers.pop().unwrap();
+            file.path = winner.path;
+            file.size = winner.size;
+            file.source = winner.source;
+            file.overridden = layers;
+        }
+    }
+
+    /// All sources, in the order they were added
+    pub fn sources(&self) -> &[VfsSource] {
+        &self.sources
+    }
+
+    /// Resolve a path (either slash convention, any case)
+    pub fn lookup(&self, path: &str) -> Option<&VfsFile> {
+        self.files.get(&normalize_path(path))
+    }
+
+    /// The source that wins for a path
+    pub fn source_of(&self, path: &str) -> Option<&VfsSource> {
+        self.lookup(path).map(|f| &self.sources[f.source])
+    }
+
+    /// Every resolved file, sorted by normalized path
+    pub fn files(&self) -> impl Iterator<Item = &VfsFile> {
+        self.files.values()
+    }
+
+    /// Number of resolved files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether the VFS has no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Read a file from the source that wins for it
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let Some(file) = self.lookup(path) else {
+            bail!("File not found in any source: {}", path);
+        };
+        self.read_from(file.source, &file.path)
+    }
+
+    /// Read a file from a specific source, using the path as stored there
+    pub fn read_from(&self, source: usize, path: &str) -> Result<Vec<u8>> {
+        match &self.sources[source] {
+            VfsSource::Archive(archive) => archive.read(path),
+            VfsSource::Loose(dir) => {
+                let disk_path = dir.join(path);
+                std::fs::read(&disk_path)
+                    .with_context(|| format!("Failed to read: {}", disk_path.display()))
+            }
+        }
+    }
+
+    /// Whether a path is a directory in any source
+    pub fn is_dir(&self, path: &str) -> bool {
+        let prefix = dir_prefix(path);
+        prefix.is_empty()
+            || self
+                .files
+                .range(prefix.clone()..)
+                .next()
+                .is_some_and(|(key, _)| key.starts_with(&prefix))
+    }
+
+    /// List a directory across all sources, directories first, then by name.
+    /// An empty path lists the root.
+    pub fn read_dir(&self, path: &str) -> Vec<VfsDirEntry> {
+        let prefix = dir_prefix(path);
+        let mut dirs: BTreeMap<String, String> = BTreeMap::new();
+        let mut files: BTreeMap<String, String> = BTreeMap::new();
+
+        for (key, file) in self.files.range(prefix.clone()..) {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let rest = &key[prefix.len()..];
+            // Take the display name from the stored path, which has the
+            // same components as the key
+            let depth = prefix.matches('/').count();
+            let stored: Vec<&str> = file.path.split(['/', '\\']).collect();
+            match rest.split_once('/') {
+                Some((dir, _)) => {
+                    let name = stored.get(depth).copied().unwrap_or(dir);
+                    dirs.entry(dir.to_string())
+                        .or_insert_with(|| name.to_string());
+                }
+                None => {
+                    let name = stored.last().copied().unwrap_or(rest);
+                    files.insert(rest.to_string(), name.to_string());
+                }
+            }
+        }
+
+        dirs.into_values()
+            .map(|name| VfsDirEntry { name, is_dir: true })
+            .chain(files.into_values().map(|name| VfsDirEntry {
+                name,
+                is_dir: false,
+            }))
+            .collect()
+    }
+}
+
+/// Normalized directory path with a trailing slash, or empty for the root
+fn dir_prefix(path: &str) -> String {
+    let normalized = normalize_path(path);
+    let trimmed = normalized.trim_end_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", trimmed)
+    }
+}
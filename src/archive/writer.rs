@@ -7,26 +7,99 @@ use ba2::tes4::{
 };
 use ba2::CompressableFrom;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::BufWriter;
 use std::path::Path;
-use tracing::info;
+use tracing::{info, warn};
 
+use super::filter::normalize_path;
 use super::{default_flags_fo3, default_flags_oblivion, detect_types, detect_version};
 
+/// Contents of a file added to the builder
+enum FileData {
+    /// Decompressed bytes
+    Raw(Vec<u8>),
+    /// A file as stored in another BSA, possibly compressed
+    Stored {
+        file: BsaFile<'static>,
+        version: Version,
+    },
+}
+
+impl FileData {
+    /// Decompressed size in bytes
+    fn len(&self) -> usize {
+        match self {
+            FileData::Raw(data) => data.len(),
+            FileData::Stored { file, .. } => file.decompressed_len().unwrap_or(file.len()),
+        }
+    }
+}
+
 /// Helper struct to hold file data with lifetime for BSA creation
 struct FileEntry {
     dir_path: String,
     file_name: String,
-    data: Vec<u8>,
+    data: FileData,
 }
 
 impl FileEntry {
-    /// Create a BSA file, optionally compressing it
+    /// Path as added, with forward slashes
+    fn path(&self) -> String {
+        if self.dir_path == "." {
+            self.file_name.clone()
+        } else {
+            format!("{}/{}", self.dir_path, self.file_name)
+        }
+    }
+
+    /// Check if the stored data can be written without recompressing
+    fn copies_as_is(&self, version: Version, should_compress: bool) -> bool {
+        match &self.data {
+            FileData::Raw(_) => false,
+            FileData::Stored {
+                file,
+                version: source,
+            } => {
+                if file.is_compressed() {
+                    should_compress && same_codec(*source, version)
+                } else {
+                    !should_compress
+                }
+            }
+        }
+    }
+
+    /// Create a BSA file, optionally compressing it.
+    /// Stored files already compressed with the target codec are copied as-is.
     fn as_bsa_file(&self, version: Version, should_compress: bool) -> Result<BsaFile<'static>> {
+        let data = match &self.data {
+            FileData::Raw(data) => data.clone(),
+            FileData::Stored { file, .. } if self.copies_as_is(version, should_compress) => {
+                return Ok(file.clone());
+            }
+            FileData::Stored {
+                file,
+                version: source,
+            } => {
+                if file.is_compressed() {
+                    let compression_options =
+                        FileCompressionOptions::builder().version(*source).build();
+                    file.decompress(&compression_options)
+                        .with_context(|| {
+                            format!("Failed to decompress: {}/{}", self.dir_path, self.file_name)
+                        })?
+                        .as_bytes()
+                        .to_vec()
+                } else {
+                    file.as_bytes().to_vec()
+                }
+            }
+        };
+
         // Create an uncompressed file from our raw data
-        let uncompressed = BsaFile::from_decompressed(self.data.clone().into_boxed_slice());
+        let uncompressed = BsaFile::from_decompressed(data.into_boxed_slice());
 
         if should_compress {
             // Compress the file using ba2's compress method
@@ -43,10 +116,24 @@ impl FileEntry {
     }
 }
 
-/// Builder for creating BSA archives
+/// Check if two BSA versions compress with the same codec (zlib up to v104, LZ4 in v105)
+fn same_codec(a: Version, b: Version) -> bool {
+    (a == Version::v105) == (b == Version::v105)
+}
+
+/// Builder for creating BSA archives.
+///
+/// Output is byte-reproducible: files are kept in path order and written in
+/// hash order with fixed compression settings, whatever order they were
+/// added in. Paths that differ only in case are the same file; the one
+/// added last wins.
+///
+/// There is no [`ArchiveOrder`](super::ArchiveOrder) here: the game
+/// binary-searches TES4 folder and file records by hash, so official BSAs
+/// are in hash order too.
 pub struct BsaBuilder {
-    /// Files organized by directory -> filename -> data
-    files: HashMap<String, HashMap<String, Vec<u8>>>,
+    /// Files keyed by normalized path
+    files: BTreeMap<String, FileEntry>,
     flags: ArchiveFlags,
     types: ArchiveTypes,
     version: Version,
@@ -55,7 +142,7 @@ pub struct BsaBuilder {
 impl BsaBuilder {
     pub fn new() -> Self {
         Self {
-            files: HashMap::new(),
+            files: BTreeMap::new(),
             flags: default_flags_fo3(),
             types: ArchiveTypes::empty(),
             version: Version::v104,
@@ -63,7 +150,6 @@ impl BsaBuilder {
     }
 
     /// Create builder with settings detected from BSA name
-    #[allow(dead_code)]
     pub fn from_name(name: &str) -> Self {
         let version = detect_version(name);
         let types = detect_types(name);
@@ -74,7 +160,7 @@ impl BsaBuilder {
         };
 
         Self {
-            files: HashMap::new(),
+            files: BTreeMap::new(),
             flags,
             types,
             version,
@@ -82,14 +168,12 @@ impl BsaBuilder {
     }
 
     /// Set archive flags
-    #[allow(dead_code)]
     pub fn with_flags(mut self, flags: ArchiveFlags) -> Self {
         self.flags = flags;
         self
     }
 
     /// Set archive types
-    #[allow(dead_code)]
     pub fn with_types(mut self, types: ArchiveTypes) -> Self {
         self.types = types;
         self
@@ -113,6 +197,23 @@ impl BsaBuilder {
 
     /// Add a file to the archive
     pub fn add_file(&mut self, path: &str, data: Vec<u8>) {
+        self.insert(path, FileData::Raw(data));
+    }
+
+    /// Add a file as stored in another BSA of version `source_version`.
+    /// Compressed data is copied without recompressing when the target
+    /// compresses with the same codec, which makes repacks much faster.
+    pub fn add_stored_file(&mut self, path: &str, file: BsaFile<'static>, source_version: Version) {
+        self.insert(
+            path,
+            FileData::Stored {
+                file,
+                version: source_version,
+            },
+        );
+    }
+
+    fn insert(&mut self, path: &str, data: FileData) {
         // Normalize: forward slashes, strip leading slash
         let normalized = path.replace('\\', "/");
         let normalized = normalized.trim_start_matches('/');
@@ -126,15 +227,25 @@ impl BsaBuilder {
             (".".to_string(), normalized.to_string())
         };
 
-        self.files
-            .entry(dir_path)
-            .or_default()
-            .insert(file_name, data);
+        let entry = FileEntry {
+            dir_path,
+            file_name,
+            data,
+        };
+        if let Some(replaced) = self.files.insert(normalize_path(normalized), entry) {
+            let previous = replaced.path();
+            if previous != normalized {
+                warn!(
+                    "{} replaces {} (paths differ only in case)",
+                    normalized, previous
+                );
+            }
+        }
     }
 
     /// Get number of files
     pub fn file_count(&self) -> usize {
-        self.files.values().map(|d| d.len()).sum()
+        self.files.len()
     }
 
     /// Check if empty
@@ -152,12 +263,7 @@ impl BsaBuilder {
         }
 
         let file_count = self.file_count();
-        let total_size: u64 = self
-            .files
-            .values()
-            .flat_map(|files| files.values())
-            .map(|data| data.len() as u64)
-            .sum();
+        let total_size: u64 = self.files.values().map(|e| e.data.len() as u64).sum();
 
         info!(
             "Building BSA: {} ({} files, {} MB, version {:?}, flags {:?})",
@@ -171,20 +277,20 @@ impl BsaBuilder {
         // Check if we should compress files
         let should_compress = self.flags.contains(ArchiveFlags::COMPRESSED);
 
-        // Flatten to FileEntry structs that own their data
-        let entries: Vec<FileEntry> = self
-            .files
-            .into_iter()
-            .flat_map(|(dir_path, files)| {
-                files.into_iter().map(move |(file_name, data)| FileEntry {
-                    dir_path: dir_path.clone(),
-                    file_name,
-                    data,
-                })
-            })
-            .collect();
+        // Sorted by path, so the parallel results come back in a fixed order
+        let entries: Vec<FileEntry> = self.files.into_values().collect();
 
         let total = entries.len();
+        let copied = entries
+            .iter()
+            .filter(|e| e.copies_as_is(self.version, should_compress))
+            .count();
+        if copied > 0 {
+            info!(
+                "Copying {} of {} files without recompressing",
+                copied, total
+            );
+        }
         let processed_count = std::sync::atomic::AtomicUsize::new(0);
 
         // Process files in parallel - create and compress BsaFile entries
@@ -206,22 +312,38 @@ impl BsaBuilder {
 
         let processed = processed?;
 
-        // Build archive
+        // Build archive. Keys are ordered by hash, so two different names
+        // with the same hash would silently replace each other.
         let mut archive = Archive::new();
         for (dir_path, file_name, file) in processed {
             let archive_key = ArchiveKey::from(dir_path.as_bytes());
             let directory_key = DirectoryKey::from(file_name.as_bytes());
 
-            match archive.get_mut(&archive_key) {
-                Some(directory) => {
-                    directory.insert(directory_key, file);
+            if let Some((existing, _)) = archive.get_key_value(&archive_key) {
+                if existing.name() != archive_key.name() {
+                    bail!(
+                        "Directory hash collision: {} and {}",
+                        existing.name(),
+                        archive_key.name()
+                    );
                 }
+            }
+            let directory = match archive.get_mut(&archive_key) {
+                Some(directory) => directory,
                 None => {
-                    let mut directory = Directory::default();
-                    directory.insert(directory_key, file);
-                    archive.insert(archive_key, directory);
+                    archive.insert(archive_key.clone(), Directory::default());
+                    archive.get_mut(&archive_key).unwrap()
                 }
+            };
+            if let Some((existing, _)) = directory.get_key_value(&directory_key) {
+                bail!(
+                    "File hash collision in {}: {} and {}",
+                    dir_path,
+                    existing.name(),
+                    directory_key.name()
+                );
             }
+            directory.insert(directory_key, file);
         }
 
         let options = ArchiveOptions::builder()
@@ -1,5 +1,6 @@
 //! GUI definition using Slint
 
+mod preview;
 pub mod state;
 
 slint::slint! {
@@ -22,11 +23,49 @@ slint::slint! {
     component TreeRow inherits Rectangle {
         in property <TreeNode> node;
         in property <bool> odd_row;
+        in property <bool> editable;
+        in property <bool> current;
+        callback activate(int);
         callback toggle_expand(int);
         callback toggle_select(int);
+        callback context_action(string, int);
+        callback request_rename(int, string);
 
         height: node.visible ? 22px : 0px;
-        background: odd_row ? #2a2a2a : #252525;
+        background: current ? #2f4a6a : odd_row ? #2a2a2a : #252525;
+
+        // Left click on a file opens it in the preview pane. Declared first so
+        // the context menu and the row's own controls sit above it.
+        TouchArea {
+            enabled: node.visible && !node.is_folder;
+            clicked => { activate(node.index); }
+        }
+
+        // Archive editing actions
+        ContextMenuArea {
+            enabled: editable && node.visible;
+            Menu {
+                MenuItem {
+                    title: node.is_folder ? "Add File Here..." : "Add File Next to This...";
+                    activated => { context_action("add", node.index); }
+                }
+                MenuItem {
+                    title: "Replace...";
+                    enabled: !node.is_folder;
+                    activated => { context_action("replace", node.index); }
+                }
+                MenuItem {
+                    title: "Rename...";
+                    enabled: node.depth > 0;
+                    activated => { request_rename(node.index, node.path); }
+                }
+                MenuItem {
+                    title: "Delete";
+                    enabled: node.depth > 0;
+                    activated => { context_action("delete", node.index); }
+                }
+            }
+        }
 
         if node.visible: HorizontalLayout {
             padding-left: (node.depth * 16px) + 4px;
@@ -95,6 +134,196 @@ slint::slint! {
         }
     }
 
+    // Preview of the file picked in the tree
+    component PreviewPane inherits Rectangle {
+        in property <string> title;
+        in property <string> info;
+        in property <string> message;   // Shown instead of the content when set
+        in property <bool> is_text;     // Text or hex lines instead of an image
+        in property <image> image;
+        in property <[string]> mips;
+        in-out property <int> mip;
+        in property <[string]> lines;
+        in property <bool> line_numbers;
+        in property <int> gutter_digits;
+        in property <string> copy_text;
+        callback mip_changed(int);
+        callback copied();
+        callback close();
+
+        background: #252525;
+        border-width: 1px;
+        border-color: #444444;
+
+        // Holds the text for the clipboard; never shown. Wrapped so the
+        // text's size doesn't become the pane's minimum size.
+        Rectangle {
+            width: 0px;
+            height: 0px;
+            visible: false;
+
+            copy_source := TextInput {
+                read-only: true;
+                text: copy_text;
+            }
+        }
+
+        VerticalLayout {
+            Rectangle {
+                height: 24px;
+                background: #333333;
+
+                HorizontalLayout {
+                    padding-left: 8px;
+                    padding-right: 4px;
+                    spacing: 4px;
+
+                    Text {
+                        text: title;
+                        font-weight: 600;
+                        vertical-alignment: center;
+                        font-size: 12px;
+                        color: #e0e0e0;
+                        overflow: elide;
+                        horizontal-stretch: 1;
+                    }
+
+                    Rectangle {
+                        width: 20px;
+                        background: close_touch.has-hover ? #3d3d3d : transparent;
+
+                        close_touch := TouchArea {
+                            clicked => { close(); }
+                        }
+
+                        Text {
+                            text: "✕";
+                            font-size: 11px;
+                            color: #aaaaaa;
+                            horizontal-alignment: center;
+                            vertical-alignment: center;
+                        }
+                    }
+                }
+            }
+
+            Rectangle {
+                vertical-stretch: 1;
+                background: #1a1a1a;
+                clip: true;
+
+                if message == "" && is_text: ListView {
+                    for line[idx] in lines: HorizontalLayout {
+                        height: 16px;
+                        padding-left: 4px;
+                        spacing: 8px;
+
+                        if line_numbers: Text {
+                            width: gutter_digits * 8px;
+                            text: idx + 1;
+                            horizontal-alignment: right;
+                            vertical-alignment: center;
+                            font-family: "monospace";
+                            font-size: 12px;
+                            color: #666666;
+                        }
+
+                        Text {
+                            text: line;
+                            vertical-alignment: center;
+                            font-family: "monospace";
+                            font-size: 12px;
+                            color: #e0e0e0;
+                            horizontal-stretch: 1;
+                        }
+                    }
+                }
+
+                if message == "" && !is_text: Image {
+                    x: 8px;
+                    y: 8px;
+                    width: parent.width - 16px;
+                    height: parent.height - 16px;
+                    source: image;
+                    image-fit: contain;
+                    image-rendering: pixelated;
+                }
+
+                if message != "": Text {
+                    text: message;
+                    horizontal-alignment: center;
+                    vertical-alignment: center;
+                    wrap: word-wrap;
+                    color: #888888;
+                    font-size: 12px;
+                }
+            }
+
+            Rectangle {
+                height: mips.length > 1 ? 60px : 28px;
+                background: #2d2d2d;
+
+                VerticalLayout {
+                    padding: 4px;
+                    spacing: 4px;
+
+                    HorizontalLayout {
+                        spacing: 4px;
+
+                        Text {
+                            text: info;
+                            vertical-alignment: center;
+                            font-size: 11px;
+                            color: #aaaaaa;
+                            overflow: elide;
+                            horizontal-stretch: 1;
+                        }
+
+                        if is_text && message == "": Rectangle {
+                            width: 44px;
+                            background: copy_touch.has-hover ? #3d3d3d : transparent;
+
+                            copy_touch := TouchArea {
+                                clicked => {
+                                    copy_source.select-all();
+                                    copy_source.copy();
+                                    copy_source.clear-selection();
+                                    copied();
+                                }
+                            }
+
+                            Text {
+                                text: "Copy";
+                                font-size: 11px;
+                                color: #e0e0e0;
+                                horizontal-alignment: center;
+                                vertical-alignment: center;
+                            }
+                        }
+                    }
+
+                    if mips.length > 1: HorizontalLayout {
+                        spacing: 4px;
+
+                        Text {
+                            text: "Mip:";
+                            vertical-alignment: center;
+                            font-size: 12px;
+                            color: #aaaaaa;
+                        }
+
+                        ComboBox {
+                            horizontal-stretch: 1;
+                            model: mips;
+                            current-index <=> mip;
+                            selected => { mip_changed(mip); }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     export component MainWindow inherits Window {
         title: "BSA/BA2 Archive Tool";
         min-width: 400px;
@@ -113,10 +342,29 @@ slint::slint! {
         in-out property <bool> pack_mode: false;
         in-out property <[string]> game_versions: [];
         in-out property <int> selected_game_version: 0;
+        in-out property <[string]> case_modes: [];
+        in-out property <int> selected_case_mode: 0;
+        in-out property <int> rename_index: -1;
+        in-out property <string> rename_text: "";
+        in-out property <int> current_index: -1;
+        in-out property <bool> preview_visible: false;
+        in-out property <string> preview_title: "";
+        in-out property <string> preview_info: "";
+        in-out property <string> preview_message: "";
+        in-out property <bool> preview_is_text: false;
+        in-out property <[string]> preview_lines: [];
+        in-out property <bool> preview_line_numbers: true;
+        in-out property <int> preview_gutter_digits: 1;
+        in-out property <string> preview_copy_text: "";
+        in-out property <image> preview_image;
+        in-out property <[string]> preview_mips: [];
+        in-out property <int> preview_mip: 0;
 
         // Callbacks
         callback open_file();
         callback open_folder();
+        callback load_manifest();
+        callback save_manifest();
         callback extract();
         callback pack();
         callback select_all();
@@ -124,7 +372,11 @@ slint::slint! {
         callback search_changed(string);
         callback toggle_expand(int);
         callback toggle_select(int);
-
+        callback context_action(string, int);
+        callback rename_entry(int, string);
+        callback preview_entry(int);
+        callback preview_mip_changed(int);
+        callback close_preview();
 
         VerticalLayout {
             // Menu bar
@@ -217,6 +469,24 @@ slint::slint! {
                             current-index <=> selected_game_version;
                         }
                     }
+
+                    if !pack_mode: HorizontalLayout {
+                        spacing: 4px;
+                        alignment: end;
+
+                        Text {
+                            text: "Case:";
+                            vertical-alignment: center;
+                            font-size: 12px;
+                            color: #aaaaaa;
+                        }
+
+                        ComboBox {
+                            width: 180px;
+                            model: case_modes;
+                            current-index <=> selected_case_mode;
+                        }
+                    }
                 }
             }
 
@@ -238,48 +508,84 @@ slint::slint! {
                 }
             }
 
-            // Tree view header
-            Rectangle {
-                height: 24px;
-                background: #333333;
-                border-width: 1px;
-                border-color: #444444;
+            HorizontalLayout {
+                vertical-stretch: 1;
 
-                HorizontalLayout {
-                    padding-left: 8px;
-                    Text {
-                        text: "File";
-                        font-weight: 600;
-                        vertical-alignment: center;
-                        font-size: 12px;
-                        color: #e0e0e0;
+                VerticalLayout {
+                    horizontal-stretch: 1;
+
+                    // Tree view header
+                    Rectangle {
+                        height: 24px;
+                        background: #333333;
+                        border-width: 1px;
+                        border-color: #444444;
+
+                        HorizontalLayout {
+                            padding-left: 8px;
+                            Text {
+                                text: "File";
+                                font-weight: 600;
+                                vertical-alignment: center;
+                                font-size: 12px;
+                                color: #e0e0e0;
+                            }
+                        }
                     }
-                }
-            }
 
-            // Tree view content
-            Rectangle {
-                vertical-stretch: 1;
-                background: #252525;
-                border-width: 1px;
-                border-color: #444444;
-                clip: true;
+                    // Tree view content
+                    Rectangle {
+                        vertical-stretch: 1;
+                        background: #252525;
+                        border-width: 1px;
+                        border-color: #444444;
+                        clip: true;
+
+                        ListView {
+                            for node[idx] in tree_nodes: TreeRow {
+                                node: node;
+                                odd_row: mod(idx, 2) == 1;
+                                current: node.index == current_index;
+                                activate(i) => { root.preview_entry(i); }
+                                toggle_expand(i) => { root.toggle_expand(i); }
+                                toggle_select(i) => { root.toggle_select(i); }
+                                editable: !pack_mode && !is_processing;
+                                context_action(action, i) => { root.context_action(action, i); }
+                                request_rename(i, path) => {
+                                    root.rename_index = i;
+                                    root.rename_text = path;
+                                    rename_popup.show();
+                                }
+                            }
+                        }
 
-                ListView {
-                    for node[idx] in tree_nodes: TreeRow {
-                        node: node;
-                        odd_row: mod(idx, 2) == 1;
-                        toggle_expand(i) => { root.toggle_expand(i); }
-                        toggle_select(i) => { root.toggle_select(i); }
+                        if tree_nodes.length == 0: Text {
+                            text: "Drag and drop BSA/BA2 file here\nor use File → Open";
+                            horizontal-alignment: center;
+                            vertical-alignment: center;
+                            color: #666666;
+                            font-size: 14px;
+                        }
                     }
                 }
 
-                if tree_nodes.length == 0: Text {
-                    text: "Drag and drop BSA/BA2 file here\nor use File → Open";
-                    horizontal-alignment: center;
-                    vertical-alignment: center;
-                    color: #666666;
-                    font-size: 14px;
+                if preview_visible: PreviewPane {
+                    horizontal-stretch: 1;
+                    min-width: 240px;
+                    title: preview_title;
+                    info: preview_info;
+                    message: preview_message;
+                    is_text: preview_is_text;
+                    image: preview_image;
+                    mips: preview_mips;
+                    mip <=> preview_mip;
+                    lines: preview_lines;
+                    line_numbers: preview_line_numbers;
+                    gutter_digits: preview_gutter_digits;
+                    copy_text: preview_copy_text;
+                    mip_changed(m) => { root.preview_mip_changed(m); }
+                    copied => { root.status_text = "Copied " + preview_title + " to the clipboard"; }
+                    close => { root.close_preview(); }
                 }
             }
 
@@ -354,7 +660,7 @@ slint::slint! {
             x: 4px;
             y: 28px;
             width: 150px;
-            height: 70px;
+            height: 118px;
 
             Rectangle {
                 background: #2d2d2d;
@@ -409,6 +715,104 @@ slint::slint! {
                             }
                         }
                     }
+
+                    Rectangle {
+                        height: 24px;
+                        background: load_manifest_touch.has-hover ? #3d5a80 : transparent;
+
+                        load_manifest_touch := TouchArea {
+                            clicked => {
+                                file_popup.close();
+                                load_manifest();
+                            }
+                        }
+
+                        HorizontalLayout {
+                            padding-left: 8px;
+                            Text {
+                                text: "Load Manifest...";
+                                vertical-alignment: center;
+                                font-size: 12px;
+                                color: #e0e0e0;
+                            }
+                        }
+                    }
+
+                    Rectangle {
+                        height: 24px;
+                        background: pack_mode && save_manifest_touch.has-hover ? #3d5a80 : transparent;
+
+                        save_manifest_touch := TouchArea {
+                            enabled: pack_mode;
+                            clicked => {
+                                file_popup.close();
+                                save_manifest();
+                            }
+                        }
+
+                        HorizontalLayout {
+                            padding-left: 8px;
+                            Text {
+                                text: "Save Manifest...";
+                                vertical-alignment: center;
+                                font-size: 12px;
+                                color: pack_mode ? #e0e0e0 : #707070;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Rename popup
+        rename_popup := PopupWindow {
+            x: (root.width - 360px) / 2;
+            y: (root.height - 110px) / 2;
+            width: 360px;
+            height: 110px;
+            close-policy: close-on-click-outside;
+
+            Rectangle {
+                background: #2d2d2d;
+                border-width: 1px;
+                border-color: #444444;
+                drop-shadow-blur: 8px;
+                drop-shadow-color: #000000a0;
+
+                VerticalLayout {
+                    padding: 12px;
+                    spacing: 8px;
+
+                    Text {
+                        text: "New path:";
+                        font-size: 12px;
+                        color: #e0e0e0;
+                    }
+
+                    LineEdit {
+                        text <=> rename_text;
+                        accepted => {
+                            rename_popup.close();
+                            rename_entry(rename_index, rename_text);
+                        }
+                    }
+
+                    HorizontalLayout {
+                        spacing: 8px;
+                        alignment: end;
+
+                        Button {
+                            text: "Cancel";
+                            clicked => { rename_popup.close(); }
+                        }
+                        Button {
+                            text: "Rename";
+                            clicked => {
+                                rename_popup.close();
+                                rename_entry(rename_index, rename_text);
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -0,0 +1,168 @@
+//! Text and hex dump rendering for the preview pane
+
+/// Most text shown, in bytes; longer files are cut off
+const MAX_TEXT_BYTES: usize = 1 << 20;
+/// Most binary data shown as a hex dump
+const MAX_HEX_BYTES: usize = 64 << 10;
+/// Bytes per hex dump line
+const HEX_WIDTH: usize = 16;
+
+/// A file rendered as lines of text
+pub(crate) struct TextPreview {
+    /// Lines as displayed, tabs expanded
+    pub lines: Vec<String>,
+    /// Text for the clipboard
+    pub copy_text: String,
+    /// A hex dump rather than decoded text
+    pub hex: bool,
+    /// One-line summary: encoding, line count, size
+    pub info: String,
+}
+
+/// Decode `data` as text when it looks like text, otherwise dump it as hex
+pub(crate) fn text_preview(data: &[u8]) -> TextPreview {
+    let shown = &data[..data.len().min(MAX_TEXT_BYTES)];
+    let Some((text, encoding)) = decode_text(shown) else {
+        return hex_preview(data);
+    };
+
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| line.replace('\t', "    "))
+        .collect();
+    let mut info = format!(
+        "{} text · {} line{} · {}",
+        encoding,
+        lines.len(),
+        if lines.len() == 1 { "" } else { "s" },
+        format_size(data.len())
+    );
+    if shown.len() < data.len() {
+        info.push_str(&format!(" · first {} shown", format_size(shown.len())));
+    }
+    TextPreview {
+        lines,
+        copy_text: text,
+        hex: false,
+        info,
+    }
+}
+
+fn hex_preview(data: &[u8]) -> TextPreview {
+    let shown = &data[..data.len().min(MAX_HEX_BYTES)];
+    let lines: Vec<String> = shown
+        .chunks(HEX_WIDTH)
+        .enumerate()
+        .map(|(row, bytes)| hex_line(row * HEX_WIDTH, bytes))
+        .collect();
+    let mut info = format!("Binary · {}", format_size(data.len()));
+    if shown.len() < data.len() {
+        info.push_str(&format!(" · first {} shown", format_size(shown.len())));
+    }
+    TextPreview {
+        copy_text: lines.join("\n"),
+        lines,
+        hex: true,
+        info,
+    }
+}
+
+/// `00000010  48 65 6c 6c 6f 0a ...  |Hello.|`
+fn hex_line(offset: usize, bytes: &[u8]) -> String {
+    let mut line = format!("{:08x} ", offset);
+    for i in 0..HEX_WIDTH {
+        if i % 8 == 0 {
+            line.push(' ');
+        }
+        match bytes.get(i) {
+            Some(byte) => line.push_str(&format!("{:02x} ", byte)),
+            None => line.push_str("   "),
+        }
+    }
+    line.push_str(" |");
+    line.extend(bytes.iter().map(|&b| {
+        if b.is_ascii_graphic() || b == b' ' {
+            b as char
+        } else {
+            '.'
+        }
+    }));
+    line.push('|');
+    line
+}
+
+/// Decode text with a UTF-8/UTF-16 byte order mark, as UTF-8, or as
+/// Windows-1252 (older Bethesda files); `None` for binary data
+fn decode_text(data: &[u8]) -> Option<(String, &'static str)> {
+    if let Some(rest) = data.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return Some((String::from_utf8_lossy(rest).into_owned(), "UTF-8"));
+    }
+    if let Some(rest) = data.strip_prefix(&[0xFF, 0xFE]) {
+        let units = rest
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]));
+        return Some((decode_utf16(units), "UTF-16"));
+    }
+    if let Some(rest) = data.strip_prefix(&[0xFE, 0xFF]) {
+        let units = rest
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]));
+        return Some((decode_utf16(units), "UTF-16"));
+    }
+
+    // NULs or many control characters mean binary
+    let control = data
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0C))
+        .count();
+    if data.contains(&0) || control * 20 > data.len() {
+        return None;
+    }
+    match std::str::from_utf8(data) {
+        Ok(text) => Some((text.to_string(), "UTF-8")),
+        // Cut mid-character by the size limit
+        Err(e) if e.error_len().is_none() => Some((
+            String::from_utf8_lossy(&data[..e.valid_up_to()]).into_owned(),
+            "UTF-8",
+        )),
+        Err(_) => Some((
+            data.iter().map(|&b| windows_1252(b)).collect(),
+            "Windows-1252",
+        )),
+    }
+}
+
+fn decode_utf16(units: impl Iterator<Item = u16>) -> String {
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Windows-1252 byte to char; 0x80-0x9F differ from Latin-1
+fn windows_1252(byte: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž',
+        '\u{8F}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}',
+        'ž', 'Ÿ',
+    ];
+    match byte {
+        0x80..=0x9F => HIGH[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
+
+/// Human-readable size, e.g. `4.2 KB`
+fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
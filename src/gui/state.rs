@@ -1,18 +1,22 @@
 //! Application state management
 
-use crate::archive::{
-    detect_game_version, extract_archive_files_batch, list_archive_files, ArchiveFileEntry,
-    Ba2Builder, Ba2Format, BsaBuilder, GameVersion,
-};
+use crate::gui::preview::text_preview;
 use crate::gui::{MainWindow, TreeNode};
 use anyhow::{bail, Result};
-use slint::{ComponentHandle, ModelRc, SharedString, VecModel, Weak};
+use bsa_ba2_tool::archive::{
+    archive_load_warning, decode_dds_mip, find_plugins, inspect_audio, inspect_dds, inspect_nif,
+    is_audio_path, plugin_archive_names, ArchiveEditor, ArchiveFileEntry, ArchiveHandle,
+    CaseFolding, DdsInfo, ExtractionPlan, GameVersion, PackManifest, PackStage,
+};
+use slint::{
+    ComponentHandle, Image, ModelRc, Rgba8Pixel, SharedPixelBuffer, SharedString, VecModel, Weak,
+};
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use tracing::error;
+use tracing::{error, warn};
 use walkdir::WalkDir;
 
 /// Thread-safe state handle
@@ -32,10 +36,33 @@ pub(crate) struct InternalNode {
     parent: Option<usize>,
 }
 
+/// The file shown in the preview pane
+pub struct Preview {
+    /// Path as shown in the tree
+    pub path: String,
+    /// DDS data and header, once loaded
+    pub texture: Option<(Arc<Vec<u8>>, DdsInfo)>,
+}
+
+/// Where the files in the tree are read from
+enum FileSource {
+    Archive(ArchiveHandle),
+    Folder(PathBuf),
+}
+
+impl FileSource {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        match self {
+            FileSource::Archive(archive) => archive.read(path),
+            FileSource::Folder(folder) => Ok(fs::read(folder.join(path.replace('\\', "/")))?),
+        }
+    }
+}
+
 /// Application state
 pub struct AppState {
-    /// Archive path (if loaded)
-    pub archive_path: Option<PathBuf>,
+    /// Open archive (if loaded), kept open so extraction doesn't re-parse it
+    pub archive: Option<ArchiveHandle>,
     /// Raw archive entries
     pub entries: Vec<ArchiveFileEntry>,
     /// Hierarchical tree nodes
@@ -50,12 +77,16 @@ pub struct AppState {
     pub pack_mode: bool,
     /// The folder being packed
     pub source_folder: Option<PathBuf>,
+    /// Pack settings from the last loaded or saved manifest
+    pub manifest: Option<PackManifest>,
+    /// File in the preview pane
+    pub preview: Option<Preview>,
 }
 
 impl AppState {
     pub fn new() -> Self {
         Self {
-            archive_path: None,
+            archive: None,
             entries: Vec::new(),
             tree: Vec::new(),
             search_filter: String::new(),
@@ -63,16 +94,20 @@ impl AppState {
             game_version: None,
             pack_mode: false,
             source_folder: None,
+            manifest: None,
+            preview: None,
         }
     }
 
     /// Load an archive and build tree
     pub fn load_archive(&mut self, path: &Path) -> Result<()> {
-        self.archive_path = Some(path.to_path_buf());
-        self.entries = list_archive_files(path)?;
-        self.game_version = detect_game_version(path);
+        let archive = ArchiveHandle::open(path)?;
+        self.entries = archive.entries()?.to_vec();
+        self.game_version = archive.game_version();
+        self.archive = Some(archive);
         self.pack_mode = false;
         self.source_folder = None;
+        self.manifest = None;
 
         let root_name = path
             .file_name()
@@ -87,7 +122,7 @@ impl AppState {
     pub fn load_folder(&mut self, path: &Path) -> Result<()> {
         self.pack_mode = true;
         self.source_folder = Some(path.to_path_buf());
-        self.archive_path = None;
+        self.archive = None;
         self.entries.clear();
 
         let mut paths = Vec::new();
@@ -275,6 +310,75 @@ impl AppState {
         }
     }
 
+    /// Select exactly the files for which `keep(path)` is true
+    pub fn select_where(&mut self, keep: impl Fn(&str) -> bool) {
+        for node in self.tree.iter_mut().filter(|n| !n.is_folder) {
+            node.selected = keep(&node.path);
+            node.partially_selected = false;
+        }
+        // Children always come after their parent, so folders are
+        // settled bottom-up by walking backwards
+        for index in (0..self.tree.len()).rev() {
+            if !self.tree[index].is_folder || self.tree[index].children.is_empty() {
+                continue;
+            }
+            let children = &self.tree[index].children;
+            let all_selected = children
+                .iter()
+                .all(|&i| self.tree[i].selected && !self.tree[i].partially_selected);
+            let any_selected = children
+                .iter()
+                .any(|&i| self.tree[i].selected || self.tree[i].partially_selected);
+            self.tree[index].selected = all_selected;
+            self.tree[index].partially_selected = any_selected && !all_selected;
+        }
+    }
+
+    /// Select the files a manifest would pack: its file list when it has
+    /// one, narrowed by its include/exclude globs
+    pub fn apply_manifest_selection(&mut self, manifest: &PackManifest) -> Result<()> {
+        let filter = manifest.filter()?;
+        let listed: Option<HashSet<String>> = manifest.files.as_ref().map(|files| {
+            files
+                .iter()
+                .map(|f| f.replace('\\', "/").to_lowercase())
+                .collect()
+        });
+        self.select_where(|path| {
+            let normalized = path.replace('\\', "/").to_lowercase();
+            listed.as_ref().is_none_or(|l| l.contains(&normalized)) && filter.matches(path)
+        });
+        Ok(())
+    }
+
+    /// Manifest for packing the current selection, keeping the settings of
+    /// the last loaded manifest. The file list is left out when every file
+    /// is selected, so files added to the folder later are picked up.
+    pub fn to_manifest(&self, game: GameVersion, output: &Path) -> Option<PackManifest> {
+        let source = self.source_folder.as_ref()?;
+        let mut manifest = match &self.manifest {
+            Some(loaded) => {
+                let mut manifest = loaded.clone();
+                manifest.game = game;
+                manifest.source = source.clone();
+                manifest.output = output.to_path_buf();
+                manifest
+            }
+            None => PackManifest::new(game, source, output),
+        };
+        manifest.files = if self.selected_count() == self.total_count() {
+            None
+        } else {
+            Some(
+                self.get_selected_files()
+                    .iter()
+                    .map(|f| f.replace('\\', "/"))
+                    .collect(),
+            )
+        };
+        Some(manifest)
+    }
+
     /// Set search filter
     pub fn set_search(&mut self, filter: String) {
         self.search_filter = filter;
@@ -402,11 +506,38 @@ impl AppState {
             .count()
     }
 
+    /// Archive paths of a file node, or of every file under a folder node
+    pub fn files_under(&self, index: usize) -> Vec<String> {
+        let Some(node) = self.tree.get(index) else {
+            return Vec::new();
+        };
+        if !node.is_folder {
+            return vec![node.path.clone()];
+        }
+        node.children
+            .iter()
+            .flat_map(|&child| self.files_under(child))
+            .collect()
+    }
+
     /// Total file count
     pub fn total_count(&self) -> usize {
         self.tree.iter().filter(|n| !n.is_folder).count()
     }
 
+    /// Where files in the tree are read from, without extracting them
+    fn file_source(&self) -> Option<FileSource> {
+        if let Some(archive) = &self.archive {
+            return Some(FileSource::Archive(archive.clone()));
+        }
+        self.source_folder.clone().map(FileSource::Folder)
+    }
+
+    /// Whether `path` is still the file in the preview pane
+    fn is_previewing(&self, path: &str) -> bool {
+        self.preview.as_ref().is_some_and(|p| p.path == path)
+    }
+
     /// Reset cancel flag
     pub fn reset_cancel(&self) {
         self.cancelled.store(false, Ordering::SeqCst);
@@ -429,15 +560,26 @@ pub fn setup_callbacks(window: &MainWindow, state: StateHandle) {
     window.set_game_versions(ModelRc::new(VecModel::from(names)));
     window.set_selected_game_version(GameVersion::default().index());
 
+    let case_names: Vec<SharedString> = CaseFolding::all()
+        .iter()
+        .map(|m| SharedString::from(m.display_name()))
+        .collect();
+    window.set_case_modes(ModelRc::new(VecModel::from(case_names)));
+    window.set_selected_case_mode(CaseFolding::default().index());
+
     setup_open_file(window, state.clone());
     setup_open_folder(window, state.clone());
+    setup_load_manifest(window, state.clone());
+    setup_save_manifest(window, state.clone());
     setup_extract(window, state.clone());
     setup_pack(window, state.clone());
     setup_select_all(window, state.clone());
     setup_select_none(window, state.clone());
     setup_search(window, state.clone());
     setup_toggle_expand(window, state.clone());
-    setup_toggle_select(window, state);
+    setup_toggle_select(window, state.clone());
+    setup_preview(window, state.clone());
+    setup_edit(window, state);
 }
 
 fn setup_open_file(window: &MainWindow, state: StateHandle) {
@@ -485,6 +627,7 @@ fn setup_open_file(window: &MainWindow, state: StateHandle) {
                             w.set_window_title(SharedString::from(&title));
                             w.set_pack_mode(false);
                             w.set_tree_nodes(model);
+                            hide_preview(&w);
                             w.set_status_text(SharedString::from(format!(
                                 "{} files, {} selected",
                                 total, selected
@@ -527,30 +670,81 @@ fn setup_open_folder(window: &MainWindow, state: StateHandle) {
 
                 let _ = window_weak_thread.upgrade_in_event_loop(move |w: MainWindow| {
                     match result {
-                        Ok(()) => {
-                            let title = format!(
-                                "{} (Pack) - BSA/BA2 Tool",
-                                path.file_name()
-                                    .map(|n| n.to_string_lossy().to_string())
-                                    .unwrap_or_default()
-                            );
-                            let total = new_state.total_count();
-                            let selected = new_state.selected_count();
-                            let model = new_state.to_slint_model();
+                        Ok(()) => show_pack_folder(&w, &state, new_state, &path),
+                        Err(e) => {
+                            error!("Failed to load folder: {}", e);
+                            w.set_status_text(SharedString::from(format!("Error: {}", e)));
+                        }
+                    }
+                    w.set_is_processing(false);
+                });
+            });
+        }
+    });
+}
 
-                            *state.lock().unwrap() = new_state;
+/// Show a folder loaded for packing
+fn show_pack_folder(w: &MainWindow, state: &StateHandle, new_state: AppState, path: &Path) {
+    let title = format!(
+        "{} (Pack) - BSA/BA2 Tool",
+        path.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    );
+    let total = new_state.total_count();
+    let selected = new_state.selected_count();
+    let model = new_state.to_slint_model();
+
+    *state.lock().unwrap() = new_state;
+
+    w.set_window_title(SharedString::from(&title));
+    w.set_pack_mode(true);
+    w.set_tree_nodes(model);
+    hide_preview(w);
+    w.set_status_text(SharedString::from(format!(
+        "{} files, {} selected — choose game version and click Pack",
+        total, selected
+    )));
+}
 
-                            w.set_window_title(SharedString::from(&title));
-                            w.set_pack_mode(true);
-                            w.set_tree_nodes(model);
-                            w.set_status_text(SharedString::from(format!(
-                                "{} files, {} selected — choose game version and click Pack",
-                                total, selected
-                            )));
+fn setup_load_manifest(window: &MainWindow, state: StateHandle) {
+    let window_weak = window.as_weak();
+    window.on_load_manifest(move || {
+        let window = window_weak.unwrap();
+
+        let path = rfd::FileDialog::new()
+            .add_filter("Pack Manifest", &["toml"])
+            .pick_file();
+
+        if let Some(path) = path {
+            window.set_is_processing(true);
+            window.set_status_text(SharedString::from(format!(
+                "Loading manifest {}...",
+                path.display()
+            )));
+
+            let window_weak_thread = window.as_weak();
+            let state = state.clone();
+
+            std::thread::spawn(move || {
+                let mut new_state = AppState::new();
+                let result = PackManifest::load(&path).and_then(|manifest| {
+                    new_state.load_folder(&manifest.source_dir())?;
+                    new_state.apply_manifest_selection(&manifest)?;
+                    Ok(manifest)
+                });
+
+                let _ = window_weak_thread.upgrade_in_event_loop(move |w: MainWindow| {
+                    match result {
+                        Ok(manifest) => {
+                            w.set_selected_game_version(manifest.game.index());
+                            let source = manifest.source_dir();
+                            new_state.manifest = Some(manifest);
+                            show_pack_folder(&w, &state, new_state, &source);
                         }
                         Err(e) => {
-                            error!("Failed to load folder: {}", e);
-                            w.set_status_text(SharedString::from(format!("Error: {}", e)));
+                            error!("Failed to load manifest: {:#}", e);
+                            w.set_status_text(SharedString::from(format!("Error: {:#}", e)));
                         }
                     }
                     w.set_is_processing(false);
@@ -560,13 +754,88 @@ fn setup_open_folder(window: &MainWindow, state: StateHandle) {
     });
 }
 
+fn setup_save_manifest(window: &MainWindow, state: StateHandle) {
+    let window_weak = window.as_weak();
+    window.on_save_manifest(move || {
+        let window = window_weak.unwrap();
+        let game_version = GameVersion::from_index(window.get_selected_game_version());
+
+        let mut state = state.lock().unwrap();
+        let Some(source_folder) = state.source_folder.clone() else {
+            window.set_status_text(SharedString::from("No folder loaded"));
+            return;
+        };
+        if state.selected_count() == 0 {
+            window.set_status_text(SharedString::from("No files selected"));
+            return;
+        }
+
+        let output = state
+            .manifest
+            .as_ref()
+            .map(|m| m.output_path())
+            .unwrap_or_else(|| {
+                default_archive_path(game_version, &source_folder, &state.get_selected_files())
+            });
+        let Some(manifest) = state.to_manifest(game_version, &output) else {
+            return;
+        };
+
+        let path = rfd::FileDialog::new()
+            .add_filter("Pack Manifest", &["toml"])
+            .set_file_name("build.toml")
+            .save_file();
+        let Some(path) = path else {
+            return;
+        };
+
+        match manifest.save(&path) {
+            Ok(()) => {
+                window.set_status_text(SharedString::from(format!(
+                    "Saved manifest {}",
+                    path.display()
+                )));
+                state.manifest = Some(manifest);
+            }
+            Err(e) => {
+                error!("Failed to save manifest: {:#}", e);
+                window.set_status_text(SharedString::from(format!("Error: {:#}", e)));
+            }
+        }
+    });
+}
+
+/// Archive offered when no output has been chosen yet: named after the
+/// plugin and next to it when the folder has exactly one, the texture
+/// archive's name when only textures are selected
+fn default_archive_path(
+    game_version: GameVersion,
+    source_folder: &Path,
+    selected: &[String],
+) -> PathBuf {
+    let plugins = find_plugins(source_folder).unwrap_or_default();
+    let [plugin] = plugins.as_slice() else {
+        let ext = if game_version.is_ba2() { "ba2" } else { "bsa" };
+        return source_folder.with_file_name(format!("archive.{}", ext));
+    };
+    let names = plugin_archive_names(plugin, game_version);
+    let textures_only = selected.iter().all(|f| {
+        let f = f.replace('\\', "/").to_lowercase();
+        f.starts_with("textures/") && f.ends_with(".dds")
+    });
+    match names.textures {
+        Some(textures) if textures_only => source_folder.join(textures),
+        _ => source_folder.join(names.main),
+    }
+}
+
 fn setup_extract(window: &MainWindow, state: StateHandle) {
     let window_weak = window.as_weak();
     window.on_extract(move || {
         let window = window_weak.unwrap();
 
         let state_ref = state.lock().unwrap();
-        let archive_path = match &state_ref.archive_path {
+        let archive = match &state_ref.archive {
             Some(p) => p.clone(),
             None => {
                 window.set_status_text(SharedString::from("No archive loaded"));
@@ -590,6 +859,8 @@ fn setup_extract(window: &MainWindow, state: StateHandle) {
             None => return,
         };
 
+        let case_folding = CaseFolding::from_index(window.get_selected_case_mode());
+
         state.lock().unwrap().reset_cancel();
         window.set_is_processing(true);
         window.set_progress(0.0);
@@ -598,16 +869,37 @@ fn setup_extract(window: &MainWindow, state: StateHandle) {
         let files = selected_files.clone();
 
         std::thread::spawn(move || {
-            let total = files.len();
+            let plan = match ExtractionPlan::new(&output_folder, &files, case_folding) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    let _ = window_weak_thread.upgrade_in_event_loop(move |w: MainWindow| {
+                        w.set_is_processing(false);
+                        w.set_status_text(SharedString::from(format!("Error: {}", e)));
+                    });
+                    return;
+                }
+            };
+            for collision in &plan.collisions {
+                warn!(
+                    "Case collision: {} <-> {}",
+                    collision.first, collision.second
+                );
+            }
+            let collisions = plan.collisions.len();
+
+            let total = plan.files.len();
             let extracted = std::sync::atomic::AtomicUsize::new(0);
             let idx = std::sync::atomic::AtomicUsize::new(0);
 
-            let result = extract_archive_files_batch(&archive_path, &files, |path, data| {
+            let result = archive.read_batch(&plan.files, |path, data| {
                 if cancelled.load(Ordering::SeqCst) {
                     anyhow::bail!("Cancelled");
                 }
 
-                let output_path = output_folder.join(path.replace('\\', "/"));
+                let output_path = match plan.target(path) {
+                    Some(p) => p.to_path_buf(),
+                    None => output_folder.join(path.replace('\\', "/")),
+                };
                 if let Some(parent) = output_path.parent() {
                     let _ = fs::create_dir_all(parent);
                 }
@@ -636,6 +928,12 @@ fn setup_extract(window: &MainWindow, state: StateHandle) {
                 w.set_is_processing(false);
                 w.set_progress(1.0);
                 match result {
+                    Ok(_) if collisions > 0 => {
+                        w.set_status_text(SharedString::from(format!(
+                            "Extracted {} of {} files ({} case collisions, see log)",
+                            extracted, total, collisions
+                        )));
+                    }
                     Ok(_) => {
                         w.set_status_text(SharedString::from(format!(
                             "Extracted {} of {} files",
@@ -668,23 +966,16 @@ fn setup_pack(window: &MainWindow, state: StateHandle) {
         let window = window_weak.unwrap();
 
         let state_ref = state.lock().unwrap();
-        let source_folder = match &state_ref.source_folder {
-            Some(p) => p.clone(),
-            None => {
-                window.set_status_text(SharedString::from("No folder loaded"));
-                return;
-            }
+        let Some(source_folder) = state_ref.source_folder.clone() else {
+            window.set_status_text(SharedString::from("No folder loaded"));
+            return;
         };
-
-        let selected_files = state_ref.get_selected_files();
-        if selected_files.is_empty() {
+        if state_ref.selected_count() == 0 {
             window.set_status_text(SharedString::from("No files selected"));
             return;
         }
 
         let cancelled = state_ref.cancelled.clone();
-        drop(state_ref);
-
         let game_version = GameVersion::from_index(window.get_selected_game_version());
 
         // Determine file extension for save dialog
@@ -695,14 +986,41 @@ fn setup_pack(window: &MainWindow, state: StateHandle) {
             "BSA Archive"
         };
 
-        let output_path = rfd::FileDialog::new()
-            .add_filter(filter_name, &[ext])
-            .set_file_name(format!("archive.{}", ext))
-            .save_file();
-        let output_path = match output_path {
+        // Offer the manifest's output when one was loaded
+        let mut dialog = rfd::FileDialog::new().add_filter(filter_name, &[ext]);
+        let output = match &state_ref.manifest {
+            Some(manifest) => manifest.output_path(),
+            None => default_archive_path(
+                game_version,
+                &source_folder,
+                &state_ref.get_selected_files(),
+            ),
+        };
+        if let Some(dir) = output.parent().filter(|d| d.is_dir()) {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(name) = output.file_name() {
+            dialog = dialog.set_file_name(name.to_string_lossy());
+        }
+        drop(state_ref);
+
+        let output_path = match dialog.save_file() {
             Some(p) => p,
             None => return,
         };
+        let plugins = find_plugins(&source_folder).unwrap_or_default();
+        let mut manifest = match state
+            .lock()
+            .unwrap()
+            .to_manifest(game_version, &output_path)
+        {
+            Some(m) => m,
+            None => return,
+        };
+        // Pack exactly what is ticked; a loaded manifest's globs already
+        // shaped the selection and would drop files ticked since
+        manifest.include.clear();
+        manifest.exclude.clear();
 
         state.lock().unwrap().reset_cancel();
         window.set_is_processing(true);
@@ -711,28 +1029,34 @@ fn setup_pack(window: &MainWindow, state: StateHandle) {
         let window_weak_thread = window.as_weak();
 
         std::thread::spawn(move || {
-            let result = pack_files(
-                &source_folder,
-                &selected_files,
-                &output_path,
-                game_version,
-                &cancelled,
-                &window_weak_thread,
-            );
+            let result = pack_manifest(&manifest, &plugins, &cancelled, &window_weak_thread);
 
             let _ = window_weak_thread.upgrade_in_event_loop(move |w: MainWindow| {
                 w.set_is_processing(false);
                 w.set_progress(1.0);
                 match result {
-                    Ok(count) => {
+                    Ok((count, 1, note)) => {
+                        w.set_status_text(SharedString::from(format!(
+                            "Packed {} files into {}{}",
+                            count,
+                            output_path.display(),
+                            note
+                        )));
+                    }
+                    Ok((count, parts, note)) => {
                         w.set_status_text(SharedString::from(format!(
-                            "Packed {} files into {}",
+                            "Packed {} files into {} archives starting with {}{}",
                             count,
-                            output_path.display()
+                            parts,
+                            output_path.display(),
+                            note
                         )));
                     }
+                    Err(e) if e.to_string() == "Cancelled" => {
+                        w.set_status_text(SharedString::from("Cancelled"));
+                    }
                     Err(e) => {
-                        w.set_status_text(SharedString::from(format!("Pack error: {}", e)));
+                        w.set_status_text(SharedString::from(format!("Pack error: {:#}", e)));
                     }
                 }
             });
@@ -740,102 +1064,63 @@ fn setup_pack(window: &MainWindow, state: StateHandle) {
     });
 }
 
-/// Pack selected files from a source folder into an archive
-fn pack_files(
-    source_folder: &Path,
-    selected_files: &[String],
-    output_path: &Path,
-    game_version: GameVersion,
+/// Pack the archives a manifest describes; returns the file and archive
+/// counts and a note on the archives the game won't load for `plugins`
+fn pack_manifest(
+    manifest: &PackManifest,
+    plugins: &[String],
     cancelled: &Arc<AtomicBool>,
     window_weak: &Weak<MainWindow>,
-) -> Result<usize> {
-    let total = selected_files.len();
+) -> Result<(usize, usize, String)> {
+    let parts = manifest.plan()?;
+    let total: usize = parts.iter().map(|p| p.files.len()).sum();
 
-    if game_version.is_ba2() {
-        let ba2_version = game_version.ba2_version().unwrap_or_default();
-        let compression = game_version.ba2_compression();
-
-        // Detect DX10 from output name
-        let name_lower = output_path
-            .file_name()
-            .map(|n| n.to_string_lossy().to_lowercase())
-            .unwrap_or_default();
-        let format = if name_lower.contains("textures") {
-            Ba2Format::DX10
-        } else {
-            Ba2Format::General
-        };
-
-        let mut builder = Ba2Builder::new()
-            .with_version(ba2_version)
-            .with_compression(compression)
-            .with_format(format);
-
-        for (idx, file_path) in selected_files.iter().enumerate() {
-            if cancelled.load(Ordering::SeqCst) {
-                bail!("Cancelled");
-            }
-            // file_path uses backslash from tree; convert to forward slash for disk read
-            let disk_path = source_folder.join(file_path.replace('\\', "/"));
-            let data = fs::read(&disk_path)?;
-            builder.add_file(file_path, data);
-
-            let progress = (idx + 1) as f32 / total as f32;
-            let path = file_path.clone();
-            let _ = window_weak.upgrade_in_event_loop(move |w: MainWindow| {
-                w.set_progress(progress * 0.5); // first half = reading
-                w.set_status_text(SharedString::from(format!("Reading: {}", path)));
-            });
-        }
-
-        let window_weak2 = window_weak.clone();
-        builder.build_with_progress(output_path, move |current, btotal, name| {
-            let progress = 0.5 + (current as f32 / btotal as f32) * 0.5;
-            let name = name.to_string();
-            let _ = window_weak2.upgrade_in_event_loop(move |w: MainWindow| {
-                w.set_progress(progress);
-                w.set_status_text(SharedString::from(format!("Packing: {}", name)));
-            });
-        })?;
-    } else if game_version.is_tes3() {
-        bail!("Morrowind TES3 BSA writing is not supported");
+    // Split parts get their own names, which the game may not load
+    let warnings: Vec<String> = parts
+        .iter()
+        .filter_map(|part| {
+            let name = part
+                .output
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            archive_load_warning(manifest.game, &name, plugins)
+        })
+        .collect();
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    let note = if warnings.is_empty() {
+        String::new()
     } else {
-        // BSA (TES4)
-        let bsa_version = game_version.bsa_version().unwrap();
-        let compress = game_version.supports_compression();
-
-        let mut builder = BsaBuilder::new()
-            .with_version(bsa_version)
-            .with_compression(compress);
+        format!(" (warning: {})", warnings.join("; "))
+    };
+    let mut done = 0;
 
-        for (idx, file_path) in selected_files.iter().enumerate() {
-            if cancelled.load(Ordering::SeqCst) {
-                bail!("Cancelled");
-            }
-            let disk_path = source_folder.join(file_path.replace('\\', "/"));
-            let data = fs::read(&disk_path)?;
-            builder.add_file(file_path, data);
-
-            let progress = (idx + 1) as f32 / total as f32;
-            let path = file_path.clone();
-            let _ = window_weak.upgrade_in_event_loop(move |w: MainWindow| {
-                w.set_progress(progress * 0.5);
-                w.set_status_text(SharedString::from(format!("Reading: {}", path)));
-            });
+    for part in &parts {
+        if cancelled.load(Ordering::SeqCst) {
+            bail!("Cancelled");
         }
-
-        let window_weak2 = window_weak.clone();
-        builder.build_with_progress(output_path, move |current, btotal, name| {
-            let progress = 0.5 + (current as f32 / btotal as f32) * 0.5;
+        let window_weak = window_weak.clone();
+        manifest.build_part(part, cancelled, move |stage, current, count, name| {
+            // First half of each file's share is reading, second half packing
+            let (label, offset) = match stage {
+                PackStage::Reading => ("Reading", 0.0),
+                PackStage::Compressing => ("Packing", 0.5),
+            };
+            let progress = (done as f32
+                + (offset + current as f32 / count as f32 * 0.5) * part.files.len() as f32)
+                / total as f32;
             let name = name.to_string();
-            let _ = window_weak2.upgrade_in_event_loop(move |w: MainWindow| {
+            let _ = window_weak.upgrade_in_event_loop(move |w: MainWindow| {
                 w.set_progress(progress);
-                w.set_status_text(SharedString::from(format!("Packing: {}", name)));
+                w.set_status_text(SharedString::from(format!("{}: {}", label, name)));
             });
         })?;
+        done += part.files.len();
     }
 
-    Ok(total)
+    Ok((total, parts.len(), note))
 }
 
 fn setup_select_all(window: &MainWindow, state: StateHandle) {
@@ -896,3 +1181,422 @@ fn setup_toggle_select(window: &MainWindow, state: StateHandle) {
         )));
     });
 }
+
+/// Largest side of the mip shown first; bigger mips can still be picked
+const PREVIEW_DEFAULT_SIZE: u32 = 2048;
+
+fn setup_preview(window: &MainWindow, state: StateHandle) {
+    let window_weak = window.as_weak();
+    let state_entry = state.clone();
+    window.on_preview_entry(move |index| {
+        let window = window_weak.unwrap();
+        let state = state_entry.clone();
+
+        let mut state_ref = state.lock().unwrap();
+        let Some(node) = state_ref.tree.get(index as usize) else {
+            return;
+        };
+        if node.is_folder {
+            return;
+        }
+        let path = node.path.clone();
+        let source = state_ref.file_source();
+        state_ref.preview = Some(Preview {
+            path: path.clone(),
+            texture: None,
+        });
+        drop(state_ref);
+
+        window.set_current_index(index);
+        window.set_preview_visible(true);
+        window.set_preview_title(SharedString::from(&path));
+        window.set_preview_info(SharedString::new());
+        window.set_preview_mips(ModelRc::default());
+        window.set_preview_mip(0);
+
+        let extension = path
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .unwrap_or_default();
+        window.set_preview_lines(ModelRc::default());
+        window.set_preview_copy_text(SharedString::new());
+        window.set_preview_is_text(extension != "dds");
+        let Some(source) = source else {
+            return;
+        };
+        window.set_preview_message(SharedString::from("Loading..."));
+        if extension != "dds" {
+            show_text(&window, state, source, path);
+            return;
+        }
+
+        let window_weak_thread = window.as_weak();
+        std::thread::spawn(move || {
+            let result = source.read(&path).and_then(|data| {
+                let info = inspect_dds(&data)?;
+                Ok((Arc::new(data), info))
+            });
+
+            let _ = window_weak_thread.upgrade_in_event_loop(move |w: MainWindow| {
+                let mut state_ref = state.lock().unwrap();
+                if !state_ref.is_previewing(&path) {
+                    return;
+                }
+                let (data, info) = match result {
+                    Ok(texture) => texture,
+                    Err(e) => {
+                        w.set_preview_message(SharedString::from(format!("{:#}", e)));
+                        return;
+                    }
+                };
+
+                let mips: Vec<SharedString> = (0..info.mip_count)
+                    .map(|mip| {
+                        let width = (info.width >> mip).max(1);
+                        let height = (info.height >> mip).max(1);
+                        SharedString::from(format!("{}: {}×{}", mip, width, height))
+                    })
+                    .collect();
+                let mip = (0..info.mip_count)
+                    .find(|mip| (info.width.max(info.height) >> mip) <= PREVIEW_DEFAULT_SIZE)
+                    .unwrap_or(0);
+                w.set_preview_info(SharedString::from(format!(
+                    "{}×{} · {} · {} mip{}{}",
+                    info.width,
+                    info.height,
+                    info.format_name(),
+                    info.mip_count,
+                    if info.mip_count == 1 { "" } else { "s" },
+                    if info.cubemap {
+                        " · cubemap, first face"
+                    } else {
+                        ""
+                    }
+                )));
+                w.set_preview_mips(ModelRc::new(VecModel::from(mips)));
+                w.set_preview_mip(mip as i32);
+
+                if let Some(preview) = state_ref.preview.as_mut() {
+                    preview.texture = Some((data.clone(), info));
+                }
+                drop(state_ref);
+                show_texture_mip(&w, state.clone(), path, data, mip);
+            });
+        });
+    });
+
+    let window_weak = window.as_weak();
+    let state_mip = state.clone();
+    window.on_preview_mip_changed(move |mip| {
+        let window = window_weak.unwrap();
+        let state_ref = state_mip.lock().unwrap();
+        let Some(Preview {
+            path,
+            texture: Some((data, _)),
+        }) = state_ref.preview.as_ref()
+        else {
+            return;
+        };
+        let (path, data) = (path.clone(), data.clone());
+        drop(state_ref);
+        show_texture_mip(&window, state_mip.clone(), path, data, mip as u32);
+    });
+
+    let window_weak = window.as_weak();
+    window.on_close_preview(move || {
+        state.lock().unwrap().preview = None;
+        hide_preview(&window_weak.unwrap());
+    });
+}
+
+/// Read a file in a worker thread and show it as text, or as a hex dump when
+/// it is binary. Audio files get their format in front of the summary.
+fn show_text(window: &MainWindow, state: StateHandle, source: FileSource, path: String) {
+    let window_weak_thread = window.as_weak();
+    std::thread::spawn(move || {
+        let result = source.read(&path).map(|data| {
+            let empty = data.is_empty();
+            let mut preview = text_preview(&data);
+            if is_audio_path(&path) {
+                match inspect_audio(&data) {
+                    Ok(audio) => {
+                        let mut summary = format!(
+                            "{} · {} Hz · {} · {}:{:02}.{:02}",
+                            audio.codec_name(),
+                            audio.sample_rate,
+                            audio.channel_layout(),
+                            audio.duration_ms / 60_000,
+                            audio.duration_ms / 1000 % 60,
+                            audio.duration_ms % 1000 / 10
+                        );
+                        if let Some(lip_size) = audio.lip_size {
+                            summary.push_str(&format!(" · lip sync {} bytes", lip_size));
+                        }
+                        preview.info = format!("{} · {}", summary, preview.info);
+                    }
+                    Err(e) => warn!("Can't read audio header of {}: {:#}", path, e),
+                }
+            }
+            if path.to_lowercase().ends_with(".nif") {
+                match inspect_nif(&data) {
+                    Ok(nif) => {
+                        let mut summary = format!(
+                            "NIF {} · {} blocks",
+                            nif.header.version, nif.header.block_count
+                        );
+                        if let Some(textures) = nif.textures {
+                            summary.push_str(&format!(" · {} textures", textures.len()));
+                        }
+                        preview.info = format!("{} · {}", summary, preview.info);
+                    }
+                    Err(e) => warn!("Can't read NIF header of {}: {:#}", path, e),
+                }
+            }
+            (preview, empty)
+        });
+
+        let _ = window_weak_thread.upgrade_in_event_loop(move |w: MainWindow| {
+            if !state.lock().unwrap().is_previewing(&path) {
+                return;
+            }
+            let (preview, empty) = match result {
+                Ok(preview) => preview,
+                Err(e) => {
+                    w.set_preview_message(SharedString::from(format!("{:#}", e)));
+                    return;
+                }
+            };
+            let lines: Vec<SharedString> = preview.lines.into_iter().map(Into::into).collect();
+            w.set_preview_gutter_digits(lines.len().max(1).to_string().len() as i32);
+            w.set_preview_lines(ModelRc::new(VecModel::from(lines)));
+            w.set_preview_line_numbers(!preview.hex);
+            w.set_preview_copy_text(SharedString::from(preview.copy_text));
+            w.set_preview_info(SharedString::from(preview.info));
+            w.set_preview_message(SharedString::from(if empty { "Empty file" } else { "" }));
+        });
+    });
+}
+
+/// Decode one mip in a worker thread and show it, unless another file or
+/// mip was picked meanwhile
+fn show_texture_mip(
+    window: &MainWindow,
+    state: StateHandle,
+    path: String,
+    data: Arc<Vec<u8>>,
+    mip: u32,
+) {
+    window.set_preview_message(SharedString::from("Decoding..."));
+    let window_weak_thread = window.as_weak();
+    std::thread::spawn(move || {
+        let result = decode_dds_mip(&data, mip).map(|decoded| {
+            SharedPixelBuffer::<Rgba8Pixel>::clone_from_slice(
+                &decoded.rgba,
+                decoded.width,
+                decoded.height,
+            )
+        });
+
+        let _ = window_weak_thread.upgrade_in_event_loop(move |w: MainWindow| {
+            if !state.lock().unwrap().is_previewing(&path) || w.get_preview_mip() != mip as i32 {
+                return;
+            }
+            match result {
+                Ok(buffer) => {
+                    w.set_preview_image(Image::from_rgba8(buffer));
+                    w.set_preview_message(SharedString::new());
+                }
+                Err(e) => {
+                    error!("Failed to decode {}: {:#}", path, e);
+                    w.set_preview_message(SharedString::from(format!("{:#}", e)));
+                }
+            }
+        });
+    });
+}
+
+/// Close the preview pane, e.g. when the tree is replaced
+fn hide_preview(window: &MainWindow) {
+    window.set_preview_visible(false);
+    window.set_current_index(-1);
+    window.set_preview_image(Image::default());
+    window.set_preview_lines(ModelRc::default());
+    window.set_preview_copy_text(SharedString::new());
+}
+
+fn setup_edit(window: &MainWindow, state: StateHandle) {
+    let window_weak = window.as_weak();
+    let state_action = state.clone();
+    window.on_context_action(move |action, index| {
+        let window = window_weak.unwrap();
+        let state = state_action.clone();
+
+        let state_ref = state.lock().unwrap();
+        let Some(node) = state_ref.tree.get(index as usize) else {
+            return;
+        };
+        let (node_path, is_folder) = (node.path.clone(), node.is_folder);
+        let files = state_ref.files_under(index as usize);
+        drop(state_ref);
+
+        match action.as_str() {
+            "add" => {
+                let Some(source) = rfd::FileDialog::new().pick_file() else {
+                    return;
+                };
+                let name = source
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let folder = if is_folder {
+                    node_path
+                } else {
+                    node_path
+                        .rsplit_once('\\')
+                        .map(|(dir, _)| dir.to_string())
+                        .unwrap_or_default()
+                };
+                let target = if folder.is_empty() {
+                    name
+                } else {
+                    format!("{}\\{}", folder, name)
+                };
+                run_edit(&window, state, format!("Added {}", target), move |editor| {
+                    editor.add(&target, fs::read(&source)?)
+                });
+            }
+            "replace" => {
+                let Some(source) = rfd::FileDialog::new().pick_file() else {
+                    return;
+                };
+                run_edit(
+                    &window,
+                    state,
+                    format!("Replaced {}", node_path),
+                    move |editor| editor.replace(&node_path, fs::read(&source)?),
+                );
+            }
+            "delete" => {
+                let question = format!(
+                    "Delete {} from the archive? This can't be undone.",
+                    file_count(files.len())
+                );
+                if !confirm("Delete files", &question) {
+                    return;
+                }
+                let message = format!("Deleted {}", file_count(files.len()));
+                run_edit(&window, state, message, move |editor| {
+                    for path in &files {
+                        editor.remove(path)?;
+                    }
+                    Ok(())
+                });
+            }
+            other => warn!("Unknown context action: {}", other),
+        }
+    });
+
+    let window_weak = window.as_weak();
+    window.on_rename_entry(move |index, new_path| {
+        let window = window_weak.unwrap();
+
+        let state_ref = state.lock().unwrap();
+        let Some(node) = state_ref.tree.get(index as usize) else {
+            return;
+        };
+        let (old_prefix, is_folder) = (node.path.clone(), node.is_folder);
+        let files = state_ref.files_under(index as usize);
+        drop(state_ref);
+
+        let new_prefix = new_path.replace('/', "\\").trim_matches('\\').to_string();
+        if new_prefix.is_empty() || new_prefix == old_prefix {
+            return;
+        }
+        if is_folder {
+            let question = format!(
+                "Move {} from {} to {}?",
+                file_count(files.len()),
+                old_prefix,
+                new_prefix
+            );
+            if !confirm("Rename folder", &question) {
+                return;
+            }
+        }
+
+        let message = format!("Renamed {} to {}", old_prefix, new_prefix);
+        run_edit(&window, state.clone(), message, move |editor| {
+            // Folders move every file under them
+            for path in &files {
+                let renamed = format!("{}{}", new_prefix, &path[old_prefix.len()..]);
+                editor.rename(path, &renamed)?;
+            }
+            Ok(())
+        });
+    });
+}
+
+/// "1 file" or "N files"
+fn file_count(count: usize) -> String {
+    if count == 1 {
+        "1 file".to_string()
+    } else {
+        format!("{} files", count)
+    }
+}
+
+/// Ask a yes/no question before an edit that can't be undone
+fn confirm(title: &str, question: &str) -> bool {
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title(title)
+        .set_description(question)
+        .set_buttons(rfd::MessageButtons::YesNo)
+        .show()
+        == rfd::MessageDialogResult::Yes
+}
+
+/// Apply an edit to the loaded archive in a worker thread, save it in place
+/// and reload the tree
+fn run_edit<F>(window: &MainWindow, state: StateHandle, message: String, edit: F)
+where
+    F: FnOnce(&mut ArchiveEditor) -> Result<()> + Send + 'static,
+{
+    // Close our handle so the archive can be replaced
+    let Some(archive) = state.lock().unwrap().archive.take() else {
+        window.set_status_text(SharedString::from("No archive loaded"));
+        return;
+    };
+    let path = archive.path().to_path_buf();
+    drop(archive);
+
+    window.set_is_processing(true);
+    window.set_status_text(SharedString::from("Saving archive..."));
+
+    let window_weak_thread = window.as_weak();
+    std::thread::spawn(move || {
+        let result = ArchiveEditor::open(&path).and_then(|mut editor| {
+            edit(&mut editor)?;
+            editor.save(&path)
+        });
+
+        // Reload either way, the old handle is gone
+        let mut new_state = AppState::new();
+        let reload = new_state.load_archive(&path);
+
+        let _ = window_weak_thread.upgrade_in_event_loop(move |w: MainWindow| {
+            let status = match (result, reload) {
+                (Ok(()), Ok(())) => message,
+                (Err(e), _) | (_, Err(e)) => {
+                    error!("Failed to edit archive: {}", e);
+                    format!("Error: {}", e)
+                }
+            };
+            w.set_tree_nodes(new_state.to_slint_model());
+            hide_preview(&w);
+            *state.lock().unwrap() = new_state;
+            w.set_status_text(SharedString::from(status));
+            w.set_is_processing(false);
+        });
+    });
+}
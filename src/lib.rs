@@ -0,0 +1,39 @@
+//! BSA/BA2 archive library
+//!
+//! Read, extract and build Bethesda archives:
+//! - TES3 format BSA files (Morrowind)
+//! - TES4 format BSA files (Oblivion, FO3, FNV, Skyrim)
+//! - FO4 format BA2 files (Fallout 4, Fallout 76, Starfield)
+//!
+//! The GUI lives in the `bsa-ba2-tool` binary behind the `gui` feature, so
+//! depending on this crate with `default-features = false` pulls in no UI code.
+//!
+//! ```no_run
+//! use bsa_ba2_tool::{ArchiveHandle, Ba2Builder, GameVersion};
+//! use std::path::Path;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let archive = ArchiveHandle::open(Path::new("Skyrim - Meshes0.bsa"))?;
+//! for entry in archive.entries()? {
+//!     println!("{} ({} bytes)", entry.path, entry.size);
+//! }
+//! let nif = archive.read("meshes/clutter/bucket01.nif")?;
+//!
+//! let game = GameVersion::Fallout4Fo76;
+//! let mut builder = Ba2Builder::new()
+//!     .with_version(game.ba2_version().unwrap_or_default())
+//!     .with_compression(game.ba2_compression());
+//! builder.add_file("meshes/bucket.nif", nif);
+//! builder.build_with_progress(Path::new("Bucket - Main.ba2"), |_, _, _| {})?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod archive;
+
+pub use archive::{
+    archive_info, detect_format, detect_game_version, extract_archive_file,
+    extract_archive_files_batch, list_archive_files, open_archive, ArchiveFileEntry, ArchiveFormat,
+    ArchiveHandle, ArchiveInfo, Ba2Builder, Ba2CompressionFormat, Ba2Format, Ba2Version,
+    BsaBuilder, FormatOptions, GameVersion, OpenArchive, TextureInfo,
+};
@@ -3,16 +3,32 @@
 //! A GUI + CLI application for packing and unpacking Bethesda BSA/BA2 archives
 //! with support for multiple game formats.
 
-mod archive;
+#[cfg(feature = "gui")]
 mod gui;
 
-use archive::{
-    extract_archive_files_batch, list_archive_files, Ba2Builder, Ba2Format, BsaBuilder, GameVersion,
+use anyhow::Context;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use bsa_ba2_tool::archive::mount_vfs;
+use bsa_ba2_tool::archive::{
+    archive_info, archive_load_warning, check_references_with_progress, export_archive,
+    extract_archive_file, extract_archive_files_batch, find_conflicts_with_progress, find_plugins,
+    import_container, inspect_meshes_with_progress, is_plugin_path, join_fuz, list_archive_files,
+    pair_fuz_sources, parse_size, plugin_archive_names, read_audio_info, read_plugin_references,
+    split_fuz, write_conflicts, write_entries, write_info, write_nif_reports, write_ref_report,
+    ArchiveEditor, ArchiveHandle, ArchiveMerger, ArchiveOrder, Ba2Builder, Ba2Format, BsaBuilder,
+    CaseFolding, ConflictStatus, ContainerFormat, ExtractionPlan, FuzSources, GameVersion,
+    ListFormat, MergePriority, MipChunking, PackManifest, PackStage, PathFilter, Vfs,
 };
+#[cfg(feature = "gui")]
 use gui::state::{setup_callbacks, AppState};
+#[cfg(feature = "gui")]
 use gui::MainWindow;
+#[cfg(feature = "gui")]
 use slint::ComponentHandle;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "gui")]
 use std::sync::{Arc, Mutex};
 use tracing_subscriber::EnvFilter;
 use walkdir::WalkDir;
@@ -37,7 +53,18 @@ fn main() -> anyhow::Result<()> {
     match command {
         "unpack" | "extract" => cli_unpack(&args[2..]),
         "pack" => cli_pack(&args[2..]),
+        "merge" => cli_merge(&args[2..]),
+        "vfs" => cli_vfs(&args[2..]),
+        "conflicts" => cli_conflicts(&args[2..]),
+        "inspect" => cli_inspect(&args[2..]),
+        "check-refs" => cli_check_refs(&args[2..]),
+        "mount" => cli_mount(&args[2..]),
+        "export" => cli_export(&args[2..]),
+        "import" => cli_import(&args[2..]),
         "list" | "ls" => cli_list(&args[2..]),
+        "info" => cli_info(&args[2..]),
+        "cat" => cli_cat(&args[2..]),
+        "add" | "replace" | "rm" | "mv" => cli_edit(command, &args[2..]),
         "help" | "--help" | "-h" => {
             print_help();
             Ok(())
@@ -50,6 +77,7 @@ fn main() -> anyhow::Result<()> {
     }
 }
 
+#[cfg(feature = "gui")]
 fn run_gui() -> anyhow::Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -64,15 +92,143 @@ fn run_gui() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[cfg(not(feature = "gui"))]
+fn run_gui() -> anyhow::Result<()> {
+    eprintln!("This build has no GUI (built without the `gui` feature)\n");
+    print_help();
+    std::process::exit(1);
+}
+
 fn print_help() {
     eprintln!(
         "BSA/BA2 Archive Tool
 
 USAGE:
     bsa-ba2-tool                              Launch GUI
-    bsa-ba2-tool unpack <archive> [output]    Extract archive to folder
-    bsa-ba2-tool pack <folder> <output> <game>  Pack folder into archive
-    bsa-ba2-tool list <archive>               List files in archive
+    bsa-ba2-tool unpack <archive> [output] [--case <mode>] [--split-fuz] [filters]
+                                              Extract archive to folder
+    bsa-ba2-tool pack <folder> [output] <game> [--plugin <name>] [--order <order>]
+                      [--max-chunk-size <size>] [--max-chunks <n>] [--join-fuz]
+                                              Pack folder into archive(s)
+    bsa-ba2-tool pack --manifest <build.toml> Pack as described by a manifest
+    bsa-ba2-tool export <archive> <out.zip|out.tar.zst> [filters]
+                                              Write archive contents to a zip or tar
+    bsa-ba2-tool import <in.zip|in.tar.zst> <output> <game> [filters]
+                                              Pack a zip or tar into an archive
+    bsa-ba2-tool merge <output> <game> <archive>... [--priority <p>] [--prefer <archive>]
+                                              Merge archives into one
+    bsa-ba2-tool list <archive> [filters] [--format <fmt>] [--audio]
+                                              List files in archive
+    bsa-ba2-tool info <archive> [--format <fmt>]
+                                              Show archive header and totals
+    bsa-ba2-tool cat <archive> <path>         Write one file to stdout
+    bsa-ba2-tool vfs <archive|folder>... [--ls <dir>] [--which <path>] [--cat <path>]
+                                              Resolve files across archives and folders
+    bsa-ba2-tool conflicts <archive|folder>... [--only <status>] [--format <fmt>]
+                                              Report paths provided more than once
+    bsa-ba2-tool inspect <archive|folder>... [filters] [--format <fmt>]
+                                              Show NIF headers and missing textures
+    bsa-ba2-tool check-refs <plugin>... <archive|folder>... [--format <fmt>]
+                                              Find missing and unreferenced assets
+    bsa-ba2-tool mount <archive>... <mountpoint>
+                                              Mount archives read-only (Linux, FUSE)
+    bsa-ba2-tool add <archive> <path> <file> [--output <archive>]
+                                              Add a file to an archive
+    bsa-ba2-tool replace <archive> <path> <file> [--output <archive>]
+                                              Replace a file in an archive
+    bsa-ba2-tool rm <archive> <path>... [--output <archive>]
+                                              Remove files from an archive
+    bsa-ba2-tool mv <archive> <from> <to> [--output <archive>]
+                                              Rename a file in an archive
+
+FILTERS (repeatable, case-insensitive, matched against a/b/c.ext paths):
+    --include <glob>          Only files matching the glob
+    --exclude <glob>          Skip files matching the glob
+    --include-regex <regex>   Only files matching the regex
+    --exclude-regex <regex>   Skip files matching the regex
+    Globs without a '/' match the file name, e.g. '*.dds'
+
+ENTRY ORDER (pack --order, manifest order):
+    hash      Sorted by name hash (default)
+    vanilla   Like official archives: BA2 files grouped by directory,
+              then extension. BSAs are always in hash order.
+    Either way, the same files always give the same bytes.
+
+TEXTURE CHUNKS (pack --max-chunk-size/--max-chunks, manifest
+max_chunk_size/max_chunks):
+    DX10 BA2 textures are split into up to --max-chunks (1-4, default 4)
+    chunks of whole mips, largest first, starting a new chunk before one
+    would reach --max-chunk-size (e.g. \"2MB\"; default: a 512x512 mip in
+    the texture's format). The defaults match Archive2.
+
+ARCHIVE NAMES (pack):
+    Games only load the archives named after a plugin: Mod.bsa (and
+    Mod - Textures.bsa for Skyrim SE, or any name starting with Mod for
+    Oblivion/Fallout 3/New Vegas), Mod - Main.ba2 and Mod - Textures.ba2.
+    Without an output archive (or with an output folder), pack names the
+    archives after --plugin or the one .esp/.esm/.esl in the folder, puts
+    textures in the texture archive when the game has one, and writes
+    them next to the plugin (or into the output folder). A name the game
+    won't load for that plugin gets a warning. Plugins and archives at the
+    top of the folder are never packed.
+
+VOICE FILES (unpack --split-fuz, pack --join-fuz):
+    --split-fuz writes each .fuz as an .xwm audio file and a .lip lip sync
+    file (when it has lip data). --join-fuz packs each .xwm under
+    sound/voice together with the .lip of the same name as one .fuz; music
+    and other .xwm files are packed as they are. list --audio adds the codec,
+    sample rate, channels and duration of .fuz/.xwm/.wav files to
+    --format json/csv/tsv.
+
+PACK MANIFESTS (pack --manifest):
+    A TOML file with game, source, output and optionally format
+    (auto|general|dx10), compression (default|none|zlib, or lz4 for Skyrim
+    SE), include and exclude globs, files, order, max_chunk_size,
+    max_chunks, a [remap] table of directory prefixes and split_size (e.g.
+    \"2GB\"). Paths are relative to the manifest.
+    The GUI saves and loads the same files in pack mode.
+
+EDITING (add/replace/rm/mv):
+    Edits are written back to the archive unless --output is given.
+    Untouched files keep their compressed data as stored.
+
+CONTAINERS (export/import):
+    The container format comes from the file extension (.zip, .tar,
+    .tar.zst) or from --container zip|tar|tar.zst. Paths inside use
+    forward slashes. Nothing is unpacked to disk.
+
+MERGING (merge):
+    When a path is in several archives, the last one listed wins
+    (--priority first: the first one wins). --prefer makes one archive
+    win over all others. Every overridden path is reported.
+
+VIRTUAL FILE SYSTEM (vfs):
+    Archives are layered in the order given, later ones winning.
+    Folders hold loose files, which win over every archive.
+    Without options, prints every file and the source it comes from.
+    mount serves the same layering as a read-only FUSE filesystem;
+    files are decompressed when first read.
+    conflicts uses the same order and hashes every copy after
+    decompression; --only identical lists redundant copies.
+
+MESHES (inspect):
+    Reads the header of every .nif across the given archives and folders
+    (layered like vfs): version, user version, block counts and the
+    texture paths it references. Textures not found in any of the
+    sources are marked [missing].
+
+PLUGIN REFERENCES (check-refs):
+    Reads the model, texture, sound and script paths of every record in
+    the given .esp/.esm/.esl files, plus the textures of the meshes found,
+    and looks them up in the given archives and folders (layered like
+    vfs). Lists the ones missing, then the archived meshes, textures,
+    sounds and scripts nothing refers to. Voice files, FaceGen data and
+    terrain LOD are found by name in game and never listed as unused.
+
+OUTPUT FORMATS (list/info/conflicts/inspect/check-refs --format):
+    text, json, csv, tsv      json/csv/tsv include sizes, compression, hash,
+                              offset, DX10 texture (and with list --audio,
+                              audio) info per entry
 
 GAME VERSIONS:"
     );
@@ -81,33 +237,290 @@ GAME VERSIONS:"
     }
     eprintln!(
         "
+CASE MODES (unpack --case):"
+    );
+    for m in CaseFolding::all() {
+        eprintln!("    {:<14} {}", m.cli_name(), m.display_name());
+    }
+    eprintln!(
+        "
 EXAMPLES:
     bsa-ba2-tool unpack Skyrim.bsa ./output
+    bsa-ba2-tool unpack Skyrim.bsa ./Data --case merge
     bsa-ba2-tool pack ./my_mod my_mod.bsa skyrimse
-    bsa-ba2-tool pack ./textures textures.ba2 fo4ng-v7
-    bsa-ba2-tool list archive.ba2"
+    bsa-ba2-tool pack ./MyMod fo4ng-v7 --plugin MyMod.esp
+    bsa-ba2-tool pack ./textures textures.ba2 fo4ng-v7 --order vanilla
+    bsa-ba2-tool pack --manifest build.toml
+    bsa-ba2-tool unpack Meshes.bsa ./out --include 'meshes/**/*.nif' --exclude '*.dds'
+    bsa-ba2-tool unpack Voices.bsa ./voices --split-fuz
+    bsa-ba2-tool pack ./voices MyMod.bsa skyrimse --join-fuz
+    bsa-ba2-tool export Textures.ba2 textures.tar.zst
+    bsa-ba2-tool import my_mod.zip my_mod.bsa skyrimse
+    bsa-ba2-tool merge Merged.bsa skyrimse Base.bsa Patch.bsa
+    bsa-ba2-tool list archive.ba2
+    bsa-ba2-tool list Textures.ba2 --format json > textures.json
+    bsa-ba2-tool vfs Skyrim.bsa Mod.bsa ./Data --which meshes/x.nif
+    bsa-ba2-tool mount Textures.ba2 ~/mnt/textures
+    bsa-ba2-tool conflicts Base.bsa Patch.bsa ./Data --only identical
+    bsa-ba2-tool inspect Meshes.bsa Textures.bsa --format csv
+    bsa-ba2-tool check-refs MyMod.esp MyMod.bsa \"MyMod - Textures.bsa\"
+    bsa-ba2-tool cat Scripts.bsa scripts/source/myquest.psc | less
+    bsa-ba2-tool replace Mod.ba2 textures/armor/cuirass_d.dds ./cuirass_d.dds
+    bsa-ba2-tool mv Mod.bsa meshes/old.nif meshes/new.nif --output Mod-fixed.bsa"
     );
 }
 
+/// Remove `<name> <value>` from `args` and return the value
+fn take_option(args: &mut Vec<String>, name: &str) -> anyhow::Result<Option<String>> {
+    let Some(pos) = args.iter().position(|a| a == name) else {
+        return Ok(None);
+    };
+    if pos + 1 >= args.len() {
+        anyhow::bail!("Missing value for {}", name);
+    }
+    let value = args.remove(pos + 1);
+    args.remove(pos);
+    Ok(Some(value))
+}
+
+/// Remove every `<name> <value>` pair from `args` and return the values in order
+fn take_options(args: &mut Vec<String>, name: &str) -> anyhow::Result<Vec<String>> {
+    let mut values = Vec::new();
+    while let Some(value) = take_option(args, name)? {
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Remove the flag `name` from `args` and return whether it was there
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let Some(pos) = args.iter().position(|a| a == name) else {
+        return false;
+    };
+    args.remove(pos);
+    true
+}
+
+/// Parse `--order <order>` from `args`, exiting on an unknown order
+fn take_order(args: &mut Vec<String>) -> anyhow::Result<ArchiveOrder> {
+    let Some(name) = take_option(args, "--order")? else {
+        return Ok(ArchiveOrder::default());
+    };
+    match ArchiveOrder::from_cli_name(&name) {
+        Some(order) => Ok(order),
+        None => {
+            eprintln!("Unknown order: {}", name);
+            eprintln!("Valid options:");
+            for o in ArchiveOrder::all() {
+                eprintln!("  {:<14} {}", o.cli_name(), o.display_name());
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Parse `--max-chunk-size <size>` and `--max-chunks <n>` from `args`
+fn take_chunking(args: &mut Vec<String>) -> anyhow::Result<MipChunking> {
+    let mut chunking = MipChunking::default();
+    if let Some(size) = take_option(args, "--max-chunk-size")? {
+        let Some(bytes) = parse_size(&size) else {
+            anyhow::bail!("Invalid chunk size: {}", size);
+        };
+        chunking.max_chunk_size = Some(bytes as usize);
+    }
+    if let Some(count) = take_option(args, "--max-chunks")? {
+        chunking.max_chunks = match count.parse() {
+            Ok(count @ 1..=4) => count,
+            _ => anyhow::bail!("--max-chunks must be 1 to 4, not {}", count),
+        };
+    }
+    Ok(chunking)
+}
+
+/// Build a path filter from the --include/--exclude options in `args`
+fn take_filter(args: &mut Vec<String>) -> anyhow::Result<PathFilter> {
+    let mut filter = PathFilter::new();
+    for pattern in take_options(args, "--include")? {
+        filter.include_glob(&pattern)?;
+    }
+    for pattern in take_options(args, "--exclude")? {
+        filter.exclude_glob(&pattern)?;
+    }
+    for pattern in take_options(args, "--include-regex")? {
+        filter.include_regex(&pattern)?;
+    }
+    for pattern in take_options(args, "--exclude-regex")? {
+        filter.exclude_regex(&pattern)?;
+    }
+    Ok(filter)
+}
+
+/// Parse the --format option from `args`
+fn take_format(args: &mut Vec<String>) -> anyhow::Result<ListFormat> {
+    match take_option(args, "--format")? {
+        Some(name) => match ListFormat::from_cli_name(&name) {
+            Some(f) => Ok(f),
+            None => {
+                let valid: Vec<&str> = ListFormat::all().iter().map(|f| f.cli_name()).collect();
+                anyhow::bail!(
+                    "Unknown output format: {} (valid: {})",
+                    name,
+                    valid.join(", ")
+                );
+            }
+        },
+        None => Ok(ListFormat::Text),
+    }
+}
+
 fn cli_list(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let filter = take_filter(&mut args)?;
+    let format = take_format(&mut args)?;
+    let audio = take_flag(&mut args, "--audio");
+
     if args.is_empty() {
-        eprintln!("Usage: bsa-ba2-tool list <archive>");
+        eprintln!("Usage: bsa-ba2-tool list <archive> [filters] [--format <fmt>] [--audio]");
         std::process::exit(1);
     }
 
-    let archive_path = Path::new(&args[0]);
-    let files = list_archive_files(archive_path)?;
+    let archive = ArchiveHandle::open(Path::new(&args[0]))?;
+    let mut files: Vec<_> = archive
+        .entries()?
+        .iter()
+        .filter(|e| filter.matches(&e.path))
+        .cloned()
+        .collect();
+    // Reads the start of every audio file, so only on request
+    if audio && format != ListFormat::Text {
+        read_audio_info(&archive, &mut files)?;
+    }
 
-    for entry in &files {
-        println!("{}", entry.path);
+    let mut stdout = std::io::stdout().lock();
+    ignore_broken_pipe(
+        write_entries(&mut stdout, &files, format).and_then(|_| Ok(stdout.flush()?)),
+    )?;
+    if format == ListFormat::Text {
+        eprintln!("{} files", files.len());
     }
-    eprintln!("{} files", files.len());
     Ok(())
 }
 
+fn cli_info(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let format = take_format(&mut args)?;
+
+    if args.is_empty() {
+        eprintln!("Usage: bsa-ba2-tool info <archive> [--format <fmt>]");
+        std::process::exit(1);
+    }
+
+    let info = archive_info(Path::new(&args[0]))?;
+    let mut stdout = std::io::stdout().lock();
+    ignore_broken_pipe(write_info(&mut stdout, &info, format).and_then(|_| Ok(stdout.flush()?)))
+}
+
+fn cli_cat(args: &[String]) -> anyhow::Result<()> {
+    if args.len() < 2 {
+        eprintln!("Usage: bsa-ba2-tool cat <archive> <path>");
+        std::process::exit(1);
+    }
+
+    let data = extract_archive_file(Path::new(&args[0]), &args[1])?;
+
+    let mut stdout = std::io::stdout().lock();
+    ignore_broken_pipe(Ok(stdout.write_all(&data).and_then(|_| stdout.flush())?))
+}
+
+fn cli_edit(command: &str, args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let output = take_option(&mut args, "--output")?;
+
+    let usage = match command {
+        "add" | "replace" if args.len() != 3 => Some("<archive> <path> <file>"),
+        "rm" if args.len() < 2 => Some("<archive> <path>..."),
+        "mv" if args.len() != 3 => Some("<archive> <from> <to>"),
+        _ => None,
+    };
+    if let Some(usage) = usage {
+        eprintln!(
+            "Usage: bsa-ba2-tool {} {} [--output <archive>]",
+            command, usage
+        );
+        std::process::exit(1);
+    }
+
+    let archive_path = PathBuf::from(&args[0]);
+    let output_path = output.map(PathBuf::from).unwrap_or(archive_path.clone());
+    let mut editor = ArchiveEditor::open(&archive_path)?;
+
+    match command {
+        "add" => {
+            let data =
+                std::fs::read(&args[2]).with_context(|| format!("Failed to read: {}", args[2]))?;
+            editor.add(&args[1], data)?;
+            eprintln!("Added {}", args[1]);
+        }
+        "replace" => {
+            let data =
+                std::fs::read(&args[2]).with_context(|| format!("Failed to read: {}", args[2]))?;
+            editor.replace(&args[1], data)?;
+            eprintln!("Replaced {}", args[1]);
+        }
+        "rm" => {
+            for path in &args[1..] {
+                editor.remove(path)?;
+                eprintln!("Removed {}", path);
+            }
+        }
+        "mv" => {
+            editor.rename(&args[1], &args[2])?;
+            eprintln!("Renamed {} -> {}", args[1], args[2]);
+        }
+        _ => unreachable!(),
+    }
+
+    editor.save(&output_path)?;
+    eprintln!("Saved {}", output_path.display());
+    Ok(())
+}
+
+/// Treat a closed stdout (e.g. output piped into `head`) as success
+fn ignore_broken_pipe(result: anyhow::Result<()>) -> anyhow::Result<()> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
 fn cli_unpack(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let case_folding = match take_option(&mut args, "--case")? {
+        Some(name) => match CaseFolding::from_cli_name(&name) {
+            Some(m) => m,
+            None => {
+                eprintln!("Unknown case mode: {}", name);
+                eprintln!("Valid options:");
+                for m in CaseFolding::all() {
+                    eprintln!("  {:<14} {}", m.cli_name(), m.display_name());
+                }
+                std::process::exit(1);
+            }
+        },
+        None => CaseFolding::Preserve,
+    };
+    let split_fuz_files = take_flag(&mut args, "--split-fuz");
+    let filter = take_filter(&mut args)?;
+
     if args.is_empty() {
-        eprintln!("Usage: bsa-ba2-tool unpack <archive> [output_folder]");
+        eprintln!(
+            "Usage: bsa-ba2-tool unpack <archive> [output_folder] [--case <mode>] [--split-fuz] [filters]"
+        );
         std::process::exit(1);
     }
 
@@ -124,21 +537,40 @@ fn cli_unpack(args: &[String]) -> anyhow::Result<()> {
     };
 
     let files = list_archive_files(&archive_path)?;
-    let total = files.len();
+    let file_paths = filter.apply(files.into_iter().map(|e| e.path).collect());
+    let plan = ExtractionPlan::new(&output_folder, &file_paths, case_folding)?;
+
+    let total = plan.files.len();
     eprintln!("Extracting {} files from {}", total, archive_path.display());
 
     std::fs::create_dir_all(&output_folder)?;
 
-    let file_paths: Vec<String> = files.iter().map(|e| e.path.clone()).collect();
     let extracted = std::sync::atomic::AtomicUsize::new(0);
     let idx = std::sync::atomic::AtomicUsize::new(0);
 
-    extract_archive_files_batch(&archive_path, &file_paths, |path, data| {
-        let out_path = output_folder.join(path.replace('\\', "/"));
+    extract_archive_files_batch(&archive_path, &plan.files, |path, data| {
+        let out_path = match plan.target(path) {
+            Some(p) => p.to_path_buf(),
+            None => output_folder.join(path.replace('\\', "/")),
+        };
         if let Some(parent) = out_path.parent() {
             std::fs::create_dir_all(parent)?;
         }
-        std::fs::write(&out_path, &data)?;
+        let fuz = if split_fuz_files && path.to_lowercase().ends_with(".fuz") {
+            split_fuz(&data)
+                .inspect_err(|e| eprintln!("\nWarning: {} kept as .fuz: {:#}", path, e))
+                .ok()
+        } else {
+            None
+        };
+        if let Some(fuz) = fuz {
+            std::fs::write(out_path.with_extension("xwm"), fuz.audio)?;
+            if !fuz.lip.is_empty() {
+                std::fs::write(out_path.with_extension("lip"), fuz.lip)?;
+            }
+        } else {
+            std::fs::write(&out_path, &data)?;
+        }
         extracted.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         let current = idx.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
 
@@ -149,6 +581,18 @@ fn cli_unpack(args: &[String]) -> anyhow::Result<()> {
     })?;
 
     eprintln!();
+    if !plan.collisions.is_empty() {
+        eprintln!(
+            "Warning: {} paths collide when case is ignored:",
+            plan.collisions.len()
+        );
+        for collision in &plan.collisions {
+            eprintln!("  {} <-> {}", collision.first, collision.second);
+        }
+        if case_folding != CaseFolding::Preserve {
+            eprintln!("  (kept the first of each pair)");
+        }
+    }
     eprintln!(
         "Done: {} of {} files extracted to {}",
         extracted.load(std::sync::atomic::Ordering::Relaxed),
@@ -158,40 +602,153 @@ fn cli_unpack(args: &[String]) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn cli_pack(args: &[String]) -> anyhow::Result<()> {
-    if args.len() < 3 {
-        eprintln!("Usage: bsa-ba2-tool pack <folder> <output> <game>");
-        eprintln!("Run 'bsa-ba2-tool help' for game version list");
-        std::process::exit(1);
-    }
-
-    let source_folder = PathBuf::from(&args[0]);
-    let output_path = PathBuf::from(&args[1]);
-    let game_version = match GameVersion::from_cli_name(&args[2]) {
+/// Parse a game version, or list the valid ones and exit
+fn game_or_exit(name: &str) -> GameVersion {
+    match GameVersion::from_cli_name(name) {
         Some(v) => v,
         None => {
-            eprintln!("Unknown game version: {}", args[2]);
+            eprintln!("Unknown game version: {}", name);
             eprintln!("Valid options:");
             for v in GameVersion::all() {
                 eprintln!("  {:<14} {}", v.cli_name(), v.display_name());
             }
             std::process::exit(1);
         }
+    }
+}
+
+/// The builder `pack` and `import` use for a game
+enum PackBuilder {
+    Bsa(BsaBuilder),
+    Ba2(Ba2Builder),
+}
+
+impl PackBuilder {
+    fn new(game_version: GameVersion, output_path: &Path) -> Self {
+        if game_version.is_ba2() {
+            let ba2_version = game_version.ba2_version().unwrap_or_default();
+            let compression = game_version.ba2_compression();
+
+            let name_lower = output_path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let format = if name_lower.contains("textures") {
+                Ba2Format::DX10
+            } else {
+                Ba2Format::General
+            };
+
+            PackBuilder::Ba2(
+                Ba2Builder::new()
+                    .with_version(ba2_version)
+                    .with_compression(compression)
+                    .with_format(format),
+            )
+        } else {
+            let bsa_version = game_version.bsa_version().unwrap();
+            let compress = game_version.supports_compression();
+
+            PackBuilder::Bsa(
+                BsaBuilder::new()
+                    .with_version(bsa_version)
+                    .with_compression(compress),
+            )
+        }
+    }
+
+    /// Set the entry order; TES4 BSAs are always in hash order
+    fn with_order(self, order: ArchiveOrder) -> Self {
+        match self {
+            PackBuilder::Bsa(builder) => PackBuilder::Bsa(builder),
+            PackBuilder::Ba2(builder) => PackBuilder::Ba2(builder.with_order(order)),
+        }
+    }
+
+    /// Set how DX10 textures are chunked; other archives have no chunks
+    fn with_mip_chunking(self, chunking: MipChunking) -> Self {
+        match self {
+            PackBuilder::Bsa(builder) => PackBuilder::Bsa(builder),
+            PackBuilder::Ba2(builder) => PackBuilder::Ba2(builder.with_mip_chunking(chunking)),
+        }
+    }
+
+    fn add_file(&mut self, path: &str, data: Vec<u8>) {
+        match self {
+            PackBuilder::Bsa(builder) => builder.add_file(path, data),
+            PackBuilder::Ba2(builder) => builder.add_file(path, data),
+        }
+    }
+
+    fn build(self, output_path: &Path) -> anyhow::Result<()> {
+        eprintln!("  Building archive...");
+        let progress = |current: usize, total: usize, _: &str| {
+            if current.is_multiple_of(100) || current == total {
+                eprint!("\r  Compressing: {}/{}", current, total);
+            }
+        };
+        match self {
+            PackBuilder::Bsa(builder) => builder.build_with_progress(output_path, progress)?,
+            PackBuilder::Ba2(builder) => builder.build_with_progress(output_path, progress)?,
+        }
+        eprintln!();
+        Ok(())
+    }
+}
+
+fn cli_pack(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    if let Some(manifest) = take_option(&mut args, "--manifest")? {
+        return cli_pack_manifest(Path::new(&manifest));
+    }
+    let order = take_order(&mut args)?;
+    let chunking = take_chunking(&mut args)?;
+    let join_fuz_files = take_flag(&mut args, "--join-fuz");
+    let plugin = take_option(&mut args, "--plugin")?;
+
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: bsa-ba2-tool pack <folder> [output] <game> [--plugin <name>] [--order hash|vanilla] [--max-chunk-size <size>] [--max-chunks <n>] [--join-fuz]"
+        );
+        eprintln!("       bsa-ba2-tool pack --manifest <build.toml>");
+        eprintln!("Run 'bsa-ba2-tool help' for game version list");
+        std::process::exit(1);
+    }
+
+    let source_folder = PathBuf::from(&args[0]);
+    let (output, game_version) = match args.len() {
+        2 => (None, game_or_exit(&args[1])),
+        _ => (Some(PathBuf::from(&args[1])), game_or_exit(&args[2])),
     };
 
     if game_version.is_tes3() {
         anyhow::bail!("Morrowind TES3 BSA writing is not supported");
     }
 
-    // Collect files
+    let plugins = match plugin {
+        Some(plugin) => vec![plugin],
+        None if source_folder.is_dir() => find_plugins(&source_folder)?,
+        None => Vec::new(),
+    };
+
+    // Collect files; plugins and archives next to them are never packed
     let mut file_paths: Vec<String> = Vec::new();
     for entry in WalkDir::new(&source_folder)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(|e| e.ok())
     {
         if entry.file_type().is_file() {
             if let Ok(rel) = entry.path().strip_prefix(&source_folder) {
-                file_paths.push(rel.to_string_lossy().to_string());
+                let rel = rel.to_string_lossy().to_string();
+                let lower = rel.to_lowercase();
+                if entry.depth() == 1
+                    && (is_plugin_path(&rel) || lower.ends_with(".bsa") || lower.ends_with(".ba2"))
+                {
+                    eprintln!("  Skipping {} (not loaded from inside an archive)", rel);
+                    continue;
+                }
+                file_paths.push(rel);
             }
         }
     }
@@ -199,8 +756,79 @@ fn cli_pack(args: &[String]) -> anyhow::Result<()> {
     if file_paths.is_empty() {
         anyhow::bail!("No files found in {}", source_folder.display());
     }
+    let (file_paths, fuz_files) = if join_fuz_files {
+        pair_fuz_sources(file_paths)
+    } else {
+        (file_paths, Vec::new())
+    };
 
-    let total = file_paths.len();
+    // Without an output archive, name the archives after the plugin and put
+    // them in the output folder, or next to the plugin
+    let mut outputs = match output {
+        Some(output) if !output.is_dir() => vec![(output, file_paths, fuz_files)],
+        output => {
+            let dir = output.unwrap_or_else(|| source_folder.clone());
+            let plugin = match plugins.as_slice() {
+                [plugin] => plugin,
+                [] => anyhow::bail!(
+                    "No plugin (.esp/.esm/.esl) in {}; pass --plugin <name> or an output archive",
+                    source_folder.display()
+                ),
+                _ => anyhow::bail!(
+                    "Several plugins in {} ({}); pick one with --plugin <name>",
+                    source_folder.display(),
+                    plugins.join(", ")
+                ),
+            };
+            let names = plugin_archive_names(plugin, game_version);
+            let (textures, main): (Vec<String>, Vec<String>) = match &names.textures {
+                Some(_) => file_paths.into_iter().partition(|p| {
+                    let p = p.replace('\\', "/").to_lowercase();
+                    p.starts_with("textures/") && p.ends_with(".dds")
+                }),
+                None => (Vec::new(), file_paths),
+            };
+            let mut outputs = vec![(dir.join(&names.main), main, fuz_files)];
+            if let Some(name) = names.textures {
+                outputs.push((dir.join(name), textures, Vec::new()));
+            }
+            outputs
+        }
+    };
+    outputs.retain(|(_, files, fuz)| !files.is_empty() || !fuz.is_empty());
+
+    for (output_path, file_paths, fuz_files) in &outputs {
+        let name = output_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(warning) = archive_load_warning(game_version, &name, &plugins) {
+            eprintln!("Warning: {}", warning);
+        }
+        pack_files(
+            &source_folder,
+            game_version,
+            order,
+            chunking,
+            output_path,
+            file_paths,
+            fuz_files,
+        )?;
+    }
+    Ok(())
+}
+
+/// Pack loose files, and .fuz files joined from their parts, into one archive
+fn pack_files(
+    source_folder: &Path,
+    game_version: GameVersion,
+    order: ArchiveOrder,
+    chunking: MipChunking,
+    output_path: &Path,
+    file_paths: &[String],
+    fuz_files: &[FuzSources],
+) -> anyhow::Result<()> {
+    let total = file_paths.len() + fuz_files.len();
     eprintln!(
         "Packing {} files as {} -> {}",
         total,
@@ -208,75 +836,520 @@ fn cli_pack(args: &[String]) -> anyhow::Result<()> {
         output_path.display()
     );
 
-    if game_version.is_ba2() {
-        let ba2_version = game_version.ba2_version().unwrap_or_default();
-        let compression = game_version.ba2_compression();
+    let mut builder = PackBuilder::new(game_version, output_path)
+        .with_order(order)
+        .with_mip_chunking(chunking);
+    for (idx, rel_path) in file_paths.iter().enumerate() {
+        let disk_path = source_folder.join(rel_path.replace('\\', "/"));
+        let data = std::fs::read(&disk_path)?;
+        builder.add_file(rel_path, data);
 
-        let name_lower = output_path
-            .file_name()
-            .map(|n| n.to_string_lossy().to_lowercase())
-            .unwrap_or_default();
-        let format = if name_lower.contains("textures") {
-            Ba2Format::DX10
-        } else {
-            Ba2Format::General
+        if (idx + 1) % 100 == 0 || idx + 1 == total {
+            eprint!("\r  Reading: {}/{}", idx + 1, total);
+        }
+    }
+    for (idx, sources) in fuz_files.iter().enumerate() {
+        let read = |rel_path: &str| {
+            let disk_path = source_folder.join(rel_path.replace('\\', "/"));
+            std::fs::read(&disk_path).with_context(|| format!("Failed to read: {}", rel_path))
         };
+        let audio = read(&sources.xwm)?;
+        let lip = match &sources.lip {
+            Some(lip) => read(lip)?,
+            None => Vec::new(),
+        };
+        builder.add_file(&sources.fuz, join_fuz(&audio, &lip));
 
-        let mut builder = Ba2Builder::new()
-            .with_version(ba2_version)
-            .with_compression(compression)
-            .with_format(format);
+        let current = file_paths.len() + idx + 1;
+        if current.is_multiple_of(100) || current == total {
+            eprint!("\r  Reading: {}/{}", current, total);
+        }
+    }
+    eprintln!();
+    if !fuz_files.is_empty() {
+        let with_lip = fuz_files.iter().filter(|f| f.lip.is_some()).count();
+        eprintln!(
+            "  Joined {} .fuz files ({} with lip sync)",
+            fuz_files.len(),
+            with_lip
+        );
+    }
+    builder.build(output_path)?;
 
-        for (idx, rel_path) in file_paths.iter().enumerate() {
-            let disk_path = source_folder.join(rel_path.replace('\\', "/"));
-            let data = std::fs::read(&disk_path)?;
-            builder.add_file(rel_path, data);
+    eprintln!(
+        "Done: {} files packed into {}",
+        total,
+        output_path.display()
+    );
+    Ok(())
+}
 
-            if (idx + 1) % 100 == 0 || idx + 1 == total {
-                eprint!("\r  Reading: {}/{}", idx + 1, total);
-            }
+fn cli_pack_manifest(manifest_path: &Path) -> anyhow::Result<()> {
+    let manifest = PackManifest::load(manifest_path)?;
+    let parts = manifest.plan()?;
+
+    let total: usize = parts.iter().map(|p| p.files.len()).sum();
+    eprintln!(
+        "Packing {} files from {} as {} into {} archive(s)",
+        total,
+        manifest.source_dir().display(),
+        manifest.game.display_name(),
+        parts.len()
+    );
+
+    let source_dir = manifest.source_dir();
+    let plugins = if source_dir.is_dir() {
+        find_plugins(&source_dir)?
+    } else {
+        Vec::new()
+    };
+    for part in &parts {
+        let name = part
+            .output
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(warning) = archive_load_warning(manifest.game, &name, &plugins) {
+            eprintln!("Warning: {}", warning);
         }
-        eprintln!();
+    }
 
-        eprintln!("  Building archive...");
-        builder.build_with_progress(&output_path, |current, btotal, _| {
-            if current % 100 == 0 || current == btotal {
-                eprint!("\r  Compressing: {}/{}", current, btotal);
+    for part in &parts {
+        eprintln!("  {} ({} files)", part.output.display(), part.files.len());
+        manifest.build_part(part, &AtomicBool::new(false), |stage, current, total, _| {
+            let label = match stage {
+                PackStage::Reading => "Reading",
+                PackStage::Compressing => "Compressing",
+            };
+            if stage == PackStage::Compressing && current == 1 {
+                eprintln!();
+            }
+            if current.is_multiple_of(100) || current == total {
+                eprint!("\r  {}: {}/{}", label, current, total);
             }
         })?;
         eprintln!();
+    }
+
+    eprintln!(
+        "Done: {} files packed into {} archive(s)",
+        total,
+        parts.len()
+    );
+    Ok(())
+}
+
+fn cli_merge(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let priority = match take_option(&mut args, "--priority")? {
+        Some(name) => match MergePriority::from_cli_name(&name) {
+            Some(p) => p,
+            None => {
+                eprintln!("Unknown priority: {}", name);
+                eprintln!("Valid options:");
+                for p in MergePriority::all() {
+                    eprintln!("  {:<14} {}", p.cli_name(), p.display_name());
+                }
+                std::process::exit(1);
+            }
+        },
+        None => MergePriority::Last,
+    };
+    let prefer = take_option(&mut args, "--prefer")?;
+
+    if args.len() < 3 {
+        eprintln!(
+            "Usage: bsa-ba2-tool merge <output> <game> <archive>... [--priority last|first] [--prefer <archive>]"
+        );
+        eprintln!("Run 'bsa-ba2-tool help' for game version list");
+        std::process::exit(1);
+    }
+
+    let output_path = PathBuf::from(&args[0]);
+    let game_version = game_or_exit(&args[1]);
+
+    let mut merger = ArchiveMerger::new(game_version).with_priority(priority);
+    if let Some(prefer) = &prefer {
+        merger = merger.with_preferred(Path::new(prefer));
+    }
+    for input in &args[2..] {
+        merger.add_input(Path::new(input));
+    }
+
+    eprintln!(
+        "Merging {} archives as {} -> {}",
+        args.len() - 2,
+        game_version.display_name(),
+        output_path.display()
+    );
+    let report = merger.build_with_progress(&output_path, |current, total, _| {
+        if current % 100 == 0 || current == total {
+            eprint!("\r  Compressing: {}/{}", current, total);
+        }
+    })?;
+    eprintln!();
+
+    for o in &report.overrides {
+        let overridden: Vec<String> = o
+            .overridden
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        eprintln!(
+            "  Overridden: {} ({} wins over {})",
+            o.path,
+            o.winner.display(),
+            overridden.join(", ")
+        );
+    }
+
+    eprintln!(
+        "Done: {} files merged into {} ({} overridden)",
+        report.files,
+        output_path.display(),
+        report.overrides.len()
+    );
+    Ok(())
+}
+
+fn cli_vfs(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let ls = take_option(&mut args, "--ls")?;
+    let which = take_option(&mut args, "--which")?;
+    let cat = take_option(&mut args, "--cat")?;
+
+    if args.is_empty() {
+        eprintln!(
+            "Usage: bsa-ba2-tool vfs <archive|folder>... [--ls <dir>] [--which <path>] [--cat <path>]"
+        );
+        std::process::exit(1);
+    }
+
+    let vfs = open_vfs(&args)?;
+
+    let mut stdout = std::io::stdout().lock();
+    let result = if let Some(path) = cat {
+        let data = vfs.read(&path)?;
+        stdout.write_all(&data).and_then(|_| stdout.flush())
+    } else if let Some(path) = which {
+        let Some(file) = vfs.lookup(&path) else {
+            anyhow::bail!("File not found in any source: {}", path);
+        };
+        let sources = vfs.sources();
+        writeln!(stdout, "{}", sources[file.source].path().display()).and_then(|_| {
+            for layer in file.overridden.iter().rev() {
+                writeln!(
+                    stdout,
+                    "  overrides {}",
+                    sources[layer.source].path().display()
+                )?;
+            }
+            Ok(())
+        })
+    } else if let Some(dir) = ls {
+        if !vfs.is_dir(&dir) {
+            anyhow::bail!("Directory not found in any source: {}", dir);
+        }
+        vfs.read_dir(&dir).iter().try_for_each(|entry| {
+            let slash = if entry.is_dir { "/" } else { "" };
+            writeln!(stdout, "{}{}", entry.name, slash)
+        })
     } else {
-        // BSA
-        let bsa_version = game_version.bsa_version().unwrap();
-        let compress = game_version.supports_compression();
+        let sources = vfs.sources();
+        let result = vfs.files().try_for_each(|file| {
+            writeln!(
+                stdout,
+                "{}\t{}",
+                file.path,
+                sources[file.source].path().display()
+            )
+        });
+        eprintln!("{} files from {} sources", vfs.len(), sources.len());
+        result
+    };
+    ignore_broken_pipe(Ok(result?))
+}
 
-        let mut builder = BsaBuilder::new()
-            .with_version(bsa_version)
-            .with_compression(compress);
+/// Layer the given archives and folders into a VFS, in order
+fn open_vfs(sources: &[String]) -> anyhow::Result<Vfs> {
+    let mut vfs = Vfs::new();
+    for source in sources {
+        let path = Path::new(source);
+        if path.is_dir() {
+            vfs.add_loose_dir(path)?;
+        } else {
+            vfs.add_archive(path)?;
+        }
+    }
+    Ok(vfs)
+}
 
-        for (idx, rel_path) in file_paths.iter().enumerate() {
-            let disk_path = source_folder.join(rel_path.replace('\\', "/"));
-            let data = std::fs::read(&disk_path)?;
-            builder.add_file(rel_path, data);
+fn cli_conflicts(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let format = take_format(&mut args)?;
+    let only = match take_option(&mut args, "--only")? {
+        Some(name) => match ConflictStatus::from_cli_name(&name) {
+            Some(s) => Some(s),
+            None => {
+                eprintln!("Unknown conflict status: {}", name);
+                eprintln!("Valid options:");
+                for s in ConflictStatus::all() {
+                    eprintln!("  {:<14} {}", s.cli_name(), s.display_name());
+                }
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    if args.is_empty() {
+        eprintln!(
+            "Usage: bsa-ba2-tool conflicts <archive|folder>... [--only identical|different] [--format <fmt>]"
+        );
+        std::process::exit(1);
+    }
+
+    let vfs = open_vfs(&args)?;
+    let conflicts: Vec<_> = find_conflicts_with_progress(&vfs, |current, total, _| {
+        if current % 100 == 0 || current == total {
+            eprint!("\r  Hashing: {}/{}", current, total);
+        }
+    })?
+    .into_iter()
+    .filter(|c| only.is_none_or(|s| c.status == s))
+    .collect();
+    eprintln!();
+
+    let mut stdout = std::io::stdout().lock();
+    ignore_broken_pipe(
+        write_conflicts(&mut stdout, &conflicts, format).and_then(|_| Ok(stdout.flush()?)),
+    )?;
+    if format == ListFormat::Text {
+        let identical = conflicts
+            .iter()
+            .filter(|c| c.status == ConflictStatus::Identical)
+            .count();
+        eprintln!(
+            "{} conflicting paths ({} identical, {} different)",
+            conflicts.len(),
+            identical,
+            conflicts.len() - identical
+        );
+    }
+    Ok(())
+}
+
+fn cli_inspect(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let format = take_format(&mut args)?;
+    let filter = take_filter(&mut args)?;
+
+    if args.is_empty() {
+        eprintln!("Usage: bsa-ba2-tool inspect <archive|folder>... [filters] [--format <fmt>]");
+        std::process::exit(1);
+    }
+
+    let vfs = open_vfs(&args)?;
+    let reports = inspect_meshes_with_progress(&vfs, &filter, |current, total, _| {
+        if current % 100 == 0 || current == total {
+            eprint!("\r  Inspecting: {}/{}", current, total);
+        }
+    })?;
+    eprintln!();
+
+    let mut stdout = std::io::stdout().lock();
+    ignore_broken_pipe(
+        write_nif_reports(&mut stdout, &reports, format).and_then(|_| Ok(stdout.flush()?)),
+    )?;
+    if format == ListFormat::Text {
+        let textures: usize = reports.iter().map(|r| r.textures.len()).sum();
+        let missing: usize = reports.iter().map(|r| r.missing_count()).sum();
+        let errors = reports.iter().filter(|r| r.error.is_some()).count();
+        eprintln!(
+            "{} meshes, {} texture references, {} missing, {} with errors",
+            reports.len(),
+            textures,
+            missing,
+            errors
+        );
+    }
+    Ok(())
+}
+
+fn cli_check_refs(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let format = take_format(&mut args)?;
+    let (plugins, sources): (Vec<String>, Vec<String>) =
+        args.into_iter().partition(|a| is_plugin_path(a));
+
+    if plugins.is_empty() || sources.is_empty() {
+        eprintln!(
+            "Usage: bsa-ba2-tool check-refs <plugin>... <archive|folder>... [--format <fmt>]"
+        );
+        std::process::exit(1);
+    }
 
-            if (idx + 1) % 100 == 0 || idx + 1 == total {
-                eprint!("\r  Reading: {}/{}", idx + 1, total);
+    let mut references = Vec::new();
+    for plugin in &plugins {
+        references.extend(read_plugin_references(Path::new(plugin))?);
+    }
+    let vfs = open_vfs(&sources)?;
+    let report = check_references_with_progress(&vfs, references, |current, total, _| {
+        if current % 100 == 0 || current == total {
+            eprint!("\r  Reading meshes: {}/{}", current, total);
+        }
+    })?;
+    eprintln!();
+
+    let mut stdout = std::io::stdout().lock();
+    ignore_broken_pipe(
+        write_ref_report(&mut stdout, &report, format).and_then(|_| Ok(stdout.flush()?)),
+    )?;
+    if format == ListFormat::Text {
+        eprintln!(
+            "{} assets referenced, {} missing, {} unreferenced archived files",
+            report.references,
+            report.missing.len(),
+            report.unreferenced.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+fn cli_mount(args: &[String]) -> anyhow::Result<()> {
+    if args.len() < 2 {
+        eprintln!("Usage: bsa-ba2-tool mount <archive|folder>... <mountpoint>");
+        std::process::exit(1);
+    }
+
+    let (mountpoint, sources) = args.split_last().unwrap();
+    let vfs = open_vfs(sources)?;
+    eprintln!(
+        "Mounting {} files read-only at {} (unmount with: fusermount -u {})",
+        vfs.len(),
+        mountpoint,
+        mountpoint
+    );
+    mount_vfs(vfs, Path::new(mountpoint))
+}
+
+#[cfg(not(all(feature = "fuse", target_os = "linux")))]
+fn cli_mount(_args: &[String]) -> anyhow::Result<()> {
+    anyhow::bail!("This build has no FUSE support (Linux only, `fuse` feature)");
+}
+
+/// Parse a container format, or list the valid ones and exit
+fn container_or_exit(name: Option<String>, path: &Path) -> ContainerFormat {
+    let format = match &name {
+        Some(name) => ContainerFormat::from_cli_name(name),
+        None => ContainerFormat::from_path(path),
+    };
+    match format {
+        Some(f) => f,
+        None => {
+            match name {
+                Some(name) => eprintln!("Unknown container format: {}", name),
+                None => eprintln!(
+                    "Can't tell the container format of {}, use --container",
+                    path.display()
+                ),
             }
+            eprintln!("Valid options:");
+            for f in ContainerFormat::all() {
+                eprintln!("  {:<14} {}", f.cli_name(), f.display_name());
+            }
+            std::process::exit(1);
         }
-        eprintln!();
+    }
+}
 
-        eprintln!("  Building archive...");
-        builder.build_with_progress(&output_path, |current, btotal, _| {
-            if current % 100 == 0 || current == btotal {
-                eprint!("\r  Compressing: {}/{}", current, btotal);
+fn cli_export(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let container = take_option(&mut args, "--container")?;
+    let filter = take_filter(&mut args)?;
+
+    if args.len() != 2 {
+        eprintln!(
+            "Usage: bsa-ba2-tool export <archive> <out.zip|out.tar|out.tar.zst> [--container <fmt>] [filters]"
+        );
+        std::process::exit(1);
+    }
+
+    let archive_path = PathBuf::from(&args[0]);
+    let output_path = PathBuf::from(&args[1]);
+    let format = container_or_exit(container, &output_path);
+
+    let archive = ArchiveHandle::open(&archive_path)?;
+    eprintln!(
+        "Exporting {} -> {} ({})",
+        archive_path.display(),
+        output_path.display(),
+        format.display_name()
+    );
+    let count = export_archive(
+        &archive,
+        &output_path,
+        format,
+        &filter,
+        |current, total, _| {
+            if current % 100 == 0 || current == total {
+                eprint!("\r  Exporting: {}/{}", current, total);
             }
-        })?;
-        eprintln!();
+        },
+    )?;
+    eprintln!();
+
+    eprintln!(
+        "Done: {} files exported to {}",
+        count,
+        output_path.display()
+    );
+    Ok(())
+}
+
+fn cli_import(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let container = take_option(&mut args, "--container")?;
+    let filter = take_filter(&mut args)?;
+
+    if args.len() != 3 {
+        eprintln!(
+            "Usage: bsa-ba2-tool import <in.zip|in.tar|in.tar.zst> <output> <game> [--container <fmt>] [filters]"
+        );
+        eprintln!("Run 'bsa-ba2-tool help' for game version list");
+        std::process::exit(1);
     }
 
+    let input_path = PathBuf::from(&args[0]);
+    let output_path = PathBuf::from(&args[1]);
+    let game_version = game_or_exit(&args[2]);
+    let format = container_or_exit(container, &input_path);
+
+    if game_version.is_tes3() {
+        anyhow::bail!("Morrowind TES3 BSA writing is not supported");
+    }
+
+    eprintln!(
+        "Importing {} as {} -> {}",
+        input_path.display(),
+        game_version.display_name(),
+        output_path.display()
+    );
+    let mut builder = PackBuilder::new(game_version, &output_path);
+    let count = import_container(&input_path, format, &filter, |path, data| {
+        builder.add_file(path, data);
+        Ok(())
+    })?;
+    if count == 0 {
+        anyhow::bail!("No files found in {}", input_path.display());
+    }
+    eprintln!("  Read {} files", count);
+    builder.build(&output_path)?;
+
     eprintln!(
         "Done: {} files packed into {}",
-        total,
+        count,
         output_path.display()
     );
     Ok(())
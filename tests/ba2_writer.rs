@@ -0,0 +1,277 @@
+//! BA2 archives written by the builder must read back with the `ba2` crate
+//! and with our own readers, for every version, entry order and format.
+
+mod common;
+
+use ba2::fo4::{Archive, FileWriteOptions};
+use ba2::prelude::*;
+use ba2::ByteSlice;
+use bsa_ba2_tool::archive::{
+    open_archive, ArchiveOrder, Ba2Builder, Ba2Format, GameVersion, MipChunking,
+};
+use common::{hash_file, output_dir, sample_files, sample_textures};
+use directxtex::{ScratchImage, CP_FLAGS, DDS_FLAGS, DXGI_FORMAT};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Archive paths are compared case-insensitively with backslashes
+fn key(path: &str) -> String {
+    path.replace('/', "\\").to_lowercase()
+}
+
+/// Texture metadata and block data, which survive the DDS header being
+/// rebuilt on extraction
+fn texture_contents(dds: &[u8]) -> (usize, usize, usize, Vec<u8>) {
+    let image = ScratchImage::load_dds(dds, DDS_FLAGS::DDS_FLAGS_NONE, None, None).unwrap();
+    let meta = image.metadata();
+    (
+        meta.width,
+        meta.height,
+        meta.mip_levels,
+        image.pixels().to_vec(),
+    )
+}
+
+fn assert_same_contents(label: &str, format: Ba2Format, expected: &[u8], actual: &[u8]) {
+    match format {
+        Ba2Format::General => assert!(expected == actual, "{}: contents differ", label),
+        Ba2Format::DX10 => assert!(
+            texture_contents(expected) == texture_contents(actual),
+            "{}: texture differs",
+            label
+        ),
+    }
+}
+
+/// Build an archive, then read every file back with the `ba2` crate and
+/// through `open_archive`
+fn assert_round_trip(game: GameVersion, order: ArchiveOrder, format: Ba2Format) {
+    let files = match format {
+        Ba2Format::General => sample_files(),
+        Ba2Format::DX10 => sample_textures(),
+    };
+    let expected: BTreeMap<String, Vec<u8>> = files
+        .iter()
+        .map(|(path, data)| (key(path), data.clone()))
+        .collect();
+
+    let label = format!("{} {} {:?}", game.cli_name(), order.cli_name(), format);
+    let dir = output_dir(&format!(
+        "round-trip-{}-{}-{:?}",
+        game.cli_name(),
+        order.cli_name(),
+        format
+    ));
+    let output = dir.join("out.ba2");
+
+    let mut builder = Ba2Builder::new()
+        .with_version(game.ba2_version().unwrap())
+        .with_compression(game.ba2_compression())
+        .with_format(format)
+        .with_order(order);
+    for (path, data) in &files {
+        builder.add_file(path, data.clone());
+    }
+    builder.build_with_progress(&output, |_, _, _| {}).unwrap();
+
+    // The ba2 crate
+    let (archive, options): (Archive, _) = Archive::read(output.as_path()).unwrap();
+    assert_eq!(
+        options.version(),
+        game.ba2_version().unwrap().to_crate_version(),
+        "{}: version",
+        label
+    );
+    assert_eq!(archive.len(), expected.len(), "{}: file count", label);
+    let write_options: FileWriteOptions = options.into();
+    for (archive_key, file) in archive.iter() {
+        let path = key(&String::from_utf8_lossy(archive_key.name().as_bytes()));
+        let data = expected
+            .get(&path)
+            .unwrap_or_else(|| panic!("{}: unexpected file {}", label, path));
+        let mut extracted = Vec::new();
+        file.write(&mut extracted, &write_options).unwrap();
+        assert_same_contents(&format!("{} {}", label, path), format, data, &extracted);
+    }
+
+    // Our readers
+    let reopened = open_archive(&output).unwrap();
+    let entries = reopened.entries().unwrap();
+    assert_eq!(entries.len(), expected.len(), "{}: entry count", label);
+    for entry in entries {
+        let data = expected
+            .get(&key(&entry.path))
+            .unwrap_or_else(|| panic!("{}: unexpected entry {}", label, entry.path));
+        let extracted = reopened.read(&entry.path).unwrap();
+        assert_same_contents(
+            &format!("{} {}", label, entry.path),
+            format,
+            data,
+            &extracted,
+        );
+    }
+
+    drop(reopened);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ba2_round_trips_every_version_order_and_format() {
+    for game in GameVersion::all().iter().filter(|g| g.is_ba2()) {
+        for order in ArchiveOrder::all() {
+            for format in [Ba2Format::General, Ba2Format::DX10] {
+                assert_round_trip(*game, *order, format);
+            }
+        }
+    }
+}
+
+/// Paths in the order their records were written. Data follows the records
+/// in the same order, so sorting by data offset recovers it.
+fn record_order(archive: &Path) -> Vec<String> {
+    let archive = open_archive(archive).unwrap();
+    let mut entries: Vec<(u64, String)> = archive
+        .entries()
+        .unwrap()
+        .iter()
+        .map(|entry| (entry.offset, key(&entry.path)))
+        .collect();
+    entries.sort();
+    entries.into_iter().map(|(_, path)| path).collect()
+}
+
+#[test]
+fn ba2_vanilla_order_groups_by_directory_then_extension() {
+    let dir = output_dir("vanilla-order");
+    let output = dir.join("out.ba2");
+    // Neither sorted by path nor by extension
+    let files = [
+        "Scripts/zeta.pex",
+        "meshes/armor/b.nif",
+        "meshes/a.nif",
+        "meshes/b.bgsm",
+        "meshes/armor/a.tri",
+        "interface/x.swf",
+    ];
+    let mut builder = Ba2Builder::new().with_order(ArchiveOrder::Vanilla);
+    for (i, path) in files.iter().enumerate() {
+        builder.add_file(path, vec![i as u8 + 1; 100 * (i + 1)]);
+    }
+    builder.build_with_progress(&output, |_, _, _| {}).unwrap();
+
+    let order = record_order(&output);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        order,
+        [
+            "interface\\x.swf",
+            "meshes\\b.bgsm",
+            "meshes\\a.nif",
+            "meshes\\armor\\b.nif",
+            "meshes\\armor\\a.tri",
+            "scripts\\zeta.pex",
+        ]
+    );
+}
+
+#[test]
+fn ba2_vanilla_order_is_reproducible() {
+    let dir = output_dir("vanilla");
+    let files = sample_files();
+    let pack = |files: &[(String, Vec<u8>)], output: &Path| {
+        let mut builder = Ba2Builder::new().with_order(ArchiveOrder::Vanilla);
+        for (path, data) in files {
+            builder.add_file(path, data.clone());
+        }
+        builder.build_with_progress(output, |_, _, _| {}).unwrap();
+        hash_file(output)
+    };
+
+    let first = pack(&files, &dir.join("first"));
+    let reversed: Vec<_> = files.iter().rev().cloned().collect();
+    let second = pack(&reversed, &dir.join("second"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(first, second, "vanilla archives differ between builds");
+}
+
+/// A square BC1 texture with a full mip chain
+fn bc1_texture(size: usize) -> Vec<u8> {
+    let mut image = ScratchImage::default();
+    image
+        .initialize_2d(
+            DXGI_FORMAT::DXGI_FORMAT_BC1_UNORM,
+            size,
+            size,
+            1,
+            0,
+            CP_FLAGS::CP_FLAGS_NONE,
+        )
+        .unwrap();
+    for (k, byte) in image.pixels_mut().iter_mut().enumerate() {
+        *byte = (k % 251) as u8;
+    }
+    image
+        .save_dds(DDS_FLAGS::DDS_FLAGS_NONE)
+        .unwrap()
+        .buffer()
+        .to_vec()
+}
+
+/// Pack one texture with `chunking` and return the mip range of each chunk
+fn chunk_mips(name: &str, texture: &[u8], chunking: MipChunking) -> Vec<RangeInclusive<u16>> {
+    let dir = output_dir(&format!("chunks-{}", name));
+    let output = dir.join("out.ba2");
+    let mut builder = Ba2Builder::new()
+        .with_format(Ba2Format::DX10)
+        .with_mip_chunking(chunking);
+    builder.add_file("textures/test_d.dds", texture.to_vec());
+    builder.build_with_progress(&output, |_, _, _| {}).unwrap();
+
+    let (archive, options): (Archive, _) = Archive::read(output.as_path()).unwrap();
+    let (_, file) = archive.iter().next().unwrap();
+    let mips = file
+        .iter()
+        .map(|chunk| chunk.mips.clone().unwrap())
+        .collect();
+
+    // The chunks still make up the whole texture
+    let mut extracted = Vec::new();
+    file.write(&mut extracted, &options.into()).unwrap();
+    assert_same_contents(name, Ba2Format::DX10, texture, &extracted);
+    drop(archive);
+    std::fs::remove_dir_all(&dir).unwrap();
+    mips
+}
+
+#[test]
+fn dx10_textures_are_chunked_by_mip() {
+    // 1024x1024 BC1: 11 mips, the first two 512 KiB and 128 KiB
+    let texture = bc1_texture(1024);
+
+    // Archive2: chunks stop short of a 512x512 mip (128 KiB)
+    assert_eq!(
+        chunk_mips("default", &texture, MipChunking::default()),
+        [0..=0, 1..=1, 2..=10]
+    );
+    assert_eq!(
+        chunk_mips("single", &texture, MipChunking::single()),
+        [0..=10]
+    );
+
+    // Once all but one chunk are full, the rest go in the last
+    let small = |max_chunks| MipChunking {
+        max_chunk_size: Some(2048),
+        max_chunks,
+    };
+    assert_eq!(
+        chunk_mips("small-4", &texture, small(4)),
+        [0..=0, 1..=1, 2..=2, 3..=10]
+    );
+    assert_eq!(chunk_mips("small-2", &texture, small(2)), [0..=0, 1..=10]);
+    // More than a file record holds is clamped to 4
+    assert_eq!(
+        chunk_mips("small-9", &texture, small(9)),
+        [0..=0, 1..=1, 2..=2, 3..=10]
+    );
+}
@@ -0,0 +1,72 @@
+//! Fixtures shared by the integration tests
+// Each test crate uses only some of these
+#![allow(dead_code)]
+
+use directxtex::{ScratchImage, CP_FLAGS, DDS_FLAGS, DXGI_FORMAT};
+use std::path::{Path, PathBuf};
+use twox_hash::XxHash3_128;
+
+/// Loose files with a mix of directories, extensions and sizes
+pub fn sample_files() -> Vec<(String, Vec<u8>)> {
+    let mut files = Vec::new();
+    for (i, dir) in [
+        "meshes/armor",
+        "meshes",
+        "scripts",
+        "sound/fx/npc",
+        "interface",
+    ]
+    .iter()
+    .enumerate()
+    {
+        for j in 0..12 {
+            let ext = ["nif", "pex", "wav", "swf"][(i + j) % 4];
+            // Compressible but distinct contents
+            let data: Vec<u8> = (0..(j + 1) * 700)
+                .map(|k| ((k * (i + 3) + j) % 251) as u8)
+                .collect();
+            files.push((format!("{}/file{:02}.{}", dir, j, ext), data));
+        }
+    }
+    files
+}
+
+/// BC1 textures of a few sizes with full mip chains, so some are chunked
+pub fn sample_textures() -> Vec<(String, Vec<u8>)> {
+    (0..6)
+        .map(|i| {
+            let size = 16 << (i * 2 % 7);
+            let mut image = ScratchImage::default();
+            image
+                .initialize_2d(
+                    DXGI_FORMAT::DXGI_FORMAT_BC1_UNORM,
+                    size,
+                    size,
+                    1,
+                    0,
+                    CP_FLAGS::CP_FLAGS_NONE,
+                )
+                .unwrap();
+            // Distinct block data per texture
+            for (k, byte) in image.pixels_mut().iter_mut().enumerate() {
+                *byte = ((k * (i + 3)) % 253) as u8;
+            }
+            let dds = image.save_dds(DDS_FLAGS::DDS_FLAGS_NONE).unwrap();
+            (
+                format!("textures/set{}/tex{:02}_d.dds", i % 2, i),
+                dds.buffer().to_vec(),
+            )
+        })
+        .collect()
+}
+
+/// A fresh folder under the system temp folder, unique to this test run
+pub fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bsa-ba2-tool-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn hash_file(path: &Path) -> u128 {
+    XxHash3_128::oneshot(&std::fs::read(path).unwrap())
+}
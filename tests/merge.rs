@@ -0,0 +1,94 @@
+//! Merged archives must hold every winning file, including root-level ones,
+//! even when the output replaces one of the inputs.
+
+mod common;
+
+use bsa_ba2_tool::archive::{open_archive, ArchiveMerger, BsaBuilder, GameVersion};
+use common::output_dir;
+use std::path::Path;
+
+fn pack_bsa(output: &Path, files: &[(&str, &[u8])]) {
+    let game = GameVersion::SkyrimSE;
+    let mut builder = BsaBuilder::new()
+        .with_version(game.bsa_version().unwrap())
+        .with_compression(game.supports_compression());
+    for (path, data) in files {
+        builder.add_file(path, data.to_vec());
+    }
+    builder.build_with_progress(output, |_, _, _| {}).unwrap();
+}
+
+fn merge(output: &Path, inputs: &[&Path]) -> usize {
+    let mut merger = ArchiveMerger::new(GameVersion::SkyrimSE);
+    for input in inputs {
+        merger.add_input(input);
+    }
+    merger
+        .build_with_progress(output, |_, _, _| {})
+        .unwrap()
+        .files
+}
+
+/// Every path and its contents, sorted by path
+fn contents(archive: &Path) -> Vec<(String, Vec<u8>)> {
+    let archive = open_archive(archive).unwrap();
+    let mut files: Vec<_> = archive
+        .entries()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            let data = archive.read(&entry.path).unwrap();
+            (entry.path.clone(), data)
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn expected() -> Vec<(String, Vec<u8>)> {
+    vec![
+        ("meshes\\x.nif".to_string(), b"mesh a".to_vec()),
+        ("meshes\\y.nif".to_string(), b"mesh b".to_vec()),
+        ("readme.txt".to_string(), b"root file".to_vec()),
+    ]
+}
+
+#[test]
+fn merge_keeps_root_files() {
+    let dir = output_dir("merge-root");
+    let a = dir.join("A.bsa");
+    let b = dir.join("B.bsa");
+    let merged = dir.join("M.bsa");
+    pack_bsa(
+        &a,
+        &[("readme.txt", b"root file"), ("meshes/x.nif", b"mesh a")],
+    );
+    pack_bsa(&b, &[("meshes/y.nif", b"mesh b")]);
+
+    let files = merge(&merged, &[&a, &b]);
+    let merged_contents = contents(&merged);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(files, 3);
+    assert_eq!(merged_contents, expected());
+}
+
+#[test]
+fn merge_can_replace_an_input() {
+    let dir = output_dir("merge-in-place");
+    let a = dir.join("A.bsa");
+    let b = dir.join("B.bsa");
+    pack_bsa(
+        &a,
+        &[("readme.txt", b"root file"), ("meshes/x.nif", b"mesh a")],
+    );
+    pack_bsa(&b, &[("meshes/y.nif", b"mesh b")]);
+
+    merge(&a, &[&a, &b]);
+    let merged_contents = contents(&a);
+    let leftovers = std::fs::read_dir(&dir).unwrap().count();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(merged_contents, expected());
+    assert_eq!(leftovers, 2, "temporary output left behind");
+}
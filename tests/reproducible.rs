@@ -0,0 +1,108 @@
+//! Packing the same files twice must give byte-identical archives, whatever
+//! order the files are added in.
+
+mod common;
+
+use bsa_ba2_tool::archive::{Ba2Builder, Ba2Format, BsaBuilder, GameVersion};
+use common::{hash_file, output_dir, sample_files, sample_textures};
+use std::path::Path;
+
+/// Pack `files` in the given order and in reverse, and compare the hashes
+fn assert_reproducible(
+    name: &str,
+    files: &[(String, Vec<u8>)],
+    pack: impl Fn(&[(String, Vec<u8>)], &Path),
+) {
+    let dir = output_dir(name);
+    let first = dir.join("first");
+    let second = dir.join("second");
+
+    pack(files, &first);
+    let reversed: Vec<_> = files.iter().rev().cloned().collect();
+    pack(&reversed, &second);
+
+    let (a, b) = (hash_file(&first), hash_file(&second));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(a, b, "{} archives differ between builds", name);
+}
+
+#[test]
+fn bsa_output_is_reproducible() {
+    for game in [
+        GameVersion::Oblivion,
+        GameVersion::FalloutNewVegas,
+        GameVersion::SkyrimSE,
+    ] {
+        assert_reproducible(game.cli_name(), &sample_files(), |files, output| {
+            let mut builder = BsaBuilder::new()
+                .with_version(game.bsa_version().unwrap())
+                .with_compression(game.supports_compression());
+            for (path, data) in files {
+                builder.add_file(path, data.clone());
+            }
+            builder.build_with_progress(output, |_, _, _| {}).unwrap();
+        });
+    }
+}
+
+#[test]
+fn ba2_general_output_is_reproducible() {
+    for game in [GameVersion::Fallout4Fo76, GameVersion::StarfieldV2] {
+        assert_reproducible(game.cli_name(), &sample_files(), |files, output| {
+            let mut builder = Ba2Builder::new()
+                .with_version(game.ba2_version().unwrap())
+                .with_compression(game.ba2_compression());
+            for (path, data) in files {
+                builder.add_file(path, data.clone());
+            }
+            builder.build_with_progress(output, |_, _, _| {}).unwrap();
+        });
+    }
+}
+
+#[test]
+fn ba2_dx10_output_is_reproducible() {
+    assert_reproducible("dx10", &sample_textures(), |files, output| {
+        let mut builder = Ba2Builder::new().with_format(Ba2Format::DX10);
+        for (path, data) in files {
+            builder.add_file(path, data.clone());
+        }
+        builder.build_with_progress(output, |_, _, _| {}).unwrap();
+    });
+}
+
+#[test]
+fn case_duplicates_resolve_the_same_way_every_time() {
+    let mut files = sample_files();
+    files.push(("Meshes/Armor/File00.nif".to_string(), b"upper".to_vec()));
+    files.push(("meshes/ARMOR/file01.pex".to_string(), b"mixed".to_vec()));
+
+    let dir = output_dir("case-duplicates");
+    for game in [GameVersion::SkyrimSE, GameVersion::Fallout4Fo76] {
+        let hashes: Vec<u128> = (0..4)
+            .map(|run| {
+                let output = dir.join(format!("{}-{}", game.cli_name(), run));
+                if game.is_ba2() {
+                    let mut builder = Ba2Builder::new();
+                    for (path, data) in &files {
+                        builder.add_file(path, data.clone());
+                    }
+                    builder.build_with_progress(&output, |_, _, _| {}).unwrap();
+                } else {
+                    let mut builder = BsaBuilder::new().with_version(game.bsa_version().unwrap());
+                    for (path, data) in &files {
+                        builder.add_file(path, data.clone());
+                    }
+                    builder.build_with_progress(&output, |_, _, _| {}).unwrap();
+                }
+                hash_file(&output)
+            })
+            .collect();
+        assert!(
+            hashes.iter().all(|h| *h == hashes[0]),
+            "{} archives differ between builds",
+            game.cli_name()
+        );
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}